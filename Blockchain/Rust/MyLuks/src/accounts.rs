@@ -6,42 +6,242 @@ pub enum AccountError {
     UnauthorizedKeyChange,
     AccountLocked,
     AccountNotLocked,
+    UnknownHeight,
+    InvalidMultiSigPolicy,
+    KeyRevoked,
+    InvalidAddress,
+    AddressMismatch,
+    BalanceOverflow,
+    Unauthorized, // Caller lacks the role required by an administrative operation
+    AccountClosed, // The id belonged to a closed account and cannot be used again
+    NonZeroBalance,
+    UnvestedBalance,
+    InvalidSweepTarget,
 }
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use crate::address::{self, LuksAddress};
+use crate::amount::Amount;
+use crate::assets::{self, Asset, AssetId, NATIVE_ASSET};
+use crate::encoding::{Action, AuthorizationPayload};
+use crate::merkle::{self, Hash, MerkleProof, SparseMerkleTree};
+use crate::nft::NftItem;
+use crate::roles::{Permission, Roles};
+use crate::security::Security;
+use crate::signature_scheme::SchemeId;
+use crate::storage::{StateStore, StorageError};
+use crate::vesting::{self, VestingSchedule};
 
-#[derive(Debug, PartialEq, Clone)]
+// Prefijo de las claves de cuentas en el almacenamiento
+const ACCOUNT_KEY_PREFIX: &str = "account/";
+// Prefijo de las cuentas cerradas
+const CLOSED_ACCOUNT_KEY_PREFIX: &str = "closed/";
+// Prefijo de las claves revocadas, guardadas aparte porque sobreviven a su cuenta
+const REVOKED_KEY_PREFIX: &str = "revoked/";
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct PublicKey {
     pub value: Vec<u8>, // Represents the public key.
+    #[serde(default)]
+    pub scheme: SchemeId, // Keys stored before schemes existed are secp256k1.
 }
 
 impl PublicKey {
+    pub fn secp256k1(value: Vec<u8>) -> Self {
+        PublicKey { value, scheme: SchemeId::Secp256k1 }
+    }
+
+    pub fn ed25519(value: Vec<u8>) -> Self {
+        PublicKey { value, scheme: SchemeId::Ed25519 }
+    }
+
+    /// Parses the textual form of a key, `<scheme>:<hex>`. Bare hex is a secp256k1 key.
+    pub fn parse_tagged(text: &str) -> Option<Self> {
+        let (scheme, value) = match text.split_once(':') {
+            Some((name, value)) => (SchemeId::from_name(name)?, value),
+            None => (SchemeId::Secp256k1, text),
+        };
+        Some(PublicKey { value: hex::decode(value).ok()?, scheme })
+    }
+
     // New function to get hex representation
     pub fn to_hex(&self) -> String {
         hex::encode(&self.value)
     }
+
+    // Inverse of `parse_tagged`. secp256k1 keys stay bare hex, so messages signed
+    // before schemes were tagged are unchanged.
+    pub fn to_tagged_string(&self) -> String {
+        match self.scheme {
+            SchemeId::Secp256k1 => self.to_hex(),
+            scheme => format!("{}:{}", scheme.name(), self.to_hex()),
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
+// A signature attached to a transaction, tagged with the key that produced it and its scheme.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AccountSignature {
+    pub public_key: PublicKey,
+    pub signature: String,
+    #[serde(default)]
+    pub scheme: SchemeId, // Must match the scheme of `public_key`.
+}
+
+// Key set controlling an account: any `threshold` distinct keys may authorise a transaction.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct MultiSigPolicy {
+    pub keys: Vec<PublicKey>,
+    pub threshold: usize,
+}
+
+impl MultiSigPolicy {
+    pub fn new(keys: Vec<PublicKey>, threshold: usize) -> Result<Self, AccountError> {
+        let mut distinct: Vec<&PublicKey> = Vec::new();
+        for key in &keys {
+            if key.value.is_empty() || distinct.contains(&key) {
+                return Err(AccountError::InvalidMultiSigPolicy);
+            }
+            distinct.push(key);
+        }
+        if threshold == 0 || threshold > keys.len() {
+            return Err(AccountError::InvalidMultiSigPolicy);
+        }
+        Ok(MultiSigPolicy { keys, threshold })
+    }
+
+    // Policy of a plain account: its single key alone.
+    pub fn single(key: PublicKey) -> Self {
+        MultiSigPolicy { keys: vec![key], threshold: 1 }
+    }
+
+    pub fn is_single_key(&self) -> bool {
+        self.threshold < 2
+    }
+
+    // Counts distinct member keys with a valid signature over `data`.
+    pub fn count_valid_signatures(&self, data: &String, signatures: &[AccountSignature]) -> usize {
+        let mut signers: Vec<&PublicKey> = Vec::new();
+        for signature in signatures {
+            if signers.contains(&&signature.public_key) || !self.keys.contains(&signature.public_key) {
+                continue;
+            }
+            if Security::verify_account_signature(signature, data) {
+                signers.push(&signature.public_key);
+            }
+        }
+        signers.len()
+    }
+
+    pub fn is_satisfied(&self, data: &String, signatures: &[AccountSignature]) -> bool {
+        self.count_valid_signatures(data, signatures) >= self.threshold
+    }
+}
+
+// Key that authorised a key rotation.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum KeyRotationAuthority {
+    CurrentKey,
+    RecoveryKey,
+    Guardians,
+}
+
+// Record of a key rotation; the old key is revoked from `height` on.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct KeyRotation {
+    pub old_key: PublicKey,
+    pub new_key: PublicKey,
+    pub authorised_by: KeyRotationAuthority,
+    pub timestamp: u64,
+    pub height: u64,
+}
+
+// Entry of the revocation list.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RevokedKey {
+    pub account_id: String,
+    pub height: u64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct TransactionHistory {
     pub from: String,
     pub to: String,
-    pub amount: u64,
+    pub amount: Amount,
     pub timestamp: u64, // Represents the time the transaction was made.
+    #[serde(default = "assets::native_asset_id")]
+    pub asset: AssetId,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct AccountDetails {
-    pub balance: u64,
+    pub balance: Amount,
     pub public_key: PublicKey,
     pub transactions: VecDeque<TransactionHistory>, // Will store the last N transactions.
     pub locked: bool,
+    #[serde(default)]
+    pub recovering: bool, // Frozen while guardians replace its key: it can receive but not send.
+    #[serde(default)]
+    pub nonce: u64, // Next nonce the account must use; incremented by every applied transaction.
+    #[serde(default)]
+    pub multisig: Option<MultiSigPolicy>, // When set, replaces `public_key` as the account's authority.
+    #[serde(default)]
+    pub recovery_key: Option<PublicKey>, // Offline key allowed to authorise a rotation.
+    #[serde(default)]
+    pub key_rotations: Vec<KeyRotation>,
+    #[serde(default)]
+    pub vesting: Vec<VestingSchedule>, // Locked tranches; their unvested part is included in `balance` but not spendable.
+    #[serde(default)]
+    pub asset_balances: BTreeMap<AssetId, Amount>, // Balances of registered assets; the Luks coin stays in `balance`.
+}
+
+impl AccountDetails {
+    // Part of the balance not held back by vesting schedules at time `now`.
+    pub fn spendable_balance(&self, now: u64) -> Amount {
+        self.balance.saturating_sub(vesting::locked_total(&self.vesting, now))
+    }
+    // Nothing may leave a locked account, nor one being recovered.
+    pub fn is_frozen(&self) -> bool {
+        self.locked || self.recovering
+    }
+
+    // Keys and threshold that authorise transactions from this account.
+    pub fn signing_policy(&self) -> MultiSigPolicy {
+        match &self.multisig {
+            Some(policy) => policy.clone(),
+            None => MultiSigPolicy::single(self.public_key.clone()),
+        }
+    }
+}
+
+/// Tombstone left by a closed account. Its id can never be used again, and the
+/// transactions it kept remain readable.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ClosedAccount {
+    pub closed_at: u64,
+    pub swept_to: Option<String>, // Account that received the remaining balances, if any.
+    pub transactions: VecDeque<TransactionHistory>,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Accounts {
     pub accounts_map: HashMap<String, AccountDetails>,
     pub max_transaction_history: usize,
+    state_tree: SparseMerkleTree, // Commitment to every account, updated on each change.
+    revoked_keys: HashMap<PublicKey, RevokedKey>, // Retired keys, by scheme and bytes; never valid again.
+    closed_accounts: HashMap<String, ClosedAccount>, // Tombstones, never reused.
+}
+
+// Proof that an account held a given state (or did not exist) at a block height.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AccountProof {
+    pub account_id: String,
+    pub height: u64,
+    pub state_root: Hash,
+    pub proof: MerkleProof,
 }
 
 // Nueva estructura para almacenar hash de las cuentas
@@ -54,9 +254,53 @@ pub struct AccountHash {
 impl AccountHash {
     // Función para calcular el hash de una cuenta
     pub fn compute_hash(account: &AccountDetails) -> Vec<u8> {
-        let data = serde_json::to_string(&account).unwrap();
-        let digest = sha2::Sha256::digest(data.as_bytes());
-        digest.to_vec()
+        Sha256::digest(Self::canonical_encoding(account)).to_vec()
+    }
+
+    // Fixed-order encoding of the committed fields: balance, public key and lock flags
+    // (bit 0 locked, bit 1 recovering).
+    // Every key is preceded by its scheme tag.
+    // Transaction history is not part of the state commitment.
+    pub fn canonical_encoding(account: &AccountDetails) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&account.balance.units().to_be_bytes());
+        data.push(account.public_key.scheme.tag());
+        data.extend_from_slice(&(account.public_key.value.len() as u32).to_be_bytes());
+        data.extend_from_slice(&account.public_key.value);
+        data.push(account.locked as u8 | (account.recovering as u8) << 1);
+        data.extend_from_slice(&account.nonce.to_be_bytes());
+        match &account.recovery_key {
+            Some(key) => {
+                data.push(1);
+                data.push(key.scheme.tag());
+                data.extend_from_slice(&(key.value.len() as u32).to_be_bytes());
+                data.extend_from_slice(&key.value);
+            }
+            None => data.push(0),
+        }
+        data.extend_from_slice(&(account.vesting.len() as u32).to_be_bytes());
+        for schedule in &account.vesting {
+            data.extend_from_slice(&schedule.total.units().to_be_bytes());
+            data.extend_from_slice(&schedule.start.to_be_bytes());
+            data.extend_from_slice(&schedule.cliff.to_be_bytes());
+            data.extend_from_slice(&schedule.duration.to_be_bytes());
+        }
+        data.extend_from_slice(&(account.asset_balances.len() as u32).to_be_bytes());
+        for (asset_id, balance) in &account.asset_balances {
+            data.extend_from_slice(&(asset_id.len() as u32).to_be_bytes());
+            data.extend_from_slice(asset_id.as_bytes());
+            data.extend_from_slice(&balance.units().to_be_bytes());
+        }
+        if let Some(policy) = &account.multisig {
+            data.extend_from_slice(&(policy.threshold as u32).to_be_bytes());
+            data.extend_from_slice(&(policy.keys.len() as u32).to_be_bytes());
+            for key in &policy.keys {
+                data.push(key.scheme.tag());
+                data.extend_from_slice(&(key.value.len() as u32).to_be_bytes());
+                data.extend_from_slice(&key.value);
+            }
+        }
+        data
     }
 }
 
@@ -65,48 +309,373 @@ impl Accounts {
         Accounts {
             accounts_map: HashMap::new(),
             max_transaction_history,
+            state_tree: SparseMerkleTree::new(),
+            revoked_keys: HashMap::new(),
+            closed_accounts: HashMap::new(),
         }
     }
 
-    pub fn create_account(&mut self, account_id: String, initial_balance: u64, public_key: PublicKey) -> Result<(), AccountError> {
+    // Root of the sparse Merkle tree over all accounts.
+    pub fn state_root(&self) -> Hash {
+        self.state_tree.root()
+    }
+
+    // Root of the state tree as of a committed block height.
+    pub fn state_root_at(&self, height: u64) -> Result<Hash, AccountError> {
+        self.state_tree.root_at(height).ok_or(AccountError::UnknownHeight)
+    }
+
+    // Seals the current state as the state of block `height`.
+    pub fn checkpoint(&mut self, height: u64) {
+        self.state_tree.seal(height);
+    }
+
+    // Drops the state history before `height`; older roots and proofs are no longer served.
+    pub fn prune_state_history(&mut self, height: u64) {
+        self.state_tree.prune(height);
+    }
+
+    // Builds a proof of the account's state at `height`. If the account did not exist
+    // at that height the proof shows its absence.
+    pub fn prove_account(&self, account_id: &str, height: u64) -> Result<AccountProof, AccountError> {
+        let key = merkle::account_key(account_id);
+        let proof = self.state_tree.prove(&key, height).ok_or(AccountError::UnknownHeight)?;
+        Ok(AccountProof {
+            account_id: account_id.to_string(),
+            height,
+            state_root: self.state_root_at(height)?,
+            proof,
+        })
+    }
+
+    // Commits the current state of a registered asset to the tree, next to the accounts,
+    // so that the state root also covers the asset registry.
+    pub fn commit_asset(&mut self, asset: &Asset) {
+        self.state_tree.update(&merkle::asset_key(&asset.id), Some(&asset.canonical_encoding()));
+    }
+
+    // Commits the current state of a non-fungible item to the tree.
+    pub fn commit_nft(&mut self, item: &NftItem) {
+        self.state_tree.update(&merkle::nft_key(&item.id), Some(&item.canonical_encoding()));
+    }
+
+    // Recomputes the tree leaf of an account after it changed or was removed.
+    fn refresh_state(&mut self, account_id: &String) {
+        let key = merkle::account_key(account_id);
+        match self.accounts_map.get(account_id) {
+            Some(account) => {
+                let value = AccountHash::canonical_encoding(account);
+                self.state_tree.update(&key, Some(&value));
+            }
+            None => self.state_tree.update(&key, None),
+        }
+    }
+
+    pub fn create_account(&mut self, account_id: String, initial_balance: Amount, public_key: PublicKey) -> Result<(), AccountError> {
         if self.accounts_map.contains_key(&account_id) {
             return Err(AccountError::AccountAlreadyExists);
         }
+        if self.closed_accounts.contains_key(&account_id) {
+            return Err(AccountError::AccountClosed);
+        }
+        // A rotated-out key stays retired, even under a new account.
+        if self.is_key_revoked(&public_key) {
+            return Err(AccountError::KeyRevoked);
+        }
+        Self::check_address(&account_id, LuksAddress::from_public_key(&public_key))?;
         let account_details = AccountDetails {
             balance: initial_balance,
             public_key,
             transactions: VecDeque::new(),
             locked: false, // Initially, the account is not locked
+            recovering: false,
+            nonce: 0,
+            multisig: None,
+            recovery_key: None,
+            key_rotations: Vec::new(),
+            vesting: Vec::new(),
+            asset_balances: BTreeMap::new(),
         };
-        self.accounts_map.insert(account_id, account_details);
+        self.accounts_map.insert(account_id.clone(), account_details);
+        self.refresh_state(&account_id);
         Ok(())
     }
 
-    pub fn get_balance(&self, account_id: &String) -> Result<u64, AccountError> {
+    // Creates an account controlled by an M-of-N key set.
+    pub fn create_multisig_account(&mut self, account_id: String, initial_balance: Amount, policy: MultiSigPolicy) -> Result<(), AccountError> {
+        if self.accounts_map.contains_key(&account_id) {
+            return Err(AccountError::AccountAlreadyExists);
+        }
+        if self.closed_accounts.contains_key(&account_id) {
+            return Err(AccountError::AccountClosed);
+        }
+        let policy = MultiSigPolicy::new(policy.keys, policy.threshold)?;
+        if policy.keys.iter().any(|key| self.is_key_revoked(key)) {
+            return Err(AccountError::KeyRevoked);
+        }
+        Self::check_address(&account_id, LuksAddress::from_multisig(&policy))?;
+        let account_details = AccountDetails {
+            balance: initial_balance,
+            public_key: policy.keys[0].clone(),
+            transactions: VecDeque::new(),
+            locked: false,
+            recovering: false,
+            nonce: 0,
+            multisig: Some(policy),
+            recovery_key: None,
+            key_rotations: Vec::new(),
+            vesting: Vec::new(),
+            asset_balances: BTreeMap::new(),
+        };
+        self.accounts_map.insert(account_id.clone(), account_details);
+        self.refresh_state(&account_id);
+        Ok(())
+    }
+
+    // Account ids are the canonical (lowercase) address derived from the account key(s).
+    fn check_address(account_id: &String, expected: LuksAddress) -> Result<(), AccountError> {
+        let parsed = address::validate_address(account_id).map_err(|_| AccountError::InvalidAddress)?;
+        if parsed != expected {
+            return Err(AccountError::AddressMismatch);
+        }
+        if account_id != &expected.to_string() {
+            return Err(AccountError::InvalidAddress);
+        }
+        Ok(())
+    }
+
+    pub fn get_balance(&self, account_id: &String) -> Result<Amount, AccountError> {
         match self.accounts_map.get(account_id) {
             Some(account) => Ok(account.balance),
             None => Err(AccountError::AccountNotFound),
         }
     }
 
-    pub fn adjust_balance(&mut self, account_id: &String, amount: i64) -> Result<(), AccountError> {
+    pub fn credit_balance(&mut self, account_id: &String, amount: Amount) -> Result<(), AccountError> {
         let account = self.accounts_map.get_mut(account_id).ok_or(AccountError::AccountNotFound)?;
         if account.locked {
             return Err(AccountError::AccountLocked);
         }
-        if amount < 0 && account.balance < (-amount) as u64 {
+        account.balance = account.balance.checked_add(amount).map_err(|_| AccountError::BalanceOverflow)?;
+        self.refresh_state(account_id);
+        Ok(())
+    }
+
+    pub fn debit_balance(&mut self, account_id: &String, amount: Amount) -> Result<(), AccountError> {
+        let account = self.accounts_map.get_mut(account_id).ok_or(AccountError::AccountNotFound)?;
+        if account.is_frozen() {
+            return Err(AccountError::AccountLocked);
+        }
+        // Only the vested part of the balance can be spent.
+        let now = current_time();
+        if account.spendable_balance(now) < amount {
             return Err(AccountError::InsufficientBalance);
         }
-        account.balance = (account.balance as i64 + amount) as u64;
+        account.balance = account.balance.checked_sub(amount).map_err(|_| AccountError::InsufficientBalance)?;
+        self.refresh_state(account_id);
         Ok(())
     }
 
-    // Function to delete an account
-    pub fn delete_account(&mut self, account_id: &String) -> Result<(), AccountError> {
-        self.accounts_map.remove(account_id).ok_or(AccountError::AccountNotFound)?;
+    pub fn get_spendable_balance(&self, account_id: &String) -> Result<Amount, AccountError> {
+        self.accounts_map
+            .get(account_id)
+            .map(|account| account.spendable_balance(current_time()))
+            .ok_or(AccountError::AccountNotFound)
+    }
+
+    pub fn get_asset_balance(&self, account_id: &String, asset_id: &AssetId) -> Result<Amount, AccountError> {
+        let account = self.accounts_map.get(account_id).ok_or(AccountError::AccountNotFound)?;
+        if asset_id == NATIVE_ASSET {
+            return Ok(account.balance);
+        }
+        Ok(account.asset_balances.get(asset_id).copied().unwrap_or(Amount::ZERO))
+    }
+
+    // Spendable balance of any asset; vesting only applies to the Luks coin.
+    pub fn get_spendable_asset_balance(&self, account_id: &String, asset_id: &AssetId) -> Result<Amount, AccountError> {
+        if asset_id == NATIVE_ASSET {
+            return self.get_spendable_balance(account_id);
+        }
+        self.get_asset_balance(account_id, asset_id)
+    }
+
+    pub fn credit_asset_balance(&mut self, account_id: &String, asset_id: &AssetId, amount: Amount) -> Result<(), AccountError> {
+        if asset_id == NATIVE_ASSET {
+            return self.credit_balance(account_id, amount);
+        }
+        let account = self.accounts_map.get_mut(account_id).ok_or(AccountError::AccountNotFound)?;
+        if account.locked {
+            return Err(AccountError::AccountLocked);
+        }
+        let balance = account.asset_balances.get(asset_id).copied().unwrap_or(Amount::ZERO);
+        let new_balance = balance.checked_add(amount).map_err(|_| AccountError::BalanceOverflow)?;
+        Self::set_asset_entry(account, asset_id, new_balance);
+        self.refresh_state(account_id);
         Ok(())
     }
 
+    pub fn debit_asset_balance(&mut self, account_id: &String, asset_id: &AssetId, amount: Amount) -> Result<(), AccountError> {
+        if asset_id == NATIVE_ASSET {
+            return self.debit_balance(account_id, amount);
+        }
+        let account = self.accounts_map.get_mut(account_id).ok_or(AccountError::AccountNotFound)?;
+        if account.is_frozen() {
+            return Err(AccountError::AccountLocked);
+        }
+        let balance = account.asset_balances.get(asset_id).copied().unwrap_or(Amount::ZERO);
+        let new_balance = balance.checked_sub(amount).map_err(|_| AccountError::InsufficientBalance)?;
+        Self::set_asset_entry(account, asset_id, new_balance);
+        self.refresh_state(account_id);
+        Ok(())
+    }
+
+    // Zero balances are dropped so they do not show up in the state commitment.
+    fn set_asset_entry(account: &mut AccountDetails, asset_id: &AssetId, balance: Amount) {
+        if balance.is_zero() {
+            account.asset_balances.remove(asset_id);
+        } else {
+            account.asset_balances.insert(asset_id.clone(), balance);
+        }
+    }
+
+    // Moves `schedule.total` from the grantor's spendable balance into a locked tranche
+    // of the beneficiary, released according to the schedule. `Ledger::grant_vesting`
+    // checks the grantor's signatures first.
+    pub(crate) fn grant_vesting(&mut self, grantor: &String, beneficiary: &String, schedule: VestingSchedule) -> Result<(), AccountError> {
+        match self.accounts_map.get(beneficiary) {
+            Some(account) if account.locked => return Err(AccountError::AccountLocked),
+            Some(_) => {},
+            None if self.is_closed(beneficiary) => return Err(AccountError::AccountClosed),
+            None => return Err(AccountError::AccountNotFound),
+        }
+        // Checked up front so that the grantor is never debited for a grant that cannot land.
+        self.get_balance(beneficiary)?.checked_add(schedule.total).map_err(|_| AccountError::BalanceOverflow)?;
+        self.debit_balance(grantor, schedule.total)?;
+        let account = self.accounts_map.get_mut(beneficiary).ok_or(AccountError::AccountNotFound)?;
+        account.balance = account.balance.checked_add(schedule.total).map_err(|_| AccountError::BalanceOverflow)?;
+        account.vesting.push(schedule);
+        self.refresh_state(beneficiary);
+        Ok(())
+    }
+
+    // Drops the schedules fully vested at `now`, which hold nothing back any more. Done
+    // when a block is sealed; returns what each pruned account had, for `restore_vesting`.
+    pub(crate) fn prune_vested(&mut self, now: u64) -> Vec<(String, Vec<VestingSchedule>)> {
+        let mut pruned = Vec::new();
+        for (account_id, account) in self.accounts_map.iter_mut() {
+            if account.vesting.iter().any(|schedule| schedule.is_fully_vested(now)) {
+                let previous = account.vesting.clone();
+                account.vesting.retain(|schedule| !schedule.is_fully_vested(now));
+                pruned.push((account_id.clone(), previous));
+            }
+        }
+        for (account_id, _) in &pruned {
+            self.refresh_state(account_id);
+        }
+        pruned
+    }
+
+    // Undoes `prune_vested`.
+    pub(crate) fn restore_vesting(&mut self, pruned: Vec<(String, Vec<VestingSchedule>)>) {
+        for (account_id, vesting) in pruned {
+            if let Some(account) = self.accounts_map.get_mut(&account_id) {
+                account.vesting = vesting;
+                self.refresh_state(&account_id);
+            }
+        }
+    }
+
+    pub fn get_nonce(&self, account_id: &String) -> Result<u64, AccountError> {
+        self.accounts_map.get(account_id).map(|account| account.nonce).ok_or(AccountError::AccountNotFound)
+    }
+
+    // Consumes the current nonce once a transaction from the account has been applied.
+    pub fn increment_nonce(&mut self, account_id: &String) -> Result<(), AccountError> {
+        let account = self.accounts_map.get_mut(account_id).ok_or(AccountError::AccountNotFound)?;
+        account.nonce += 1;
+        self.refresh_state(account_id);
+        Ok(())
+    }
+
+    // Administrative closure of an account; see `close_account`.
+    pub(crate) fn delete_account(&mut self, roles: &Roles, caller: &String, account_id: &String, sweep_to: Option<&String>, now: u64) -> Result<Vec<(AssetId, Amount)>, AccountError> {
+        Self::check_permission(roles, caller, Permission::DeleteAccount)?;
+        self.close_account(account_id, sweep_to, now)
+    }
+
+    // Data the account signs to close itself. The nonce makes the signature single-use.
+    pub fn closure_data(account_id: &str, sweep_to: Option<&String>, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::CloseAccount).string(account_id).optional_string(sweep_to).u64(nonce).signing_data()
+    }
+
+    // Data the caller signs to close another account administratively.
+    pub fn deletion_data(caller: &str, account_id: &str, sweep_to: Option<&String>, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::DeleteAccount).string(account_id).string(caller).optional_string(sweep_to).u64(nonce).signing_data()
+    }
+
+    // Data the caller signs to lock or unlock an account.
+    pub fn lock_data(caller: &str, account_id: &str, locked: bool, nonce: u64) -> String {
+        let action = if locked { Action::LockAccount } else { Action::UnlockAccount };
+        AuthorizationPayload::new(action).string(caller).string(account_id).u64(nonce).signing_data()
+    }
+
+    /// Closes an account and leaves a tombstone in its place. Every remaining balance is
+    /// swept to `sweep_to`; without one, all balances must already be zero. Unvested coins
+    /// cannot be swept, so an account with a locked tranche cannot be closed.
+    ///
+    /// Returns the balances swept, the Luks coin under `NATIVE_ASSET`.
+    pub(crate) fn close_account(&mut self, account_id: &String, sweep_to: Option<&String>, now: u64) -> Result<Vec<(AssetId, Amount)>, AccountError> {
+        let account = self.accounts_map.get(account_id).ok_or(AccountError::AccountNotFound)?;
+        if account.is_frozen() {
+            return Err(AccountError::AccountLocked);
+        }
+        if !vesting::locked_total(&account.vesting, now).is_zero() {
+            return Err(AccountError::UnvestedBalance);
+        }
+        let mut balances: Vec<(AssetId, Amount)> = Vec::new();
+        if !account.balance.is_zero() {
+            balances.push((assets::native_asset_id(), account.balance));
+        }
+        balances.extend(account.asset_balances.iter().map(|(asset_id, balance)| (asset_id.clone(), *balance)));
+
+        match sweep_to {
+            None if !balances.is_empty() => return Err(AccountError::NonZeroBalance),
+            None => {}
+            Some(target) => {
+                if target == account_id {
+                    return Err(AccountError::InvalidSweepTarget);
+                }
+                let target_account = self.accounts_map.get(target).ok_or(AccountError::InvalidSweepTarget)?;
+                if target_account.locked {
+                    return Err(AccountError::AccountLocked);
+                }
+                // Checked up front so that the account is only removed if every balance lands.
+                for (asset_id, amount) in &balances {
+                    self.get_asset_balance(target, asset_id)?.checked_add(*amount).map_err(|_| AccountError::BalanceOverflow)?;
+                }
+            }
+        }
+
+        let account = self.accounts_map.remove(account_id).ok_or(AccountError::AccountNotFound)?;
+        if let Some(target) = sweep_to {
+            for (asset_id, amount) in &balances {
+                self.credit_asset_balance(target, asset_id, *amount)?;
+            }
+        }
+        let tombstone = ClosedAccount { closed_at: now, swept_to: sweep_to.cloned(), transactions: account.transactions };
+        self.closed_accounts.insert(account_id.clone(), tombstone);
+        self.refresh_state(account_id);
+        Ok(balances)
+    }
+
+    pub fn is_closed(&self, account_id: &String) -> bool {
+        self.closed_accounts.contains_key(account_id)
+    }
+
+    pub fn get_closed_account(&self, account_id: &String) -> Option<&ClosedAccount> {
+        self.closed_accounts.get(account_id)
+    }
+
     // Function to list all accounts
     pub fn list_all_accounts(&self) -> Vec<String> {
         self.accounts_map.keys().cloned().collect()
@@ -117,19 +686,98 @@ impl Accounts {
         self.accounts_map.get(account_id).ok_or(AccountError::AccountNotFound)
     }
 
-    // Function to securely set a new public key
-    pub fn set_public_key_secure(&mut self, account_id: &String, old_key: &PublicKey, new_key: PublicKey) -> Result<(), AccountError> {
+    // Data signed to authorise a key rotation. The nonce makes the signature single-use.
+    pub fn rotation_data(account_id: &str, new_key: &PublicKey, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::RotateKey).string(account_id).key(new_key).u64(nonce).signing_data()
+    }
+
+    // Data signed by the current key to nominate a recovery key.
+    pub fn recovery_key_data(account_id: &str, recovery_key: &PublicKey, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::SetRecoveryKey).string(account_id).key(recovery_key).u64(nonce).signing_data()
+    }
+
+    pub fn is_key_revoked(&self, key: &PublicKey) -> bool {
+        self.revoked_keys.contains_key(key)
+    }
+
+    pub fn get_revoked_key(&self, key: &PublicKey) -> Option<&RevokedKey> {
+        self.revoked_keys.get(key)
+    }
+
+    // Nominates a recovery key. Must be signed by the current key.
+    pub fn set_recovery_key(&mut self, account_id: &String, recovery_key: PublicKey, signature: &AccountSignature) -> Result<(), AccountError> {
+        if self.is_key_revoked(&recovery_key) {
+            return Err(AccountError::KeyRevoked);
+        }
+        let account = self.accounts_map.get(account_id).ok_or(AccountError::AccountNotFound)?;
+        if account.is_frozen() {
+            return Err(AccountError::AccountLocked);
+        }
+        let data = Self::recovery_key_data(account_id, &recovery_key, account.nonce);
+        if account.multisig.is_some()
+            || signature.public_key != account.public_key
+            || !Security::verify_account_signature(signature, &data)
+        {
+            return Err(AccountError::UnauthorizedKeyChange);
+        }
+
         let account = self.accounts_map.get_mut(account_id).ok_or(AccountError::AccountNotFound)?;
-        if &account.public_key != old_key {
+        account.recovery_key = Some(recovery_key);
+        account.nonce += 1;
+        self.refresh_state(account_id);
+        Ok(())
+    }
+
+    // Replaces the account key. The rotation must be signed by the current key or the
+    // recovery key; the old key is revoked and its signatures are rejected from then on.
+    // Multisig accounts change their key set instead and cannot rotate a single key.
+    pub fn rotate_key(&mut self, account_id: &String, new_key: PublicKey, signature: &AccountSignature, timestamp: u64, height: u64) -> Result<(), AccountError> {
+        if self.is_key_revoked(&new_key) || self.is_key_revoked(&signature.public_key) {
+            return Err(AccountError::KeyRevoked);
+        }
+        let account = self.accounts_map.get(account_id).ok_or(AccountError::AccountNotFound)?;
+        // A locked account may be compromised; only guardian recovery replaces its key.
+        if account.is_frozen() {
+            return Err(AccountError::AccountLocked);
+        }
+        if account.multisig.is_some() {
             return Err(AccountError::UnauthorizedKeyChange);
         }
-        account.public_key = new_key;
+        let authorised_by = if signature.public_key == account.public_key {
+            KeyRotationAuthority::CurrentKey
+        } else if Some(&signature.public_key) == account.recovery_key.as_ref() {
+            KeyRotationAuthority::RecoveryKey
+        } else {
+            return Err(AccountError::UnauthorizedKeyChange);
+        };
+        let data = Self::rotation_data(account_id, &new_key, account.nonce);
+        if !Security::verify_account_signature(signature, &data) {
+            return Err(AccountError::UnauthorizedKeyChange);
+        }
+
+        self.replace_key(account_id, new_key, authorised_by, timestamp, height)
+    }
+
+    // Swaps in the new key, records the rotation and revokes the old key.
+    pub(crate) fn replace_key(&mut self, account_id: &String, new_key: PublicKey, authorised_by: KeyRotationAuthority, timestamp: u64, height: u64) -> Result<(), AccountError> {
+        let account = self.accounts_map.get_mut(account_id).ok_or(AccountError::AccountNotFound)?;
+        let old_key = std::mem::replace(&mut account.public_key, new_key.clone());
+        account.key_rotations.push(KeyRotation {
+            old_key: old_key.clone(),
+            new_key,
+            authorised_by,
+            timestamp,
+            height,
+        });
+        account.nonce += 1;
+        self.revoked_keys.insert(old_key, RevokedKey { account_id: account_id.clone(), height });
+        self.refresh_state(account_id);
         Ok(())
     }
 
     // Nueva función para obtener el hash de una cuenta
     pub fn get_account_hash(&self, account_id: &String) -> Option<Vec<u8>> {
-        self.accounts_map.get(account_id).map(|account| AccountHash::compute_hash(account))
+        self.accounts_map.get(account_id).map(AccountHash::compute_hash)
     }
 
     // Nueva función para validar la integridad de una cuenta
@@ -141,32 +789,37 @@ impl Accounts {
         }
     }
 
-    // New Functions
-    // Function to update the account balance
-    pub fn update_account_balance(&mut self, account_id: &String, new_balance: u64) -> Result<(), AccountError> {
+    // Freezes the outgoing side of an account while a guardian recovery is in progress.
+    pub(crate) fn set_recovering(&mut self, account_id: &String, recovering: bool) -> Result<(), AccountError> {
         let account = self.accounts_map.get_mut(account_id).ok_or(AccountError::AccountNotFound)?;
-        if account.locked {
-            return Err(AccountError::AccountLocked);
-        }
-        account.balance = new_balance;
+        account.recovering = recovering;
+        self.refresh_state(account_id);
         Ok(())
     }
 
     // Function to lock an account
-    pub fn lock_account(&mut self, account_id: &String) -> Result<(), AccountError> {
+    pub(crate) fn lock_account(&mut self, roles: &Roles, caller: &String, account_id: &String) -> Result<(), AccountError> {
+        Self::check_permission(roles, caller, Permission::LockAccount)?;
         let account = self.accounts_map.get_mut(account_id).ok_or(AccountError::AccountNotFound)?;
         account.locked = true;
+        self.refresh_state(account_id);
         Ok(())
     }
 
     // Function to unlock an account
-    pub fn unlock_account(&mut self, account_id: &String) -> Result<(), AccountError> {
+    pub(crate) fn unlock_account(&mut self, roles: &Roles, caller: &String, account_id: &String) -> Result<(), AccountError> {
+        Self::check_permission(roles, caller, Permission::LockAccount)?;
         let account = self.accounts_map.get_mut(account_id).ok_or(AccountError::AccountNotFound)?;
         account.locked = false;
+        self.refresh_state(account_id);
         Ok(())
     }
+    fn check_permission(roles: &Roles, caller: &String, permission: Permission) -> Result<(), AccountError> {
+        roles.check_permission(caller, permission).map_err(|_| AccountError::Unauthorized)
+    }
+
     // New function to validate if the account is locked
-    fn is_account_locked(&self, account_id: &String) -> Result<(), AccountError> {
+    pub fn is_account_locked(&self, account_id: &String) -> Result<(), AccountError> {
         match self.accounts_map.get(account_id) {
             Some(account) if account.locked => Err(AccountError::AccountLocked),
             Some(_) => Ok(()),
@@ -183,10 +836,200 @@ impl Accounts {
         Ok(())
     }
 
+    // Closed accounts still return the transactions they kept.
     pub fn get_transactions(&self, account_id: &String) -> Result<VecDeque<TransactionHistory>, AccountError> {
+        if let Some(closed) = self.closed_accounts.get(account_id) {
+            return Ok(closed.transactions.clone());
+        }
         match self.accounts_map.get(account_id) {
             Some(account) => Ok(account.transactions.clone()),
             None => Err(AccountError::AccountNotFound),
         }
-    }    
+    }
+
+    // Writes every account to the store; accounts that no longer exist are deleted.
+    pub fn persist(&self, store: &mut dyn StateStore) -> Result<(), StorageError> {
+        for key in store.keys_with_prefix(ACCOUNT_KEY_PREFIX) {
+            if !self.accounts_map.contains_key(&key[ACCOUNT_KEY_PREFIX.len()..]) {
+                store.delete(&key);
+            }
+        }
+        for (account_id, details) in &self.accounts_map {
+            let value = serde_json::to_vec(details).map_err(|_| StorageError::Serialization)?;
+            store.put(&format!("{}{}", ACCOUNT_KEY_PREFIX, account_id), value);
+        }
+        // Tombstones and revocations are never removed.
+        for (account_id, closed) in &self.closed_accounts {
+            let value = serde_json::to_vec(closed).map_err(|_| StorageError::Serialization)?;
+            store.put(&format!("{}{}", CLOSED_ACCOUNT_KEY_PREFIX, account_id), value);
+        }
+        for (key, revoked) in &self.revoked_keys {
+            let value = serde_json::to_vec(revoked).map_err(|_| StorageError::Serialization)?;
+            store.put(&format!("{}{}", REVOKED_KEY_PREFIX, key.to_tagged_string()), value);
+        }
+        Ok(())
+    }
+
+    // Rebuilds the accounts from the last committed state in the store. The caller seals
+    // it with `checkpoint` once the assets and NFTs are committed to the tree as well.
+    pub fn restore(store: &dyn StateStore, max_transaction_history: usize) -> Result<Self, StorageError> {
+        let height = store.committed_height().unwrap_or(0);
+        let mut accounts = Accounts::new(max_transaction_history);
+        // History before the restart is not kept, so proofs start at the restored height.
+        accounts.state_tree = SparseMerkleTree::starting_at(height);
+        for key in store.keys_with_prefix(ACCOUNT_KEY_PREFIX) {
+            let value = store.get(&key).ok_or(StorageError::Corrupted)?;
+            let details: AccountDetails = serde_json::from_slice(&value).map_err(|_| StorageError::Serialization)?;
+            let account_id = key[ACCOUNT_KEY_PREFIX.len()..].to_string();
+            accounts.accounts_map.insert(account_id.clone(), details);
+            accounts.refresh_state(&account_id);
+        }
+        for key in store.keys_with_prefix(CLOSED_ACCOUNT_KEY_PREFIX) {
+            let value = store.get(&key).ok_or(StorageError::Corrupted)?;
+            let closed: ClosedAccount = serde_json::from_slice(&value).map_err(|_| StorageError::Serialization)?;
+            accounts.closed_accounts.insert(key[CLOSED_ACCOUNT_KEY_PREFIX.len()..].to_string(), closed);
+        }
+        // Closed accounts no longer hold their rotations, so the list is restored on its own.
+        for key in store.keys_with_prefix(REVOKED_KEY_PREFIX) {
+            let value = store.get(&key).ok_or(StorageError::Corrupted)?;
+            let revoked: RevokedKey = serde_json::from_slice(&value).map_err(|_| StorageError::Serialization)?;
+            let public_key = PublicKey::parse_tagged(&key[REVOKED_KEY_PREFIX.len()..]).ok_or(StorageError::Corrupted)?;
+            accounts.revoked_keys.insert(public_key, revoked);
+        }
+        Ok(accounts)
+    }
+}
+
+fn current_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Checks a proof served by an untrusted node against a trusted state root.
+///
+/// With `expected_balance` set, the proof must show the account exists with that balance;
+/// with `None`, it must show the account does not exist.
+pub fn verify_account_proof(state_root: &Hash, account_id: &str, expected_balance: Option<Amount>, proof: &MerkleProof) -> bool {
+    if proof.key != merkle::account_key(account_id) || !merkle::verify_proof(state_root, proof) {
+        return false;
+    }
+    match (&proof.value, expected_balance) {
+        // The canonical encoding starts with the balance.
+        (Some(value), Some(balance)) => value.len() >= 8 && value[..8] == balance.units().to_be_bytes(),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coin_issue::{CoinIssue, CoinIssueError};
+    use crate::concensus::{Consensus, TransactionKind};
+    use crate::ledger::{Ledger, LedgerError, LedgerTransaction, LedgerTransactionKind};
+    use crate::roles::Role;
+    use crate::storage::MemoryStore;
+    use crate::testing::{self, ALICE, BOB, CAROL, OWNER};
+    use crate::validate_transaction::{ValidateTransaction, ValidationError};
+
+    const NEW_KEY: [u8; 32] = [0x07; 32];
+
+    // Alice replaces her key with `NEW_KEY`, signing with her current one.
+    fn rotate_alice(accounts: &mut Accounts) -> Result<(), AccountError> {
+        let alice = testing::address(&ALICE);
+        let nonce = accounts.get_nonce(&alice)?;
+        let signature = testing::sign(&ALICE, &Accounts::rotation_data(&alice, &testing::public_key(&NEW_KEY), nonce));
+        accounts.rotate_key(&alice, testing::public_key(&NEW_KEY), &signature, 0, 1)
+    }
+
+    #[test]
+    fn a_multisig_account_needs_its_threshold_to_send_and_to_close() {
+        let keys = [ALICE, BOB, CAROL];
+        assert!(matches!(MultiSigPolicy::new(keys.iter().map(testing::public_key).collect(), 0), Err(AccountError::InvalidMultiSigPolicy)));
+        assert!(matches!(MultiSigPolicy::new(keys.iter().map(testing::public_key).collect(), 4), Err(AccountError::InvalidMultiSigPolicy)));
+        assert!(matches!(MultiSigPolicy::new(vec![testing::public_key(&ALICE); 2], 2), Err(AccountError::InvalidMultiSigPolicy)));
+        // Neither the central bank nor the treasury may hang on a single key.
+        let single = MultiSigPolicy::single(testing::public_key(&ALICE));
+        let alice = testing::address(&ALICE);
+        assert!(matches!(CoinIssue::new(testing::INITIAL_SUPPLY, Amount::ONE, 0, Amount::MAX, alice.clone(), single), Err(CoinIssueError::SingleKeyCentralBank)));
+        let ledger = testing::ledger();
+        let validator = ValidateTransaction::new(Security::new());
+        let single_key_treasury = Ledger::new(testing::accounts(), validator, ledger.coin_issue, Consensus::new("00".repeat(32)), alice, Roles::new(&testing::address(&OWNER)));
+        assert!(matches!(single_key_treasury, Err(LedgerError::SingleKeyTreasury)));
+
+        // 2-of-3 account held by alice, bob and carol.
+        let policy = MultiSigPolicy::new(keys.iter().map(testing::public_key).collect(), 2).ok().unwrap();
+        let shared = LuksAddress::from_multisig(&policy).to_string();
+        let owner = testing::address(&OWNER);
+        let mut accounts = testing::accounts();
+        assert!(accounts.create_multisig_account(shared.clone(), Amount::from_units(50_000), policy).is_ok());
+        let mut ledger = testing::ledger_with(accounts);
+
+        let asset = NATIVE_ASSET.to_string();
+        let amount = Amount::from_units(1_000);
+        let data = ValidateTransaction::signing_data(&TransactionKind::Transfer, &shared, &owner, &asset, amount, Amount::ZERO, 0);
+        let send = |signers: &[[u8; 32]]| LedgerTransaction {
+            kind: LedgerTransactionKind::Transfer,
+            from: shared.clone(),
+            to: owner.clone(),
+            asset: asset.clone(),
+            amount,
+            tip: Amount::ZERO,
+            nonce: 0,
+            signatures: signers.iter().map(|key| testing::sign(key, &data)).collect(),
+        };
+        // The same key twice still counts once.
+        assert!(matches!(ledger.apply(send(&[ALICE])), Err(LedgerError::Validation(ValidationError::InsufficientSignatures))));
+        assert!(matches!(ledger.apply(send(&[ALICE, ALICE])), Err(LedgerError::Validation(ValidationError::InsufficientSignatures))));
+        assert!(ledger.apply(send(&[ALICE, CAROL])).is_ok());
+
+        // Closing sweeps what is left to the owner, again only with two of the three keys.
+        let left = ledger.accounts.get_balance(&shared).ok().unwrap();
+        let before = ledger.accounts.get_balance(&owner).ok().unwrap();
+        let close = Accounts::closure_data(&shared, Some(&owner), 1);
+        let one = [testing::sign(&BOB, &close)];
+        assert!(matches!(ledger.close_account(&shared, Some(&owner), 1, &one), Err(LedgerError::Validation(ValidationError::InsufficientSignatures))));
+        assert_eq!(ledger.accounts.get_balance(&shared).ok(), Some(left));
+        let two = [testing::sign(&BOB, &close), testing::sign(&CAROL, &close)];
+        assert!(ledger.close_account(&shared, Some(&owner), 1, &two).is_ok());
+        assert_eq!(ledger.accounts.get_balance(&owner).ok(), before.checked_add(left).ok());
+        assert!(ledger.accounts.is_closed(&shared));
+    }
+
+    #[test]
+    fn keys_revoked_by_a_closed_account_stay_revoked_after_a_restart() {
+        let mut accounts = testing::accounts();
+        let (alice, bob) = (testing::address(&ALICE), testing::address(&BOB));
+        assert!(rotate_alice(&mut accounts).is_ok());
+        assert!(accounts.close_account(&alice, Some(&bob), 0).is_ok());
+
+        let mut store = MemoryStore::new();
+        assert!(accounts.persist(&mut store).is_ok());
+        assert!(store.commit(1).is_ok());
+        let restored = match Accounts::restore(&store, 10) {
+            Ok(restored) => restored,
+            Err(_) => panic!("restore"),
+        };
+        let old_key = testing::public_key(&ALICE);
+        assert_eq!(restored.get_revoked_key(&old_key), Some(&RevokedKey { account_id: alice, height: 1 }));
+        assert_eq!(restored.revoked_keys, accounts.revoked_keys);
+    }
+
+    #[test]
+    fn a_locked_account_cannot_change_its_keys() {
+        let mut accounts = testing::accounts();
+        let (owner, alice) = (testing::address(&OWNER), testing::address(&ALICE));
+        let mut roles = Roles::new(&owner);
+        assert!(roles.grant_role(&owner, &owner, Role::ComplianceOfficer).is_ok());
+        assert!(accounts.lock_account(&roles, &owner, &alice).is_ok());
+        assert!(matches!(rotate_alice(&mut accounts), Err(AccountError::AccountLocked)));
+        let recovery_key = testing::public_key(&NEW_KEY);
+        let signature = testing::sign(&ALICE, &Accounts::recovery_key_data(&alice, &recovery_key, 0));
+        assert!(matches!(accounts.set_recovery_key(&alice, recovery_key, &signature), Err(AccountError::AccountLocked)));
+        assert_eq!(accounts.get_nonce(&alice).ok(), Some(0));
+        assert!(!accounts.is_key_revoked(&testing::public_key(&ALICE)));
+
+        assert!(accounts.unlock_account(&roles, &owner, &alice).is_ok());
+        assert!(rotate_alice(&mut accounts).is_ok());
+        assert!(accounts.is_key_revoked(&testing::public_key(&ALICE)));
+    }
 }
@@ -0,0 +1,239 @@
+use std::fmt;
+use std::str::FromStr;
+use sha2::{Sha256, Digest};
+use crate::accounts::{MultiSigPolicy, PublicKey};
+use crate::signature_scheme::SchemeId;
+
+/// Human-readable prefix of every Luks address.
+pub const ADDRESS_HRP: &str = "luks";
+
+// Bytes of the public key hash kept in an address.
+const PAYLOAD_LEN: usize = 20;
+const CHECKSUM_LEN: usize = 6;
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATORS: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+pub enum AddressError {
+    InvalidLength,
+    InvalidCharacter,
+    MixedCase,
+    InvalidPrefix,
+    InvalidChecksum,
+    InvalidPadding,
+}
+
+/// Canonical account address: the first 20 bytes of the SHA-256 of the account key,
+/// bech32-encoded under the `luks` prefix (e.g. `luks1...`). The six-character checksum
+/// catches mistyped addresses before any funds move.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct LuksAddress {
+    payload: [u8; PAYLOAD_LEN],
+}
+
+impl LuksAddress {
+    // secp256k1 keys hash the bare key, as before schemes were tagged; other schemes
+    // prefix their tag so equal bytes under two schemes never share an address.
+    pub fn from_public_key(public_key: &PublicKey) -> Self {
+        let mut hasher = Sha256::new();
+        put_scheme_tag(&mut hasher, public_key);
+        hasher.update(&public_key.value);
+        Self::from_digest(&hasher.finalize())
+    }
+
+    // Address of a multisig account: hash of the threshold followed by its keys.
+    pub fn from_multisig(policy: &MultiSigPolicy) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update((policy.threshold as u32).to_be_bytes());
+        for key in &policy.keys {
+            put_scheme_tag(&mut hasher, key);
+            hasher.update((key.value.len() as u32).to_be_bytes());
+            hasher.update(&key.value);
+        }
+        Self::from_digest(&hasher.finalize())
+    }
+
+    fn from_digest(digest: &[u8]) -> Self {
+        let mut payload = [0u8; PAYLOAD_LEN];
+        payload.copy_from_slice(&digest[..PAYLOAD_LEN]);
+        LuksAddress { payload }
+    }
+
+    pub fn parse(address: &str) -> Result<Self, AddressError> {
+        let has_lower = address.chars().any(|c| c.is_ascii_lowercase());
+        let has_upper = address.chars().any(|c| c.is_ascii_uppercase());
+        if has_lower && has_upper {
+            return Err(AddressError::MixedCase);
+        }
+        let address = address.to_ascii_lowercase();
+
+        let separator = address.rfind('1').ok_or(AddressError::InvalidPrefix)?;
+        let (hrp, data) = (&address[..separator], &address[separator + 1..]);
+        if hrp != ADDRESS_HRP {
+            return Err(AddressError::InvalidPrefix);
+        }
+
+        let mut values = Vec::with_capacity(data.len());
+        for c in data.bytes() {
+            let value = CHARSET.iter().position(|&x| x == c).ok_or(AddressError::InvalidCharacter)?;
+            values.push(value as u8);
+        }
+        if values.len() <= CHECKSUM_LEN {
+            return Err(AddressError::InvalidLength);
+        }
+        if !verify_checksum(hrp, &values) {
+            return Err(AddressError::InvalidChecksum);
+        }
+
+        let bytes = convert_bits(&values[..values.len() - CHECKSUM_LEN], 5, 8, false)?;
+        if bytes.len() != PAYLOAD_LEN {
+            return Err(AddressError::InvalidLength);
+        }
+        Ok(Self::from_digest(&bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+impl fmt::Display for LuksAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Converting whole bytes with padding cannot fail.
+        let mut values = convert_bits(&self.payload, 8, 5, true).unwrap_or_default();
+        values.extend_from_slice(&create_checksum(ADDRESS_HRP, &values));
+        let encoded: String = values.iter().map(|&v| CHARSET[v as usize] as char).collect();
+        write!(f, "{}1{}", ADDRESS_HRP, encoded)
+    }
+}
+
+impl FromStr for LuksAddress {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// Parses an address received at an entry point, rejecting malformed or mistyped ones.
+pub fn validate_address(address: &str) -> Result<LuksAddress, AddressError> {
+    LuksAddress::parse(address)
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    let mut checksum: u32 = 1;
+    for &value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ffffff) << 5) ^ value as u32;
+        for (i, generator) in GENERATORS.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut expanded: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    expanded.push(0);
+    expanded.extend(hrp.bytes().map(|b| b & 31));
+    expanded
+}
+
+fn create_checksum(hrp: &str, data: &[u8]) -> Vec<u8> {
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(data);
+    values.extend_from_slice(&[0u8; CHECKSUM_LEN]);
+    let checksum = polymod(&values) ^ 1;
+    (0..CHECKSUM_LEN).map(|i| ((checksum >> (5 * (5 - i))) & 31) as u8).collect()
+}
+
+fn verify_checksum(hrp: &str, data: &[u8]) -> bool {
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(data);
+    polymod(&values) == 1
+}
+
+// Regroups bits, e.g. 8-bit bytes into 5-bit bech32 values and back.
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, AddressError> {
+    let mut accumulator: u32 = 0;
+    let mut bits: u32 = 0;
+    let max_value: u32 = (1 << to) - 1;
+    let mut result = Vec::new();
+    for &value in data {
+        accumulator = (accumulator << from) | value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            result.push(((accumulator >> bits) & max_value) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            result.push(((accumulator << (to - bits)) & max_value) as u8);
+        }
+    } else if bits >= from || ((accumulator << (to - bits)) & max_value) != 0 {
+        return Err(AddressError::InvalidPadding);
+    }
+    Ok(result)
+}
+
+fn put_scheme_tag(hasher: &mut Sha256, key: &PublicKey) {
+    if key.scheme != SchemeId::Secp256k1 {
+        hasher.update([key.scheme.tag()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::ledger::LedgerError;
+    use crate::testing::{self, ALICE, BOB};
+
+    // The address with its `index`-th data character replaced by the next one in the charset.
+    fn mistype(address: &str, index: usize) -> String {
+        let mut chars: Vec<char> = address.chars().collect();
+        let position = ADDRESS_HRP.len() + 1 + index;
+        let value = CHARSET.iter().position(|&c| c as char == chars[position]).unwrap();
+        chars[position] = CHARSET[(value + 1) % CHARSET.len()] as char;
+        chars.into_iter().collect()
+    }
+
+    #[test]
+    fn addresses_round_trip_and_accept_a_single_case() {
+        let address = LuksAddress::from_public_key(&testing::public_key(&ALICE));
+        let encoded = address.to_string();
+        assert!(encoded.starts_with("luks1"));
+        assert_eq!(LuksAddress::parse(&encoded).ok(), Some(address.clone()));
+        assert_eq!(LuksAddress::parse(&encoded.to_ascii_uppercase()).ok(), Some(address));
+
+        // Same key bytes under another scheme give another address.
+        let mut ed25519 = testing::public_key(&ALICE);
+        ed25519.scheme = SchemeId::Ed25519;
+        assert_ne!(LuksAddress::from_public_key(&ed25519).to_string(), encoded);
+    }
+
+    #[test]
+    fn malformed_or_mistyped_addresses_are_rejected() {
+        let encoded = testing::address(&ALICE);
+        let mixed = format!("LUKS{}", &encoded[ADDRESS_HRP.len()..]);
+        assert!(matches!(LuksAddress::parse(&mixed), Err(AddressError::MixedCase)));
+
+        // Any single mistyped character breaks the checksum.
+        for index in 0..encoded.len() - ADDRESS_HRP.len() - 1 {
+            assert!(matches!(LuksAddress::parse(&mistype(&encoded, index)), Err(AddressError::InvalidChecksum)));
+        }
+        assert!(matches!(LuksAddress::parse(&encoded.replacen("luks", "lukz", 1)), Err(AddressError::InvalidPrefix)));
+        assert!(matches!(LuksAddress::parse(&format!("{}b", &encoded[..encoded.len() - 1])), Err(AddressError::InvalidCharacter)));
+        assert!(matches!(LuksAddress::parse("luks1qqqqqqq"), Err(AddressError::InvalidChecksum)));
+        assert!(matches!(LuksAddress::parse("luks1qqqqqq"), Err(AddressError::InvalidLength)));
+
+        // The ledger refuses to send to a mistyped recipient.
+        let mut ledger = testing::ledger();
+        let to = mistype(&testing::address(&BOB), 3);
+        let tx = testing::transfer(&ledger, &ALICE, &to, Amount::from_units(100));
+        assert!(matches!(ledger.apply(tx), Err(LedgerError::Address(AddressError::InvalidChecksum))));
+        assert_eq!(ledger.accounts.get_balance(&testing::address(&ALICE)).ok(), Some(testing::INITIAL_BALANCE));
+    }
+}
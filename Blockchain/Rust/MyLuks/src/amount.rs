@@ -0,0 +1,98 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+
+pub enum AmountError {
+    Overflow,
+    Underflow,
+    DivisionByZero,
+    InvalidFormat,
+}
+
+/// A quantity of coins, stored as a whole number of base units.
+///
+/// One coin is `10^DECIMALS` base units, so `Amount::from_units(150_000_000)` displays as
+/// `1.50000000`. All arithmetic is checked: nothing wraps, and every failure is an error.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const DECIMALS: u32 = 8;
+    pub const ZERO: Amount = Amount(0);
+    pub const ONE: Amount = Amount(100_000_000);
+    pub const MAX: Amount = Amount(u64::MAX);
+
+    pub const fn from_units(units: u64) -> Self {
+        Amount(units)
+    }
+
+    // Whole coins, e.g. `from_coins(3)` is 3.00000000.
+    pub fn from_coins(coins: u64) -> Result<Self, AmountError> {
+        coins.checked_mul(Self::ONE.0).map(Amount).ok_or(AmountError::Overflow)
+    }
+
+    pub const fn units(self) -> u64 {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Result<Amount, AmountError> {
+        self.0.checked_add(other.0).map(Amount).ok_or(AmountError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Result<Amount, AmountError> {
+        self.0.checked_sub(other.0).map(Amount).ok_or(AmountError::Underflow)
+    }
+
+    /// `self * numerator / denominator`, rounded down. The product is computed in 128 bits,
+    /// so only a result that does not fit in an `Amount` fails.
+    pub fn checked_mul_ratio(self, numerator: u64, denominator: u64) -> Result<Amount, AmountError> {
+        if denominator == 0 {
+            return Err(AmountError::DivisionByZero);
+        }
+        let result = self.0 as u128 * numerator as u128 / denominator as u128;
+        u64::try_from(result).map(Amount).map_err(|_| AmountError::Overflow)
+    }
+
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_sub(other.0))
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let one = Self::ONE.0;
+        write!(f, "{}.{:0width$}", self.0 / one, self.0 % one, width = Self::DECIMALS as usize)
+    }
+}
+
+// Parses "12", "12.5" or "0.00000001"; more than `DECIMALS` decimal places is an error.
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (whole, fraction) = match s.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (s, ""),
+        };
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) || fraction.len() > Self::DECIMALS as usize {
+            return Err(AmountError::InvalidFormat);
+        }
+        let whole: u64 = whole.parse().map_err(|_| AmountError::Overflow)?;
+        let mut fraction_units: u64 = 0;
+        if !fraction.is_empty() {
+            let padding = Self::DECIMALS - fraction.len() as u32;
+            fraction_units = fraction.parse::<u64>().map_err(|_| AmountError::InvalidFormat)? * 10u64.pow(padding);
+        }
+        Self::from_coins(whole)?.checked_add(Amount(fraction_units))
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::accounts::{Accounts, AccountError, AccountSignature};
+use crate::address;
+use crate::amount::Amount;
+use crate::concensus::TransactionKind;
+use crate::storage::{StateStore, StorageError};
+use crate::validate_transaction::ValidateTransaction;
+
+pub type AssetId = String;
+
+// Prefix of registered assets in the store
+const ASSET_KEY_PREFIX: &str = "asset/";
+
+/// The Luks coin. Its balance lives in `AccountDetails::balance` and its issuance is
+/// governed by `CoinIssue`, not by the registry.
+pub const NATIVE_ASSET: &str = "LUKS";
+
+pub fn native_asset_id() -> AssetId {
+    NATIVE_ASSET.to_string()
+}
+
+pub enum AssetError {
+    AssetAlreadyExists,
+    AssetNotFound,
+    InvalidAsset,
+    NotIssuer,
+    InvalidSignature,
+    InvalidNonce,
+    SupplyCapExceeded,
+    BurnExceedsSupply,
+    InvalidAddress,
+    Account(AccountError),
+}
+
+impl From<AccountError> for AssetError {
+    fn from(error: AccountError) -> Self {
+        AssetError::Account(error)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Asset {
+    pub id: AssetId,
+    pub symbol: String,
+    pub decimals: u8,
+    pub issuer: String, // Account allowed to issue this asset.
+    pub supply_cap: Option<Amount>,
+    pub total_supply: Amount,
+}
+
+impl Asset {
+    // Fixed-order encoding of the asset, committed to the state tree.
+    pub fn canonical_encoding(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for field in [&self.id, &self.symbol, &self.issuer] {
+            data.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            data.extend_from_slice(field.as_bytes());
+        }
+        data.push(self.decimals);
+        match self.supply_cap {
+            Some(cap) => {
+                data.push(1);
+                data.extend_from_slice(&cap.units().to_be_bytes());
+            }
+            None => data.push(0),
+        }
+        data.extend_from_slice(&self.total_supply.units().to_be_bytes());
+        data
+    }
+}
+
+/// Registry of the assets, other than the Luks coin, that can be held on the ledger.
+#[derive(Debug, PartialEq)]
+pub struct AssetRegistry {
+    assets: HashMap<AssetId, Asset>,
+}
+
+impl Default for AssetRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AssetRegistry {
+    pub fn new() -> Self {
+        AssetRegistry {
+            assets: HashMap::new(),
+        }
+    }
+
+    pub fn register_asset(&mut self, accounts: &mut Accounts, id: AssetId, symbol: String, decimals: u8, issuer: String, supply_cap: Option<Amount>) -> Result<(), AssetError> {
+        if id == NATIVE_ASSET || id.is_empty() || symbol.is_empty() {
+            return Err(AssetError::InvalidAsset);
+        }
+        if self.assets.contains_key(&id) {
+            return Err(AssetError::AssetAlreadyExists);
+        }
+        address::validate_address(&issuer).map_err(|_| AssetError::InvalidAddress)?;
+        accounts.get_account_details(&issuer)?;
+        let asset = Asset {
+            id: id.clone(),
+            symbol,
+            decimals,
+            issuer,
+            supply_cap,
+            total_supply: Amount::ZERO,
+        };
+        accounts.commit_asset(&asset);
+        self.assets.insert(id, asset);
+        Ok(())
+    }
+
+    pub fn get_asset(&self, id: &AssetId) -> Option<&Asset> {
+        self.assets.get(id)
+    }
+
+    pub fn list_assets(&self) -> Vec<&Asset> {
+        self.assets.values().collect()
+    }
+
+    // Native asset or a registered one.
+    pub fn is_known(&self, id: &AssetId) -> bool {
+        id == NATIVE_ASSET || self.assets.contains_key(id)
+    }
+
+    // Whether `amount` of an asset can be burned, checked before any balance moves.
+    pub fn can_burn(&self, asset_id: &AssetId, amount: Amount) -> bool {
+        self.assets.get(asset_id).is_some_and(|asset| amount <= asset.total_supply)
+    }
+
+    /// Removes units of a registered asset from its supply, e.g. burned base fees.
+    pub fn burn(&mut self, accounts: &mut Accounts, asset_id: &AssetId, amount: Amount) -> Result<(), AssetError> {
+        let asset = self.assets.get_mut(asset_id).ok_or(AssetError::AssetNotFound)?;
+        asset.total_supply = asset.total_supply.checked_sub(amount).map_err(|_| AssetError::BurnExceedsSupply)?;
+        accounts.commit_asset(asset);
+        Ok(())
+    }
+
+    // Block transaction kind of a registration.
+    pub fn registration_kind(symbol: &str, decimals: u8, supply_cap: Option<Amount>) -> TransactionKind {
+        TransactionKind::AssetRegistration { symbol: symbol.to_string(), decimals, supply_cap }
+    }
+
+    // Data the caller signs to register an asset: the signing payload of its block transaction.
+    pub fn registration_data(caller: &str, id: &AssetId, symbol: &str, decimals: u8, issuer: &str, supply_cap: Option<Amount>, nonce: u64) -> String {
+        ValidateTransaction::signing_data(&Self::registration_kind(symbol, decimals, supply_cap), caller, issuer, id, Amount::ZERO, Amount::ZERO, nonce)
+    }
+
+    // Data the issuer signs to issue `amount` of an asset: the signing payload of its block transaction.
+    pub fn issue_data(issuer: &str, asset_id: &AssetId, to: &str, amount: Amount, nonce: u64) -> String {
+        ValidateTransaction::signing_data(&TransactionKind::AssetIssuance, issuer, to, asset_id, amount, Amount::ZERO, nonce)
+    }
+
+    /// Issues new units of an asset to `to`. Only the asset's issuer may do this, signed
+    /// with its account keys and its next nonce, and never beyond the supply cap.
+    #[allow(clippy::too_many_arguments)]
+    pub fn issue(&mut self, accounts: &mut Accounts, asset_id: &AssetId, caller: &String, to: &String, amount: Amount, nonce: u64, signatures: &[AccountSignature]) -> Result<(), AssetError> {
+        let asset = self.assets.get(asset_id).ok_or(AssetError::AssetNotFound)?;
+        address::validate_address(to).map_err(|_| AssetError::InvalidAddress)?;
+        if &asset.issuer != caller {
+            return Err(AssetError::NotIssuer);
+        }
+        let new_supply = asset.total_supply.checked_add(amount).map_err(|_| AssetError::SupplyCapExceeded)?;
+        if asset.supply_cap.is_some_and(|cap| new_supply > cap) {
+            return Err(AssetError::SupplyCapExceeded);
+        }
+
+        let issuer = accounts.get_account_details(caller)?;
+        if issuer.is_frozen() {
+            return Err(AssetError::Account(AccountError::AccountLocked));
+        }
+        if issuer.nonce != nonce {
+            return Err(AssetError::InvalidNonce);
+        }
+        let data = Self::issue_data(caller, asset_id, to, amount, nonce);
+        if signatures.iter().any(|s| accounts.is_key_revoked(&s.public_key))
+            || !issuer.signing_policy().is_satisfied(&data, signatures)
+        {
+            return Err(AssetError::InvalidSignature);
+        }
+        accounts.get_account_details(to)?;
+
+        accounts.credit_asset_balance(to, asset_id, amount)?;
+        accounts.increment_nonce(caller)?;
+        if let Some(asset) = self.assets.get_mut(asset_id) {
+            asset.total_supply = new_supply;
+            accounts.commit_asset(asset);
+        }
+        Ok(())
+    }
+
+    // Writes every asset to the store; assets that no longer exist are deleted.
+    pub fn persist(&self, store: &mut dyn StateStore) -> Result<(), StorageError> {
+        for key in store.keys_with_prefix(ASSET_KEY_PREFIX) {
+            if !self.assets.contains_key(&key[ASSET_KEY_PREFIX.len()..]) {
+                store.delete(&key);
+            }
+        }
+        for (id, asset) in &self.assets {
+            let value = serde_json::to_vec(asset).map_err(|_| StorageError::Serialization)?;
+            store.put(&format!("{}{}", ASSET_KEY_PREFIX, id), value);
+        }
+        Ok(())
+    }
+
+    // Rebuilds the registry from the last committed state in the store, committing each
+    // asset back to the state tree of `accounts`.
+    pub fn restore(store: &dyn StateStore, accounts: &mut Accounts) -> Result<Self, StorageError> {
+        let mut registry = AssetRegistry::new();
+        for key in store.keys_with_prefix(ASSET_KEY_PREFIX) {
+            let value = store.get(&key).ok_or(StorageError::Corrupted)?;
+            let asset: Asset = serde_json::from_slice(&value).map_err(|_| StorageError::Serialization)?;
+            accounts.commit_asset(&asset);
+            registry.assets.insert(key[ASSET_KEY_PREFIX.len()..].to_string(), asset);
+        }
+        Ok(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::concensus::TransactionKind;
+    use crate::ledger::{Ledger, LedgerError};
+    use crate::roles::Role;
+    use crate::storage::MemoryStore;
+    use crate::testing::{self, ALICE, BOB, OWNER};
+
+    // The owner, as operator, registers GOLD issued by alice and capped at 1,000 units.
+    fn ledger_with_gold() -> Ledger {
+        let mut ledger = testing::ledger();
+        let (owner, alice) = (testing::address(&OWNER), testing::address(&ALICE));
+        testing::grant_role(&mut ledger, &owner, Role::Operator);
+        let nonce = ledger.accounts.get_nonce(&owner).unwrap_or(0);
+        let cap = Some(Amount::from_units(1_000));
+        let data = AssetRegistry::registration_data(&owner, &"GOLD".to_string(), "GOLD", 2, &alice, cap, nonce);
+        assert!(ledger.register_asset(&owner, "GOLD".to_string(), "GOLD".to_string(), 2, alice, cap, nonce, &[testing::sign(&OWNER, &data)]).is_ok());
+        ledger
+    }
+
+    fn issue(ledger: &mut Ledger, key: &[u8; 32], amount: Amount) -> Result<(), LedgerError> {
+        let (issuer, bob, gold) = (testing::address(key), testing::address(&BOB), "GOLD".to_string());
+        let nonce = ledger.accounts.get_nonce(&issuer).unwrap_or(0);
+        let signature = testing::sign(key, &AssetRegistry::issue_data(&issuer, &gold, &bob, amount, nonce));
+        ledger.issue_asset(&gold, &issuer, &bob, amount, nonce, &[signature])
+    }
+
+    #[test]
+    fn only_the_issuer_issues_and_never_beyond_the_cap() {
+        let mut ledger = ledger_with_gold();
+        let (bob, gold) = (testing::address(&BOB), "GOLD".to_string());
+        assert!(matches!(issue(&mut ledger, &BOB, Amount::from_units(100)), Err(LedgerError::Asset(AssetError::NotIssuer))));
+        assert!(issue(&mut ledger, &ALICE, Amount::from_units(600)).is_ok());
+        assert!(matches!(issue(&mut ledger, &ALICE, Amount::from_units(401)), Err(LedgerError::Asset(AssetError::SupplyCapExceeded))));
+        assert!(issue(&mut ledger, &ALICE, Amount::from_units(400)).is_ok());
+        assert_eq!(ledger.accounts.get_asset_balance(&bob, &gold).ok(), Some(Amount::from_units(1_000)));
+        assert_eq!(ledger.assets.get_asset(&gold).map(|asset| asset.total_supply), Some(Amount::from_units(1_000)));
+    }
+
+    #[test]
+    fn a_locked_issuer_cannot_issue() {
+        let mut ledger = ledger_with_gold();
+        testing::lock_account(&mut ledger, &testing::address(&ALICE));
+        let root = ledger.accounts.state_root();
+        assert!(matches!(issue(&mut ledger, &ALICE, Amount::from_units(100)), Err(LedgerError::Asset(AssetError::Account(AccountError::AccountLocked)))));
+        assert_eq!(ledger.accounts.state_root(), root);
+    }
+
+    #[test]
+    fn registration_and_issuance_are_sealed_in_blocks() {
+        let mut ledger = ledger_with_gold();
+        let (owner, alice, bob) = (testing::address(&OWNER), testing::address(&ALICE), testing::address(&BOB));
+        assert!(issue(&mut ledger, &ALICE, Amount::from_units(250)).is_ok());
+        let mut store = MemoryStore::new();
+        assert!(ledger.commit_block(&mut store, &bob).is_ok());
+
+        // The block holds everything needed to register GOLD and credit bob again.
+        let block = ledger.consensus.blockchain.last().unwrap();
+        let registration = &block.transactions[0];
+        assert_eq!(registration.kind, TransactionKind::AssetRegistration { symbol: "GOLD".to_string(), decimals: 2, supply_cap: Some(Amount::from_units(1_000)) });
+        assert_eq!((&registration.from, &registration.to, registration.asset.as_str()), (&owner, &alice, "GOLD"));
+        let issuance = &block.transactions[1];
+        assert_eq!(issuance.kind, TransactionKind::AssetIssuance);
+        assert_eq!((&issuance.from, &issuance.to, issuance.asset.as_str(), issuance.amount), (&alice, &bob, "GOLD", Amount::from_units(250)));
+    }
+}
@@ -1,23 +1,36 @@
-use std::collections::HashSet;
-use std::time::Instant; // Para medir el rendimiento
-use crypto::digest::Digest; // Para hash verification
-use crypto::sha2::Sha256; // Para hash verification
+use sha2::{Digest, Sha256}; // Para hash verification
+use crate::accounts::{AccountSignature, MultiSigPolicy};
+use crate::address::LuksAddress;
+use crate::amount::Amount;
+use crate::roles::{Permission, Roles};
+use crate::storage::{StateStore, StorageError};
+use serde::{Serialize, Deserialize};
+
+// Key of the issuance state in the store
+const COIN_ISSUE_KEY: &str = "coin_issue";
 
 pub enum CoinIssueError {
-    TransactionAlreadyProcessed,
+    StaleNonce,
+    NonceGap,
     MaxSupplyReached,
     InvalidAddress,
     NotAuthorized,
+    SingleKeyCentralBank, // El banco central debe estar controlado por varias llaves
     IntegrityViolation, // Nuevo: Para verificación de hash
+    BurnExceedsSupply, // Se intentó quemar más de lo que circula
+    AmountMismatch, // El importe firmado no es la emisión siguiente
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct CoinIssue {
-    total_supply: u64,
-    reward_per_block: u64,
-    inflation_rate: u64,
-    max_supply: u64,
+    total_supply: Amount,
+    reward_per_block: Amount,
+    inflation_rate: u64, // Porcentaje del suministro añadido a la recompensa en cada emisión
+    max_supply: Amount,
     central_bank: String,
-    emitted_transactions: HashSet<String>,
+    central_bank_policy: MultiSigPolicy, // Llaves que deben aprobar cada emisión
+    issue_nonce: u64, // Siguiente nonce de emisión esperado
+    total_burned: Amount, // Comisiones base quemadas desde el génesis
     accounts_hash: String, // Nuevo: Para la verificación de hash
 }
 
@@ -25,76 +38,148 @@ impl CoinIssue {
     const INITIAL_HASH: &'static str = "some_real_initial_hash_value"; 
 
     pub fn new(
-        initial_supply: u64,
-        reward_per_block: u64,
+        initial_supply: Amount,
+        reward_per_block: Amount,
         inflation_rate: u64,
-        max_supply: u64,
+        max_supply: Amount,
         central_bank: String,
-    ) -> Self {
-        CoinIssue {
+        central_bank_policy: MultiSigPolicy,
+    ) -> Result<Self, CoinIssueError> {
+        if central_bank_policy.is_single_key() {
+            return Err(CoinIssueError::SingleKeyCentralBank);
+        }
+        // El banco central debe ser la dirección derivada de su política.
+        if central_bank != LuksAddress::from_multisig(&central_bank_policy).to_string() {
+            return Err(CoinIssueError::InvalidAddress);
+        }
+        Ok(CoinIssue {
             total_supply: initial_supply,
             reward_per_block,
             inflation_rate,
             max_supply,
             central_bank,
-            emitted_transactions: HashSet::new(),
+            central_bank_policy,
+            issue_nonce: 0,
+            total_burned: Amount::ZERO,
             accounts_hash: Self::INITIAL_HASH.to_string(),
-        }
+        })
     }
 
-    pub fn can_issue_coins(&self, requesting_address: &String) -> bool {
-        &self.central_bank == requesting_address
+    // Cualquier cuenta con el rol de emisor puede solicitar una emisión; las llaves del
+    // banco central deben aprobarla igualmente.
+    pub fn can_issue_coins(&self, roles: &Roles, requesting_address: &String) -> bool {
+        roles.has_permission(requesting_address, Permission::IssueCoins)
     }
 
-    fn update_and_verify_hash(&mut self, new_transaction_id: &String) -> bool {
+    // Siguiente eslabón de la cadena de hashes, o None si no cambiaría.
+    fn next_hash(&self, new_transaction_id: &String) -> Option<String> {
         let mut hasher = Sha256::new();
-        hasher.input_str(&format!("{}{}", &self.accounts_hash, new_transaction_id));
-        let new_hash = hasher.result_str();
+        hasher.update(format!("{}{}", &self.accounts_hash, new_transaction_id));
+        let new_hash = hex::encode(hasher.finalize());
 
         if new_hash == self.accounts_hash {
-            return false;
+            return None;
         }
+        Some(new_hash)
+    }
 
-        self.accounts_hash = new_hash;
-        true
+    /// Importe de la próxima emisión; es el que deben firmar las llaves del banco central.
+    pub fn next_emission(&self) -> Result<Amount, CoinIssueError> {
+        self.recalculate_reward()
+    }
+
+    // Una recompensa que no cabe en un `Amount` nunca podría emitirse.
+    fn recalculate_reward(&self) -> Result<Amount, CoinIssueError> {
+        let inflation = self.total_supply.checked_mul_ratio(self.inflation_rate, 100).map_err(|_| CoinIssueError::MaxSupplyReached)?;
+        self.reward_per_block.checked_add(inflation).map_err(|_| CoinIssueError::MaxSupplyReached)
+    }
+
+    pub fn central_bank(&self) -> &String {
+        &self.central_bank
+    }
+
+    pub fn total_supply(&self) -> Amount {
+        self.total_supply
+    }
+
+    pub fn total_burned(&self) -> Amount {
+        self.total_burned
+    }
+
+    // Comprueba sin modificar nada que `amount` puede quemarse.
+    pub fn can_burn(&self, amount: Amount) -> bool {
+        amount <= self.total_supply
+    }
+
+    /// Retira monedas de la circulación, p. ej. la comisión base de cada transferencia.
+    pub fn burn(&mut self, amount: Amount) -> Result<(), CoinIssueError> {
+        self.total_supply = self.total_supply.checked_sub(amount).map_err(|_| CoinIssueError::BurnExceedsSupply)?;
+        self.total_burned = self.total_burned.saturating_add(amount);
+        Ok(())
     }
 
-    fn recalculate_reward(&mut self) {
-        self.reward_per_block = self.reward_per_block + (self.total_supply * self.inflation_rate / 100);
+    pub fn next_issue_nonce(&self) -> u64 {
+        self.issue_nonce
     }
 
+    /// Emite `amount`, que debe ser exactamente `next_emission`. Si falla no modifica nada.
+    #[allow(clippy::too_many_arguments)]
     pub fn issue_coins(
         &mut self,
         transaction_id: String,
+        nonce: u64,
+        amount: Amount,
+        roles: &Roles,
         requesting_address: &String,
-    ) -> Result<u64, CoinIssueError> {
-        let start_time = Instant::now(); 
-
-        if !self.can_issue_coins(requesting_address) {
+        signed_data: &String,
+        signatures: &[AccountSignature],
+    ) -> Result<Amount, CoinIssueError> {
+        if !self.can_issue_coins(roles, requesting_address) {
             return Err(CoinIssueError::NotAuthorized);
         }
 
-        if !self.update_and_verify_hash(&transaction_id) {
-            return Err(CoinIssueError::IntegrityViolation);
+        if !self.central_bank_policy.is_satisfied(signed_data, signatures) {
+            return Err(CoinIssueError::NotAuthorized);
         }
 
-        if self.emitted_transactions.contains(&transaction_id) {
-            return Err(CoinIssueError::TransactionAlreadyProcessed);
+        if nonce < self.issue_nonce {
+            return Err(CoinIssueError::StaleNonce);
+        }
+        if nonce > self.issue_nonce {
+            return Err(CoinIssueError::NonceGap);
         }
 
-        self.recalculate_reward();
-
-        let emission = self.reward_per_block;
-        if self.total_supply + emission > self.max_supply {
+        // Todas las comprobaciones van antes de la primera escritura.
+        let emission = self.recalculate_reward()?;
+        if amount != emission {
+            return Err(CoinIssueError::AmountMismatch);
+        }
+        let new_supply = self.total_supply.checked_add(emission).map_err(|_| CoinIssueError::MaxSupplyReached)?;
+        if new_supply > self.max_supply {
             return Err(CoinIssueError::MaxSupplyReached);
         }
+        let new_hash = self.next_hash(&transaction_id).ok_or(CoinIssueError::IntegrityViolation)?;
 
-        self.total_supply += emission;
-        self.emitted_transactions.insert(transaction_id);
-
-        let elapsed_time = start_time.elapsed(); 
-        println!("Tiempo tomado para emitir monedas: {:?}", elapsed_time);
+        self.accounts_hash = new_hash;
+        self.reward_per_block = emission;
+        self.total_supply = new_supply;
+        self.issue_nonce += 1;
 
         Ok(emission)
     }
+
+    // Writes the issuance state (nonce, supply, burned total and hash chain) to the store.
+    pub fn persist(&self, store: &mut dyn StateStore) -> Result<(), StorageError> {
+        let value = serde_json::to_vec(self).map_err(|_| StorageError::Serialization)?;
+        store.put(COIN_ISSUE_KEY, value);
+        Ok(())
+    }
+
+    // Restores the issuance state, or None if none was ever committed.
+    pub fn restore(store: &dyn StateStore) -> Result<Option<Self>, StorageError> {
+        match store.get(COIN_ISSUE_KEY) {
+            Some(value) => serde_json::from_slice(&value).map(Some).map_err(|_| StorageError::Serialization),
+            None => Ok(None),
+        }
+    }
 }
@@ -1,14 +1,50 @@
 use std::collections::{HashMap, HashSet};
 use std::time::{SystemTime, UNIX_EPOCH};
-use sha2::{Sha256, Digest};
-use crate::validate_transaction::ValidationError;
-use secp256k1::{Secp256k1, Message, PublicKey};
+use crate::validate_transaction::ValidateTransaction;
+use crate::security::Security;
+use crate::accounts::AccountSignature;
+use crate::address;
+use crate::amount::Amount;
+use crate::assets::AssetId;
+use crate::encoding;
+use crate::events::{BlockRejectionReason, EventBus, LedgerEvent};
+use crate::storage::{StateStore, StorageError};
 
+// Prefijo de las claves de bloques en el almacenamiento
+const BLOCK_KEY_PREFIX: &str = "block/";
+
+// Issuances carry the issue nonce; every other kind the sender's.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum TransactionKind {
+    Transfer,
+    Issuance,
+    NftMint { metadata_hash: [u8; 32] }, // `asset` is the item id; the minter is both sender and recipient.
+    NftTransfer, // `asset` is the item id.
+    VestingGrant { start: u64, cliff: u64, duration: u64 }, // `amount` is locked for `to` and released on this schedule.
+    AssetRegistration { symbol: String, decimals: u8, supply_cap: Option<Amount> }, // `asset` is the new asset, `to` its issuer.
+    AssetIssuance, // Signed by the asset's issuer with its account nonce.
+}
+
+impl TransactionKind {
+    // Kind whose nonce sequence this kind consumes.
+    pub fn nonce_domain(&self) -> TransactionKind {
+        match self {
+            TransactionKind::Issuance => TransactionKind::Issuance,
+            _ => TransactionKind::Transfer,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Transaction {
+    pub kind: TransactionKind,
     pub from: String,
     pub to: String,
-    pub amount: u64,
-    pub signature: String,
+    pub amount: Amount,
+    pub asset: AssetId,
+    pub tip: Amount, // Prioridad pagada al productor del bloque
+    pub nonce: u64,
+    pub signatures: Vec<AccountSignature>,
 }
 
 pub struct Block {
@@ -16,14 +52,21 @@ pub struct Block {
     pub timestamp: u64,
     pub prev_block_hash: String,
     pub block_hash: String,
+    pub state_root: String, // Hex root of the account, asset and NFT state after this block.
+    pub base_fee: Amount, // Base fee burned by every transfer in this block.
+    pub producer: String, // Account credited with the tips.
     pub transactions: Vec<Transaction>,
 }
 
 pub enum ConsensusError {
     DuplicateTransaction,
+    InvalidSignature,
+    InvalidAddress,
     BlockValidationError(BlockValidationError),
+    BlockRejectedByNodes,
 }
 
+#[derive(PartialEq, Eq, Hash)]
 pub struct Node {
     id: String,
     is_validating: bool, // Si el nodo actualmente está validando
@@ -36,22 +79,35 @@ pub enum BlockValidationError {
     IncorrectBlockHash,
 }
 
+/// A validated block that is not yet part of the chain, see `Consensus::propose_block`.
+pub struct ProposedBlock {
+    pub block: Block,
+    next_nonces: HashMap<(TransactionKind, String), u64>, // Nonces expected after this block, per kind and sender
+}
+
 pub struct Consensus {
     pub blockchain: Vec<Block>,
     pub hash_prefix: String,
-    processed_transactions: HashSet<String>,
+    account_nonces: HashMap<(TransactionKind, String), u64>, // Lowest nonce still unused, per kind and sender
     nodes: HashSet<Node>,
 }
 
+impl Node {
+    pub fn new(id: String, is_validating: bool) -> Self {
+        Node { id, is_validating }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
 impl Consensus {
-    // Nuevo campo para mantener un registro de nodos
-    nodes: HashSet<Node>,
-    
     pub fn new(hash_prefix: String) -> Self {
         let mut consensus = Consensus {
             blockchain: Vec::new(),
             hash_prefix,
-            processed_transactions: HashSet::new(),
+            account_nonces: HashMap::new(),
             nodes: HashSet::new(),
         };
 
@@ -59,6 +115,68 @@ impl Consensus {
         consensus
     }
 
+    // Bloque inicial de la cadena; enlaza con `hash_prefix` para distinguir redes.
+    fn create_genesis_block(&mut self) {
+        let mut genesis = Block {
+            index: 0,
+            timestamp: 0,
+            prev_block_hash: self.hash_prefix.clone(),
+            block_hash: String::new(),
+            state_root: String::new(),
+            base_fee: Amount::ZERO,
+            producer: String::new(),
+            transactions: Vec::new(),
+        };
+        genesis.block_hash = self.calculate_block_hash(&genesis);
+        self.blockchain.push(genesis);
+    }
+
+    // Restores the chain from the store, or starts a new one from genesis if it is empty.
+    pub fn restore(hash_prefix: String, store: &dyn StateStore) -> Result<Self, StorageError> {
+        let keys = store.keys_with_prefix(BLOCK_KEY_PREFIX);
+        if keys.is_empty() {
+            return Ok(Consensus::new(hash_prefix));
+        }
+
+        let mut consensus = Consensus {
+            blockchain: Vec::new(),
+            hash_prefix,
+            account_nonces: HashMap::new(),
+            nodes: HashSet::new(),
+        };
+        // Keys are zero-padded, so the store returns them in chain order.
+        for key in keys {
+            let value = store.get(&key).ok_or(StorageError::Corrupted)?;
+            let block = encoding::decode_block(&value).map_err(|_| StorageError::Serialization)?;
+            for tx in &block.transactions {
+                consensus.account_nonces.insert((tx.kind.nonce_domain(), tx.from.clone()), tx.nonce + 1);
+            }
+            consensus.blockchain.push(block);
+        }
+        Ok(consensus)
+    }
+
+    // Writes the blocks that are not yet in the store.
+    pub fn persist(&self, store: &mut dyn StateStore) -> Result<(), StorageError> {
+        for block in &self.blockchain {
+            Self::persist_block(block, store);
+        }
+        Ok(())
+    }
+
+    // Writes `block` unless the store already holds it, e.g. a proposed block before it is appended.
+    pub fn persist_block(block: &Block, store: &mut dyn StateStore) {
+        let key = format!("{}{:020}", BLOCK_KEY_PREFIX, block.index);
+        if store.get(&key).is_none() {
+            store.put(&key, encoding::encode_block(block));
+        }
+    }
+
+    // Height of the last block in the chain.
+    pub fn height(&self) -> u64 {
+        self.blockchain.last().map(|block| block.index).unwrap_or(0)
+    }
+
     // Función para registrar un nuevo nodo en la red
     pub fn register_node(&mut self, node: Node) {
         self.nodes.insert(node);
@@ -74,100 +192,116 @@ impl Consensus {
         }
         // Considerando el caso de bajo número de nodos.
         if self.nodes.len() < 3 {
-            return votes_for == self.nodes.len();
+            votes_for == self.nodes.len()
         } else {
-            return votes_for > (self.nodes.len() / 2);
+            votes_for > (self.nodes.len() / 2)
         }
     }
 
     fn validate_block_for_node(&self, _node: &Node, block: &Block) -> bool {
         // Aquí puedes agregar lógica específica de validación por nodo, por ahora simplemente reutilizamos la función validate_block
-        self.validate_block(block)
+        self.validate_block(block).is_ok()
     }
 
-    pub fn implement_poh(&mut self, transactions: Vec<Transaction>) -> Result<(), ConsensusError> {
+    pub fn implement_poh(&mut self, transactions: Vec<Transaction>, state_root: String, base_fee: Amount, producer: String, events: &mut EventBus) -> Result<(), ConsensusError> {
+        let proposed = self.propose_block(transactions, state_root, base_fee, producer, events)?;
+        self.append_block(proposed);
+        Ok(())
+    }
+
+    /// Validates `transactions` and seals them into the next block without adding it to
+    /// the chain, so the caller can persist it first and append it with `append_block`.
+    pub fn propose_block(&self, transactions: Vec<Transaction>, state_root: String, base_fee: Amount, producer: String, events: &mut EventBus) -> Result<ProposedBlock, ConsensusError> {
+        // Nonces expected after this block, per kind and sender
+        let mut next_nonces: HashMap<(TransactionKind, String), u64> = HashMap::new();
         for tx in &transactions {
+            // Direcciones mal formadas o con checksum incorrecto
+            if address::validate_address(&tx.from).is_err() || address::validate_address(&tx.to).is_err() {
+                events.publish(LedgerEvent::BlockRejected { reason: BlockRejectionReason::InvalidAddress });
+                return Err(ConsensusError::InvalidAddress);
+            }
+
             // Verificación de la firma de la transacción
             if !self.verify_transaction_signature(tx) {
-                self.notify("Invalid transaction signature detected");
-                return Err(ConsensusError::DuplicateTransaction);
+                events.publish(LedgerEvent::BlockRejected { reason: BlockRejectionReason::InvalidSignature });
+                return Err(ConsensusError::InvalidSignature);
             }
-            
-            let tx_id = self.calculate_transaction_id(tx);
-            if self.processed_transactions.contains(&tx_id) {
-                self.notify("Duplicate transaction detected");
+
+            let domain = (tx.kind.nonce_domain(), tx.from.clone());
+            let expected = next_nonces
+                .get(&domain)
+                .or_else(|| self.account_nonces.get(&domain))
+                .copied()
+                .unwrap_or(0);
+            // Nonces are also consumed by operations that never reach a block (key rotation,
+            // recovery, asset issuance), so gaps are allowed here; only reuse is rejected.
+            // `ValidateTransaction` enforces the exact sequence against account state.
+            if tx.nonce < expected {
+                events.publish(LedgerEvent::BlockRejected { reason: BlockRejectionReason::DuplicateTransaction });
                 return Err(ConsensusError::DuplicateTransaction);
             }
+            next_nonces.insert(domain, tx.nonce + 1);
         }
 
-        if !self.validate_block(&new_block).is_ok() {
-            self.notify("Block validation failed");
-            return Err(ConsensusError::BlockValidationError(self.validate_block(&new_block).unwrap_err()));
-        }
-
-        let new_block = self.construct_new_block(transactions)?;
+        let new_block = self.construct_new_block(transactions, state_root, base_fee, producer)?;
 
         // Antes de validar el bloque localmente, pedimos a los nodos que voten
         if !self.nodes_vote_on_block(&new_block) {
-            self.notify("Block rejected by network nodes");
-            return Err(ConsensusError::BlockValidationError);
+            events.publish(LedgerEvent::BlockRejected { reason: BlockRejectionReason::RejectedByNodes });
+            return Err(ConsensusError::BlockRejectedByNodes);
         }
 
-        if !self.validate_block(&new_block) {
-            self.notify("Block validation failed");
-            return Err(ConsensusError::BlockValidationError);
+        if let Err(e) = self.validate_block(&new_block) {
+            events.publish(LedgerEvent::BlockRejected { reason: BlockRejectionReason::ValidationFailed });
+            return Err(ConsensusError::BlockValidationError(e));
         }
 
-        
-        let validation_result = self.validate_block(&new_block);
-        
-        // Almacenar el resultado de la validación en una variable.
-        if !validation_result.is_ok() {
-            self.notify("Block validation failed");
-            return Err(ConsensusError::BlockValidationError(validation_result.unwrap_err()));
-        }
-
-        // Si el bloque es válido, añadirlo a la cadena de bloques y actualizar las transacciones procesadas
-        self.update_blockchain(new_block);
-        self.notify("Block successfully added to the blockchain");
+        Ok(ProposedBlock { block: new_block, next_nonces })
+    }
 
-        Ok(())
+    // Si el bloque es válido, añadirlo a la cadena de bloques y actualizar los nonces procesados
+    pub fn append_block(&mut self, proposed: ProposedBlock) {
+        self.blockchain.push(proposed.block);
+        self.account_nonces.extend(proposed.next_nonces);
     }
 
-    fn calculate_block_hash(&self, block: &Block) -> String {
-        let input = format!("{}|{}|{}|{:?}", block.index, block.timestamp, block.prev_block_hash, block.transactions);
-        let mut hasher = Sha256::new();
-        hasher.update(input);
-        format!("{:x}", hasher.finalize())
+    fn construct_new_block(&self, transactions: Vec<Transaction>, state_root: String, base_fee: Amount, producer: String) -> Result<Block, ConsensusError> {
+        let prev_block = self.blockchain.last().ok_or(ConsensusError::BlockValidationError(BlockValidationError::MismatchedPreviousHash))?;
+        // Timestamps must strictly increase, even for two blocks sealed in the same second.
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let mut block = Block {
+            index: prev_block.index + 1,
+            timestamp: now.max(prev_block.timestamp + 1),
+            prev_block_hash: prev_block.block_hash.clone(),
+            block_hash: String::new(),
+            state_root,
+            base_fee,
+            producer,
+            transactions,
+        };
+        block.block_hash = self.calculate_block_hash(&block);
+        Ok(block)
     }
 
-    fn calculate_transaction_id(&self, tx: &Transaction) -> String {
-        let input = format!("{}|{}|{}|{}", tx.from, tx.to, tx.amount, tx.signature);
-        let mut hasher = Sha256::new();
-        hasher.update(input);
-        format!("{:x}", hasher.finalize())
+    fn calculate_block_hash(&self, block: &Block) -> String {
+        encoding::block_hash(block)
     }
 
     fn verify_transaction_signature(&self, tx: &Transaction) -> bool {
-        // Crear una instancia del contexto de secp256k1
-        let secp = Secp256k1::verification_only();
+        let message_data = ValidateTransaction::signing_data(&tx.kind, &tx.from, &tx.to, &tx.asset, tx.amount, tx.tip, tx.nonce);
 
-        // Construir el mensaje que se firmó originalmente. 
-        // Puede variar según cómo se haya estructurado la firma en tu implementación.
-        let message_data = format!("{}{}{}", tx.from, tx.to, tx.amount);
-        let message = Message::from_slice(&sha256(message_data)).expect("32 bytes");
-
-        // Convertir la firma y la clave pública de la transacción a estructuras manejables por la biblioteca.
-        let signature = secp256k1::Signature::from_compact(&tx.signature.as_bytes()).expect("Signature parsing");
-        let pubkey_bytes = hex::decode(&tx.from).expect("Decoding pubkey"); // Asumiendo que 'from' contiene la clave pública en formato hex
-        let pubkey = PublicKey::from_slice(&pubkey_bytes).expect("Public key parsing");
-
-        // Verificar la firma
-        secp.verify(&message, &signature, &pubkey).is_ok()
+        // Every attached signature must be valid for the key it names. Whether they meet the
+        // sender's threshold was already checked by `ValidateTransaction` against account state.
+        if tx.signatures.is_empty() {
+            return false;
+        }
+        tx.signatures.iter().all(|account_signature| {
+            Security::verify_account_signature(account_signature, &message_data)
+        })
     }
 
     fn validate_block(&self, block: &Block) -> Result<(), BlockValidationError> {
-        let prev_block = self.blockchain.last().unwrap();
+        let prev_block = self.blockchain.last().ok_or(BlockValidationError::MismatchedPreviousHash)?;
 
         if block.timestamp <= prev_block.timestamp {
             return Err(BlockValidationError::InvalidTimestamp);
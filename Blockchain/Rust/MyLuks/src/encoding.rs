@@ -0,0 +1,586 @@
+use sha2::{Sha256, Digest};
+use crate::accounts::{AccountSignature, PublicKey};
+use crate::amount::Amount;
+use crate::assets::AssetId;
+use crate::concensus::{Block, Transaction, TransactionKind};
+use crate::signature_scheme::SchemeId;
+
+/// Version of the canonical encoding, the first byte of every encoded message.
+///
+/// Ids, signed messages and stored blocks are all built from this encoding, never from
+/// `format!` output. Integers are big-endian and strings carry a `u32` length prefix; the
+/// second byte is a tag naming what is encoded, so a signature over one kind of message
+/// cannot be passed off as another. The layout and test vectors are in `ENCODING.md`.
+pub const ENCODING_VERSION: u8 = 3;
+
+// Kinds of transaction.
+const KIND_TRANSFER: u8 = 0x00;
+const KIND_ISSUANCE: u8 = 0x01;
+const KIND_NFT_MINT: u8 = 0x02;
+const KIND_NFT_TRANSFER: u8 = 0x03;
+const KIND_VESTING_GRANT: u8 = 0x04;
+const KIND_ASSET_REGISTRATION: u8 = 0x05;
+const KIND_ASSET_ISSUANCE: u8 = 0x06;
+
+// Tags of the encoded messages.
+const TAG_TRANSACTION: u8 = 0x01;
+const TAG_SIGNING_PAYLOAD: u8 = 0x02;
+const TAG_BLOCK: u8 = 0x03;
+const TAG_BLOCK_HEADER: u8 = 0x04;
+const TAG_AUTHORIZATION: u8 = 0x05;
+
+pub enum EncodingError {
+    UnsupportedVersion(u8),
+    UnexpectedTag(u8),
+    UnknownScheme(u8),
+    UnknownKind(u8),
+    InvalidFlag(u8), // Presence byte of an optional field other than 0 or 1.
+    UnexpectedEnd,
+    InvalidUtf8,
+    TrailingBytes,
+}
+
+/// Bytes a sender signs: every field of the transaction except the signatures.
+pub fn encode_signing_payload(kind: &TransactionKind, from: &str, to: &str, asset: &AssetId, amount: Amount, tip: Amount, nonce: u64) -> Vec<u8> {
+    let mut out = vec![ENCODING_VERSION, TAG_SIGNING_PAYLOAD];
+    put_transfer_fields(&mut out, kind, from, to, asset, amount, tip, nonce);
+    out
+}
+
+pub fn encode_transaction(tx: &Transaction) -> Vec<u8> {
+    let mut out = vec![ENCODING_VERSION, TAG_TRANSACTION];
+    put_transfer_fields(&mut out, &tx.kind, &tx.from, &tx.to, &tx.asset, tx.amount, tx.tip, tx.nonce);
+    put_u32(&mut out, tx.signatures.len() as u32);
+    for signature in &tx.signatures {
+        out.push(signature.scheme.tag());
+        put_bytes(&mut out, &signature.public_key.value);
+        put_bytes(&mut out, signature.signature.as_bytes());
+    }
+    out
+}
+
+/// Hex SHA-256 of the signing payload. Signatures are left out, so a signature encoded
+/// differently (another hex case, the other S) cannot give the same transaction a new id.
+pub fn transaction_id(tx: &Transaction) -> String {
+    signing_payload_id(&tx.kind, &tx.from, &tx.to, &tx.asset, tx.amount, tx.tip, tx.nonce)
+}
+
+pub fn signing_payload_id(kind: &TransactionKind, from: &str, to: &str, asset: &AssetId, amount: Amount, tip: Amount, nonce: u64) -> String {
+    hex::encode(Sha256::digest(encode_signing_payload(kind, from, to, asset, amount, tip, nonce)))
+}
+
+/// Operations authorised by a signature that are not block transactions. The value is the
+/// action byte of the authorization payload.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Action {
+    CloseAccount = 0x01,
+    RotateKey = 0x02,
+    SetRecoveryKey = 0x03,
+    DeleteAccount = 0x05,
+    AcceptOwnership = 0x06,
+    RegisterWebhook = 0x07,
+    RemoveWebhook = 0x08,
+    SetGuardians = 0x09,
+    ApproveRecovery = 0x0a,
+    CancelRecovery = 0x0b,
+    GrantRole = 0x0c,
+    RevokeRole = 0x0d,
+    LockAccount = 0x0e,
+    UnlockAccount = 0x0f,
+    SetFeePolicy = 0x10,
+    ViewHistory = 0x12,
+    ProposeOwnership = 0x13,
+    CancelOwnership = 0x14,
+    SetOwnershipDelay = 0x15,
+    FinalizeRecovery = 0x16,
+}
+
+/// Bytes signed to authorise an `Action`: version, tag `0x05`, the action byte, then the
+/// fields of the action in the order they are added.
+pub struct AuthorizationPayload {
+    out: Vec<u8>,
+}
+
+impl AuthorizationPayload {
+    pub fn new(action: Action) -> Self {
+        AuthorizationPayload { out: vec![ENCODING_VERSION, TAG_AUTHORIZATION, action as u8] }
+    }
+
+    pub fn string(mut self, value: &str) -> Self {
+        put_string(&mut self.out, value);
+        self
+    }
+
+    pub fn u64(mut self, value: u64) -> Self {
+        put_u64(&mut self.out, value);
+        self
+    }
+
+    // None and Some("") encode differently.
+    pub fn optional_string(mut self, value: Option<&String>) -> Self {
+        match value {
+            Some(value) => {
+                self.out.push(1);
+                put_string(&mut self.out, value);
+            }
+            None => self.out.push(0),
+        }
+        self
+    }
+
+    pub fn optional_u64(mut self, value: Option<u64>) -> Self {
+        match value {
+            Some(value) => {
+                self.out.push(1);
+                put_u64(&mut self.out, value);
+            }
+            None => self.out.push(0),
+        }
+        self
+    }
+
+    pub fn strings(mut self, values: &[String]) -> Self {
+        put_u32(&mut self.out, values.len() as u32);
+        for value in values {
+            put_string(&mut self.out, value);
+        }
+        self
+    }
+
+    pub fn key(mut self, key: &PublicKey) -> Self {
+        self.out.push(key.scheme.tag());
+        put_bytes(&mut self.out, &key.value);
+        self
+    }
+
+    /// What signers sign: the lowercase hex of the payload, as for transactions.
+    pub fn signing_data(&self) -> String {
+        hex::encode(&self.out)
+    }
+
+    // Hex SHA-256 of the payload, used as the hash of the operation in the history.
+    pub fn id(&self) -> String {
+        hex::encode(Sha256::digest(&self.out))
+    }
+}
+
+// Fields of a block covered by its hash, that is all of them but the hash itself.
+fn encode_block_header(block: &Block) -> Vec<u8> {
+    let mut out = vec![ENCODING_VERSION, TAG_BLOCK_HEADER];
+    put_block_fields(&mut out, block);
+    out
+}
+
+/// Hex SHA-256 of the block header encoding.
+pub fn block_hash(block: &Block) -> String {
+    hex::encode(Sha256::digest(encode_block_header(block)))
+}
+
+/// Full block, hash included, as sent between nodes and written to the store.
+pub fn encode_block(block: &Block) -> Vec<u8> {
+    let mut out = vec![ENCODING_VERSION, TAG_BLOCK];
+    put_block_fields(&mut out, block);
+    put_string(&mut out, &block.block_hash);
+    out
+}
+
+pub fn decode_transaction(bytes: &[u8]) -> Result<Transaction, EncodingError> {
+    let mut reader = Reader::new(bytes);
+    reader.expect_header(TAG_TRANSACTION)?;
+    let tx = reader.transaction()?;
+    reader.finish()?;
+    Ok(tx)
+}
+
+pub fn decode_block(bytes: &[u8]) -> Result<Block, EncodingError> {
+    let mut reader = Reader::new(bytes);
+    reader.expect_header(TAG_BLOCK)?;
+    let index = reader.u64()?;
+    let timestamp = reader.u64()?;
+    let prev_block_hash = reader.string()?;
+    let state_root = reader.string()?;
+    let base_fee = Amount::from_units(reader.u64()?);
+    let producer = reader.string()?;
+    let count = reader.u32()?;
+    let mut transactions = Vec::new();
+    for _ in 0..count {
+        let encoded = reader.bytes()?;
+        transactions.push(decode_transaction(encoded)?);
+    }
+    let block_hash = reader.string()?;
+    reader.finish()?;
+    Ok(Block { index, timestamp, prev_block_hash, block_hash, state_root, base_fee, producer, transactions })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn put_transfer_fields(out: &mut Vec<u8>, kind: &TransactionKind, from: &str, to: &str, asset: &AssetId, amount: Amount, tip: Amount, nonce: u64) {
+    match kind {
+        TransactionKind::Transfer => out.push(KIND_TRANSFER),
+        TransactionKind::Issuance => out.push(KIND_ISSUANCE),
+        TransactionKind::NftMint { metadata_hash } => {
+            out.push(KIND_NFT_MINT);
+            out.extend_from_slice(metadata_hash);
+        }
+        TransactionKind::NftTransfer => out.push(KIND_NFT_TRANSFER),
+        TransactionKind::VestingGrant { start, cliff, duration } => {
+            out.push(KIND_VESTING_GRANT);
+            put_u64(out, *start);
+            put_u64(out, *cliff);
+            put_u64(out, *duration);
+        }
+        TransactionKind::AssetRegistration { symbol, decimals, supply_cap } => {
+            out.push(KIND_ASSET_REGISTRATION);
+            put_string(out, symbol);
+            out.push(*decimals);
+            match supply_cap {
+                Some(cap) => {
+                    out.push(1);
+                    put_u64(out, cap.units());
+                }
+                None => out.push(0),
+            }
+        }
+        TransactionKind::AssetIssuance => out.push(KIND_ASSET_ISSUANCE),
+    }
+    put_string(out, from);
+    put_string(out, to);
+    put_string(out, asset);
+    put_u64(out, amount.units());
+    put_u64(out, tip.units());
+    put_u64(out, nonce);
+}
+
+fn put_block_fields(out: &mut Vec<u8>, block: &Block) {
+    put_u64(out, block.index);
+    put_u64(out, block.timestamp);
+    put_string(out, &block.prev_block_hash);
+    put_string(out, &block.state_root);
+    put_u64(out, block.base_fee.units());
+    put_string(out, &block.producer);
+    put_u32(out, block.transactions.len() as u32);
+    for tx in &block.transactions {
+        put_bytes(out, &encode_transaction(tx));
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_bytes(out: &mut Vec<u8>, value: &[u8]) {
+    put_u32(out, value.len() as u32);
+    out.extend_from_slice(value);
+}
+
+fn put_string(out: &mut Vec<u8>, value: &str) {
+    put_bytes(out, value.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, position: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], EncodingError> {
+        let end = self.position.checked_add(len).ok_or(EncodingError::UnexpectedEnd)?;
+        let slice = self.bytes.get(self.position..end).ok_or(EncodingError::UnexpectedEnd)?;
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, EncodingError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, EncodingError> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, EncodingError> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], EncodingError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String, EncodingError> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| EncodingError::InvalidUtf8)
+    }
+
+    fn expect_header(&mut self, tag: u8) -> Result<(), EncodingError> {
+        let version = self.u8()?;
+        if version != ENCODING_VERSION {
+            return Err(EncodingError::UnsupportedVersion(version));
+        }
+        let found = self.u8()?;
+        if found != tag {
+            return Err(EncodingError::UnexpectedTag(found));
+        }
+        Ok(())
+    }
+
+    fn transaction(&mut self) -> Result<Transaction, EncodingError> {
+        let kind = match self.u8()? {
+            KIND_TRANSFER => TransactionKind::Transfer,
+            KIND_ISSUANCE => TransactionKind::Issuance,
+            KIND_NFT_MINT => {
+                let mut metadata_hash = [0u8; 32];
+                metadata_hash.copy_from_slice(self.take(32)?);
+                TransactionKind::NftMint { metadata_hash }
+            }
+            KIND_NFT_TRANSFER => TransactionKind::NftTransfer,
+            KIND_VESTING_GRANT => TransactionKind::VestingGrant { start: self.u64()?, cliff: self.u64()?, duration: self.u64()? },
+            KIND_ASSET_REGISTRATION => {
+                let symbol = self.string()?;
+                let decimals = self.u8()?;
+                let supply_cap = match self.u8()? {
+                    0 => None,
+                    1 => Some(Amount::from_units(self.u64()?)),
+                    other => return Err(EncodingError::InvalidFlag(other)),
+                };
+                TransactionKind::AssetRegistration { symbol, decimals, supply_cap }
+            }
+            KIND_ASSET_ISSUANCE => TransactionKind::AssetIssuance,
+            other => return Err(EncodingError::UnknownKind(other)),
+        };
+        let from = self.string()?;
+        let to = self.string()?;
+        let asset = self.string()?;
+        let amount = Amount::from_units(self.u64()?);
+        let tip = Amount::from_units(self.u64()?);
+        let nonce = self.u64()?;
+        let count = self.u32()?;
+        let mut signatures = Vec::new();
+        for _ in 0..count {
+            let tag = self.u8()?;
+            let scheme = SchemeId::from_tag(tag).ok_or(EncodingError::UnknownScheme(tag))?;
+            let public_key = PublicKey { value: self.bytes()?.to_vec(), scheme };
+            let signature = self.string()?;
+            signatures.push(AccountSignature { public_key, signature, scheme });
+        }
+        Ok(Transaction { kind, from, to, amount, asset, tip, nonce, signatures })
+    }
+
+    // Every byte must be consumed: one value has exactly one encoding.
+    fn finish(&self) -> Result<(), EncodingError> {
+        if self.position != self.bytes.len() {
+            return Err(EncodingError::TrailingBytes);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Vectores de ENCODING.md
+    const A: &str = "luks1qyqszqgpqyqszqgpqyqszqgpqyqszqgpjfq0x8";
+    const B: &str = "luks1qgpqyqszqgpqyqszqgpqyqszqgpqyqszrdx2d3";
+    const C: &str = "luks1qvpsxqcrqvpsxqcrqvpsxqcrqvpsxqcrza8t8s";
+
+    const SIGNING_PAYLOAD: &str = concat!(
+        "0302000000002b6c756b7331717971737a716770717971737a71677071797173",
+        "7a716770717971737a7167706a66713078380000002b6c756b73317167707179",
+        "71737a716770717971737a716770717971737a716770717971737a7264783264",
+        "33000000044c554b530000000008f0d18000000000000003e800000000000000",
+        "07",
+    );
+    const TRANSACTION: &str = concat!(
+        "0301000000002b6c756b7331717971737a716770717971737a71677071797173",
+        "7a716770717971737a7167706a66713078380000002b6c756b73317167707179",
+        "71737a716770717971737a716770717971737a716770717971737a7264783264",
+        "33000000044c554b530000000008f0d18000000000000003e800000000000000",
+        "0700000001010000002102111111111111111111111111111111111111111111",
+        "1111111111111111111111000000806161616161616161616161616161616161",
+        "6161616161616161616161616161616161616161616161616161616161616161",
+        "6161616161616161616161616161616161616161616161616161616161616161",
+        "6161616161616161616161616161616161616161616161616161616161616161",
+        "616161616161616161616161616161",
+    );
+    const TRANSACTION_ID: &str = "efe60981f610cf2e4c2f4c9a42e366b973214cab87747692e14b59dd3715690d";
+    const ISSUANCE_ID: &str = "823725c94c6d4c08352565a901309703abfcec62e1ccaecd242e005e5324c042";
+    const BLOCK: &str = concat!(
+        "03030000000000000001000000006553f1000000004030303030303030303030",
+        "3030303030303030303030303030303030303030303030303030303030303030",
+        "3030303030303030303030303030303030303030303000000040313131313131",
+        "3131313131313131313131313131313131313131313131313131313131313131",
+        "3131313131313131313131313131313131313131313131313131000000000000",
+        "03e80000002b6c756b7331717670737871637271767073787163727176707378",
+        "71637271767073787163727a6138743873000000010000012f0301000000002b",
+        "6c756b7331717971737a716770717971737a716770717971737a716770717971",
+        "737a7167706a66713078380000002b6c756b7331716770717971737a71677071",
+        "7971737a716770717971737a716770717971737a726478326433000000044c55",
+        "4b530000000008f0d18000000000000003e80000000000000007000000010100",
+        "0000210211111111111111111111111111111111111111111111111111111111",
+        "1111111100000080616161616161616161616161616161616161616161616161",
+        "6161616161616161616161616161616161616161616161616161616161616161",
+        "6161616161616161616161616161616161616161616161616161616161616161",
+        "6161616161616161616161616161616161616161616161616161616161616161",
+        "6161616161616161000000403538646630313234636534313263633330666133",
+        "6261636235313537303262663831636230303864633137366636336233333262",
+        "373339636632346461663230",
+    );
+    const BLOCK_HASH: &str = "58df0124ce412cc30fa3bacb515702bf81cb008dc176f63b332b739cf24daf20";
+    const AUTHORIZATION: &str = concat!(
+        "0305020000002b6c756b7331717971737a716770717971737a71677071797173",
+        "7a716770717971737a7167706a66713078380100000021021111111111111111",
+        "1111111111111111111111111111111111111111111111110000000000000007",
+    );
+    const AUTHORIZATION_ID: &str = "5923674f199f50aecb459dc33fc423f40d25bdd79053d52fcb72b9a7864739aa";
+
+    fn vector_key() -> PublicKey {
+        let mut value = vec![0x02];
+        value.extend_from_slice(&[0x11; 32]);
+        PublicKey { value, scheme: SchemeId::Secp256k1 }
+    }
+
+    fn vector_transaction() -> Transaction {
+        Transaction {
+            kind: TransactionKind::Transfer,
+            from: A.to_string(),
+            to: B.to_string(),
+            amount: Amount::from_units(150_000_000),
+            asset: "LUKS".to_string(),
+            tip: Amount::from_units(1000),
+            nonce: 7,
+            signatures: vec![AccountSignature {
+                public_key: vector_key(),
+                signature: "aa".repeat(64),
+                scheme: SchemeId::Secp256k1,
+            }],
+        }
+    }
+
+    fn vector_block() -> Block {
+        let mut block = Block {
+            index: 1,
+            timestamp: 1_700_000_000,
+            prev_block_hash: "00".repeat(32),
+            block_hash: String::new(),
+            state_root: "11".repeat(32),
+            base_fee: Amount::from_units(1000),
+            producer: C.to_string(),
+            transactions: vec![vector_transaction()],
+        };
+        block.block_hash = block_hash(&block);
+        block
+    }
+
+    #[test]
+    fn signing_payload_matches_vector() {
+        let tx = vector_transaction();
+        let bytes = encode_signing_payload(&tx.kind, &tx.from, &tx.to, &tx.asset, tx.amount, tx.tip, tx.nonce);
+        assert_eq!(hex::encode(bytes), SIGNING_PAYLOAD);
+    }
+
+    #[test]
+    fn transaction_matches_vector() {
+        let tx = vector_transaction();
+        assert_eq!(hex::encode(encode_transaction(&tx)), TRANSACTION);
+        assert_eq!(transaction_id(&tx), TRANSACTION_ID);
+    }
+
+    #[test]
+    fn transaction_id_leaves_out_signatures() {
+        let mut tx = vector_transaction();
+        tx.signatures[0].public_key.scheme = SchemeId::Ed25519;
+        tx.signatures[0].scheme = SchemeId::Ed25519;
+        assert_eq!(transaction_id(&tx), TRANSACTION_ID);
+        tx.signatures.clear();
+        assert_eq!(transaction_id(&tx), TRANSACTION_ID);
+
+        tx.kind = TransactionKind::Issuance;
+        assert_eq!(transaction_id(&tx), ISSUANCE_ID);
+    }
+
+    #[test]
+    fn block_matches_vector() {
+        let block = vector_block();
+        assert_eq!(block.block_hash, BLOCK_HASH);
+        assert_eq!(hex::encode(encode_block(&block)), BLOCK);
+    }
+
+    #[test]
+    fn vectors_decode_back() {
+        let bytes = hex::decode(TRANSACTION).unwrap();
+        let tx = match decode_transaction(&bytes) {
+            Ok(tx) => tx,
+            Err(_) => panic!("vector 2 should decode"),
+        };
+        assert_eq!(encode_transaction(&tx), bytes);
+
+        let bytes = hex::decode(BLOCK).unwrap();
+        let block = match decode_block(&bytes) {
+            Ok(block) => block,
+            Err(_) => panic!("vector 3 should decode"),
+        };
+        assert_eq!(block.block_hash, BLOCK_HASH);
+        assert_eq!(block_hash(&block), BLOCK_HASH);
+        assert_eq!(encode_block(&block), bytes);
+    }
+
+    #[test]
+    fn vesting_grants_keep_their_schedule() {
+        let mut tx = vector_transaction();
+        tx.kind = TransactionKind::VestingGrant { start: 1_700_000_000, cliff: 86_400, duration: 31_536_000 };
+        let bytes = encode_transaction(&tx);
+        let decoded = match decode_transaction(&bytes) {
+            Ok(decoded) => decoded,
+            Err(_) => panic!("vesting grant should decode"),
+        };
+        assert_eq!(decoded.kind, tx.kind);
+        assert_eq!(encode_transaction(&decoded), bytes);
+        assert_ne!(transaction_id(&tx), TRANSACTION_ID);
+    }
+
+    #[test]
+    fn asset_registrations_keep_their_metadata() {
+        let mut tx = vector_transaction();
+        for supply_cap in [None, Some(Amount::from_units(1_000))] {
+            tx.kind = TransactionKind::AssetRegistration { symbol: "GOLD".to_string(), decimals: 2, supply_cap };
+            let bytes = encode_transaction(&tx);
+            assert!(decode_transaction(&bytes).is_ok_and(|decoded| decoded.kind == tx.kind));
+        }
+        // The presence byte of the cap sits right after the symbol and the decimals.
+        let mut bytes = encode_transaction(&tx);
+        let flag = 2 + 1 + 4 + "GOLD".len() + 1;
+        bytes[flag] = 2;
+        assert!(matches!(decode_transaction(&bytes), Err(EncodingError::InvalidFlag(2))));
+    }
+
+    #[test]
+    fn authorization_matches_vector() {
+        let payload = AuthorizationPayload::new(Action::RotateKey).string(A).key(&vector_key()).u64(7);
+        assert_eq!(payload.signing_data(), AUTHORIZATION);
+        assert_eq!(payload.id(), AUTHORIZATION_ID);
+    }
+
+    #[test]
+    fn unknown_versions_and_tags_are_rejected() {
+        for version in [0x01, 0x02, ENCODING_VERSION + 1] {
+            let mut bytes = hex::decode(TRANSACTION).unwrap();
+            bytes[0] = version;
+            assert!(matches!(decode_transaction(&bytes), Err(EncodingError::UnsupportedVersion(v)) if v == version));
+        }
+
+        let bytes = hex::decode(BLOCK).unwrap();
+        assert!(matches!(decode_transaction(&bytes), Err(EncodingError::UnexpectedTag(TAG_BLOCK))));
+    }
+}
@@ -0,0 +1,172 @@
+use crate::amount::Amount;
+use crate::assets::AssetId;
+use crate::nft::ItemId;
+use crate::roles::Role;
+use crate::transfer::TransferType;
+
+// Why the consensus module refused to seal a block.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BlockRejectionReason {
+    InvalidAddress,
+    InvalidSignature,
+    DuplicateTransaction,
+    ValidationFailed,
+    RejectedByNodes,
+}
+
+/// Something that happened on the ledger, published to every subscriber of the `EventBus`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum LedgerEvent {
+    TransferCompleted {
+        from: String,
+        to: String,
+        asset: AssetId,
+        amount: Amount, // Received by `to`.
+        transfer_type: TransferType,
+        transaction_hash: Option<String>, // None for transfers that never reach a block.
+    },
+    FeeCharged {
+        account: String,
+        asset: AssetId,
+        fee: Amount,
+        base_fee: Amount,
+        tip: Amount,
+    },
+    CoinsIssued { to: String, amount: Amount, total_supply: Amount },
+    AssetIssued { asset: AssetId, to: String, amount: Amount },
+    NftMinted { item: ItemId, owner: String, transaction_hash: String },
+    NftTransferred { item: ItemId, from: String, to: String, transaction_hash: String },
+    VestingGranted { grantor: String, beneficiary: String, amount: Amount, transaction_hash: String },
+    AccountLocked { account: String },
+    AccountUnlocked { account: String },
+    AccountClosed { account: String, swept_to: Option<String> },
+    KeyRotated { account: String },
+    RoleGranted { account: String, role: Role },
+    RoleRevoked { account: String, role: Role },
+    OwnershipTransferProposed { owner: String, new_owner: String, accept_after: u64 },
+    OwnershipTransferAccepted { previous_owner: String, new_owner: String },
+    OwnershipTransferCancelled { owner: String, new_owner: String },
+    BlockCommitted { height: u64, block_hash: String, transactions: usize, producer: String },
+    BlockRejected { reason: BlockRejectionReason },
+}
+
+pub type SubscriberId = u64;
+type Handler = Box<dyn FnMut(&LedgerEvent) + Send>;
+
+/// In-process publish/subscribe bus. Subscribers are called synchronously, in the order
+/// they subscribed, with every event published after they subscribed.
+pub struct EventBus {
+    subscribers: Vec<(SubscriberId, Handler)>,
+    next_id: SubscriberId,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        EventBus {
+            subscribers: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn subscribe<F>(&mut self, handler: F) -> SubscriberId
+    where
+        F: FnMut(&LedgerEvent) + Send + 'static,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscribers.push((id, Box::new(handler)));
+        id
+    }
+
+    // Returns false if no subscriber had this id.
+    pub fn unsubscribe(&mut self, id: SubscriberId) -> bool {
+        let before = self.subscribers.len();
+        self.subscribers.retain(|(subscriber, _)| *subscriber != id);
+        self.subscribers.len() != before
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    pub fn publish(&mut self, event: LedgerEvent) {
+        for (_, handler) in self.subscribers.iter_mut() {
+            handler(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::storage::MemoryStore;
+    use crate::testing::{self, ALICE, BOB};
+
+    type Log = Arc<Mutex<Vec<(&'static str, LedgerEvent)>>>;
+
+    fn record(log: &Log, name: &'static str) -> impl FnMut(&LedgerEvent) + Send + 'static {
+        let log = Arc::clone(log);
+        move |event| log.lock().unwrap().push((name, event.clone()))
+    }
+
+    fn kinds(log: &Log) -> Vec<(&'static str, &'static str)> {
+        log.lock().unwrap().iter().map(|(name, event)| {
+            let kind = match event {
+                LedgerEvent::TransferCompleted { .. } => "transfer",
+                LedgerEvent::FeeCharged { .. } => "fee",
+                LedgerEvent::BlockCommitted { .. } => "block",
+                _ => "other",
+            };
+            (*name, kind)
+        }).collect()
+    }
+
+    #[test]
+    fn subscribers_see_events_in_publication_order_until_they_unsubscribe() {
+        let mut ledger = testing::ledger();
+        let bob = testing::address(&BOB);
+        let log: Log = Arc::default();
+        let first = ledger.subscribe(record(&log, "first"));
+        ledger.subscribe(record(&log, "second"));
+
+        assert!(ledger.apply(testing::transfer(&ledger, &ALICE, &bob, Amount::from_units(100))).is_ok());
+        assert!(ledger.commit_block(&mut MemoryStore::new(), &bob).is_ok());
+        // Each event reaches every subscriber, in subscription order, before the next one.
+        assert_eq!(kinds(&log), vec![
+            ("first", "transfer"), ("second", "transfer"),
+            ("first", "fee"), ("second", "fee"),
+            ("first", "block"), ("second", "block"),
+        ]);
+
+        // A refused transfer publishes nothing.
+        log.lock().unwrap().clear();
+        let mut refused = testing::transfer(&ledger, &ALICE, &bob, Amount::from_units(100));
+        refused.signatures.clear();
+        assert!(ledger.apply(refused).is_err());
+        assert!(log.lock().unwrap().is_empty());
+
+        assert!(ledger.unsubscribe(first));
+        assert!(!ledger.unsubscribe(first));
+        assert!(ledger.apply(testing::transfer(&ledger, &ALICE, &bob, Amount::from_units(100))).is_ok());
+        assert_eq!(kinds(&log), vec![("second", "transfer"), ("second", "fee")]);
+    }
+
+    #[test]
+    fn late_subscribers_only_see_later_events() {
+        let mut bus = EventBus::new();
+        let log: Log = Arc::default();
+        bus.publish(LedgerEvent::KeyRotated { account: "alice".to_string() });
+        let id = bus.subscribe(record(&log, "late"));
+        bus.publish(LedgerEvent::AccountLocked { account: "bob".to_string() });
+        assert_eq!(log.lock().unwrap().clone(), vec![("late", LedgerEvent::AccountLocked { account: "bob".to_string() })]);
+        assert!(bus.unsubscribe(id));
+        assert_eq!(bus.subscriber_count(), 0);
+    }
+}
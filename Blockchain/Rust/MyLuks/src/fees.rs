@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::amount::{Amount, AmountError};
+use crate::encoding::{Action, AuthorizationPayload};
+use crate::storage::{StateStore, StorageError};
+use crate::transfer::TransferType;
+
+// Key of the fee policy in the store
+const FEE_POLICY_KEY: &str = "fee_policy";
+
+// 10_000 basis points = 100%.
+pub const BASIS_POINTS_DENOMINATOR: u64 = 10_000;
+
+pub enum FeeError {
+    InvalidPolicy,
+    Amount(AmountError),
+}
+
+impl From<AmountError> for FeeError {
+    fn from(error: AmountError) -> Self {
+        FeeError::Amount(error)
+    }
+}
+
+// Tier of a tiered fee: applies to amounts of at least `from`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FeeTier {
+    pub from: Amount,
+    pub flat: Amount,
+    pub basis_points: u64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum FeeRule {
+    Flat(Amount),
+    BasisPoints(u64),
+    Tiered(Vec<FeeTier>), // Sorted by `from`, first tier starting at zero.
+}
+
+/// How the fee of one transfer type is computed: a rule, then optional bounds.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    rule: FeeRule,
+    min_fee: Option<Amount>,
+    max_fee: Option<Amount>,
+}
+
+impl FeeSchedule {
+    pub fn new(rule: FeeRule, min_fee: Option<Amount>, max_fee: Option<Amount>) -> Result<Self, FeeError> {
+        if let (Some(min), Some(max)) = (min_fee, max_fee) {
+            if min > max {
+                return Err(FeeError::InvalidPolicy);
+            }
+        }
+        match &rule {
+            FeeRule::Flat(_) => {}
+            FeeRule::BasisPoints(bps) if *bps <= BASIS_POINTS_DENOMINATOR => {}
+            FeeRule::Tiered(tiers) if Self::valid_tiers(tiers) => {}
+            _ => return Err(FeeError::InvalidPolicy),
+        }
+        Ok(FeeSchedule { rule, min_fee, max_fee })
+    }
+
+    pub fn flat(fee: Amount) -> Self {
+        FeeSchedule { rule: FeeRule::Flat(fee), min_fee: None, max_fee: None }
+    }
+
+    fn valid_tiers(tiers: &[FeeTier]) -> bool {
+        tiers.first().is_some_and(|tier| tier.from.is_zero())
+            && tiers.windows(2).all(|pair| pair[0].from < pair[1].from)
+            && tiers.iter().all(|tier| tier.basis_points <= BASIS_POINTS_DENOMINATOR)
+    }
+
+    pub fn compute(&self, amount: Amount) -> Result<Amount, FeeError> {
+        let fee = match &self.rule {
+            FeeRule::Flat(fee) => *fee,
+            FeeRule::BasisPoints(bps) => amount.checked_mul_ratio(*bps, BASIS_POINTS_DENOMINATOR)?,
+            FeeRule::Tiered(tiers) => {
+                // Tiers are sorted, so the last one reached is the one that applies.
+                let tier = tiers.iter().rev().find(|tier| tier.from <= amount).ok_or(FeeError::InvalidPolicy)?;
+                tier.flat.checked_add(amount.checked_mul_ratio(tier.basis_points, BASIS_POINTS_DENOMINATOR)?)?
+            }
+        };
+        let fee = self.min_fee.map_or(fee, |min| fee.max(min));
+        Ok(self.max_fee.map_or(fee, |max| fee.min(max)))
+    }
+}
+
+/// What a transfer will cost, known before it is signed or applied.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FeeQuote {
+    pub amount: Amount, // Received by the recipient.
+    pub fee: Amount, // Policy fee, paid to the fees account.
+    pub base_fee: Amount, // Burned.
+    pub tip: Amount, // Paid to the block producer.
+    pub total: Amount, // Debited from the sender.
+}
+
+impl FeeQuote {
+    // Adds the network fees of the block being built to a policy quote.
+    pub fn with_network_fees(self, base_fee: Amount, tip: Amount) -> Result<FeeQuote, FeeError> {
+        let total = self.amount.checked_add(self.fee)?.checked_add(base_fee)?.checked_add(tip)?;
+        Ok(FeeQuote { base_fee, tip, total, ..self })
+    }
+}
+
+/// Fee schedule for every transfer type. Types without their own schedule use the default.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FeePolicy {
+    default: FeeSchedule,
+    schedules: HashMap<TransferType, FeeSchedule>,
+}
+
+impl FeePolicy {
+    pub fn new(default: FeeSchedule) -> Self {
+        FeePolicy { default, schedules: HashMap::new() }
+    }
+
+    pub fn set_schedule(&mut self, transfer_type: TransferType, schedule: FeeSchedule) {
+        self.schedules.insert(transfer_type, schedule);
+    }
+
+    pub fn schedule(&self, transfer_type: &TransferType) -> &FeeSchedule {
+        self.schedules.get(transfer_type).unwrap_or(&self.default)
+    }
+
+    pub fn quote(&self, transfer_type: &TransferType, amount: Amount) -> Result<FeeQuote, FeeError> {
+        let fee = self.schedule(transfer_type).compute(amount)?;
+        Ok(FeeQuote { amount, fee, base_fee: Amount::ZERO, tip: Amount::ZERO, total: amount.checked_add(fee)? })
+    }
+
+    // JSON of the policy with the schedules ordered by transfer type, so that it can be signed.
+    pub fn canonical_json(&self) -> String {
+        let mut schedules: Vec<(String, &FeeSchedule)> = self.schedules.iter().map(|(transfer_type, schedule)| (format!("{:?}", transfer_type), schedule)).collect();
+        schedules.sort_by(|a, b| a.0.cmp(&b.0));
+        serde_json::to_string(&(&self.default, schedules)).unwrap_or_default()
+    }
+
+    // Data the caller signs to replace the fee policy with `policy`.
+    pub fn update_data(caller: &str, policy: &FeePolicy, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::SetFeePolicy).string(caller).string(&policy.canonical_json()).u64(nonce).signing_data()
+    }
+
+    // Writes the fee policy to the store.
+    pub fn persist(&self, store: &mut dyn StateStore) -> Result<(), StorageError> {
+        let value = serde_json::to_vec(self).map_err(|_| StorageError::Serialization)?;
+        store.put(FEE_POLICY_KEY, value);
+        Ok(())
+    }
+
+    // Restores the fee policy, or None if none was ever committed.
+    pub fn restore(store: &dyn StateStore) -> Result<Option<Self>, StorageError> {
+        match store.get(FEE_POLICY_KEY) {
+            Some(value) => serde_json::from_slice(&value).map(Some).map_err(|_| StorageError::Serialization),
+            None => Ok(None),
+        }
+    }
+}
+
+// 5% on every transfer, the rate the ledger has always charged.
+impl Default for FeePolicy {
+    fn default() -> Self {
+        FeePolicy::new(FeeSchedule { rule: FeeRule::BasisPoints(500), min_fee: None, max_fee: None })
+    }
+}
+
+/// Per-block base fee that follows demand, in the style of EIP-1559.
+///
+/// After each block the base fee moves towards balance: up when the block held more than
+/// `target_transactions`, down when it held fewer, by at most 1/8 per block. The base fee is
+/// burned, so congestion makes transfers more expensive instead of queueing them forever.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BaseFeeMarket {
+    base_fee: Amount,
+    min_base_fee: Amount,
+    target_transactions: u64,
+}
+
+impl BaseFeeMarket {
+    pub const MAX_CHANGE_DENOMINATOR: u64 = 8;
+
+    pub fn new(base_fee: Amount, min_base_fee: Amount, target_transactions: u64) -> Result<Self, FeeError> {
+        if target_transactions == 0 || base_fee < min_base_fee {
+            return Err(FeeError::InvalidPolicy);
+        }
+        Ok(BaseFeeMarket { base_fee, min_base_fee, target_transactions })
+    }
+
+    pub fn base_fee(&self) -> Amount {
+        self.base_fee
+    }
+
+    // Resumes from the base fee recorded in the last committed block.
+    pub fn restore_base_fee(&mut self, base_fee: Amount) {
+        self.base_fee = base_fee.max(self.min_base_fee);
+    }
+
+    // Base fee of the block after one holding `block_transactions` transactions.
+    pub fn next_base_fee(&self, block_transactions: u64) -> Amount {
+        let target = self.target_transactions;
+        let denominator = target.saturating_mul(Self::MAX_CHANGE_DENOMINATOR);
+        if block_transactions > target {
+            let delta = self.base_fee.checked_mul_ratio(block_transactions - target, denominator).unwrap_or(Amount::MAX);
+            // Always move by at least one unit, so a tiny base fee can still rise.
+            self.base_fee.saturating_add(delta.max(Amount::from_units(1)))
+        } else {
+            let delta = self.base_fee.checked_mul_ratio(target - block_transactions, denominator).unwrap_or(Amount::MAX);
+            self.base_fee.saturating_sub(delta).max(self.min_base_fee)
+        }
+    }
+
+    pub fn adjust(&mut self, block_transactions: usize) {
+        self.base_fee = self.next_base_fee(block_transactions as u64);
+    }
+}
+
+// 0.00001 LUKS per transfer to start, targeting 50 transfers per block.
+impl Default for BaseFeeMarket {
+    fn default() -> Self {
+        BaseFeeMarket { base_fee: Amount::from_units(1_000), min_base_fee: Amount::from_units(1), target_transactions: 50 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStore;
+    use crate::testing::{self, ALICE, BOB, CAROL};
+
+    fn tier(from: u64, flat: u64, basis_points: u64) -> FeeTier {
+        FeeTier { from: Amount::from_units(from), flat: Amount::from_units(flat), basis_points }
+    }
+
+    fn fee(schedule: &FeeSchedule, amount: u64) -> Option<Amount> {
+        schedule.compute(Amount::from_units(amount)).ok()
+    }
+
+    #[test]
+    fn tiered_fees_apply_the_highest_tier_reached_within_the_bounds() {
+        let tiers = vec![tier(0, 10, 100), tier(100_000, 0, 50), tier(1_000_000, 0, 10)];
+        let tiered = FeeSchedule::new(FeeRule::Tiered(tiers.clone()), None, None).ok().unwrap();
+        assert_eq!(fee(&tiered, 50_000), Some(Amount::from_units(510)));
+        assert_eq!(fee(&tiered, 100_000), Some(Amount::from_units(500)));
+        assert_eq!(fee(&tiered, 2_000_000), Some(Amount::from_units(2_000)));
+        // Integer math rounds down: 1% of 99 units is no fee at all.
+        let percent = FeeSchedule::new(FeeRule::BasisPoints(100), None, None).ok().unwrap();
+        assert_eq!(fee(&percent, 99), Some(Amount::ZERO));
+
+        let bounded = FeeSchedule::new(FeeRule::Tiered(tiers), Some(Amount::from_units(600)), Some(Amount::from_units(1_500))).ok().unwrap();
+        assert_eq!(fee(&bounded, 50_000), Some(Amount::from_units(600)));
+        assert_eq!(fee(&bounded, 300_000), Some(Amount::from_units(1_500)));
+        assert_eq!(fee(&bounded, 2_000_000), Some(Amount::from_units(1_500)));
+
+        // Tiers must start at zero and rise; rates cannot pass 100%; min cannot pass max.
+        assert!(matches!(FeeSchedule::new(FeeRule::Tiered(vec![tier(1, 0, 10)]), None, None), Err(FeeError::InvalidPolicy)));
+        assert!(matches!(FeeSchedule::new(FeeRule::Tiered(vec![tier(0, 0, 10), tier(0, 0, 5)]), None, None), Err(FeeError::InvalidPolicy)));
+        assert!(matches!(FeeSchedule::new(FeeRule::BasisPoints(10_001), None, None), Err(FeeError::InvalidPolicy)));
+        assert!(matches!(FeeSchedule::new(FeeRule::Flat(Amount::ZERO), Some(Amount::from_units(2)), Some(Amount::from_units(1))), Err(FeeError::InvalidPolicy)));
+    }
+
+    #[test]
+    fn each_transfer_type_is_quoted_with_its_own_schedule() {
+        let mut policy = FeePolicy::default();
+        policy.set_schedule(TransferType::FromContract, FeeSchedule::flat(Amount::from_units(7)));
+        let amount = Amount::from_units(10_000);
+
+        let peer = policy.quote(&TransferType::PeerToPeer, amount).ok().unwrap();
+        assert_eq!((peer.fee, peer.total), (Amount::from_units(500), Amount::from_units(10_500)));
+        let from_contract = policy.quote(&TransferType::FromContract, amount).ok().unwrap();
+        assert_eq!((from_contract.fee, from_contract.total), (Amount::from_units(7), Amount::from_units(10_007)));
+
+        let quote = peer.with_network_fees(Amount::from_units(3), Amount::from_units(2)).ok().unwrap();
+        assert_eq!(quote.total, Amount::from_units(10_505));
+        assert!(matches!(policy.quote(&TransferType::PeerToPeer, Amount::MAX), Err(FeeError::Amount(_))));
+    }
+
+    #[test]
+    fn base_fee_moves_towards_the_target_by_at_most_an_eighth() {
+        let market = BaseFeeMarket::new(Amount::from_units(8_000), Amount::from_units(5_000), 10).ok().unwrap();
+        assert_eq!(market.next_base_fee(10), Amount::from_units(8_000));
+        assert_eq!(market.next_base_fee(20), Amount::from_units(9_000));
+        assert_eq!(market.next_base_fee(15), Amount::from_units(8_500));
+        // An empty block would take an eighth off, but never below the minimum.
+        assert_eq!(market.next_base_fee(0), Amount::from_units(7_000));
+        let mut floor = market.clone();
+        for _ in 0..10 {
+            floor.adjust(0);
+        }
+        assert_eq!(floor.base_fee(), Amount::from_units(5_000));
+
+        // A base fee too small for an eighth to show still rises under demand.
+        let tiny = BaseFeeMarket::new(Amount::from_units(1), Amount::from_units(1), 10).ok().unwrap();
+        assert_eq!(tiny.next_base_fee(11), Amount::from_units(2));
+        assert!(matches!(BaseFeeMarket::new(Amount::from_units(1), Amount::from_units(2), 10), Err(FeeError::InvalidPolicy)));
+        assert!(matches!(BaseFeeMarket::new(Amount::from_units(1), Amount::ZERO, 0), Err(FeeError::InvalidPolicy)));
+    }
+
+    #[test]
+    fn base_fees_are_burned_and_tips_go_to_the_producer() {
+        let mut ledger = testing::ledger();
+        let (bob, carol) = (testing::address(&BOB), testing::address(&CAROL));
+        let base_fee = ledger.fee_market.base_fee();
+        let (supply, burned) = (ledger.coin_issue.total_supply(), ledger.coin_issue.total_burned());
+
+        let tx = testing::transfer(&ledger, &ALICE, &bob, Amount::from_units(100));
+        let receipt = ledger.apply(tx).ok().unwrap();
+        assert_eq!(receipt.base_fee, base_fee);
+        assert_eq!(ledger.coin_issue.total_supply().checked_add(base_fee).ok(), Some(supply));
+        assert_eq!(ledger.coin_issue.total_burned(), burned.saturating_add(base_fee));
+
+        // Carol produces the block and collects the tip; one transfer is under the target of
+        // 50, so the next base fee drops.
+        let before = ledger.accounts.get_balance(&carol).ok().unwrap();
+        assert!(ledger.commit_block(&mut MemoryStore::new(), &carol).is_ok());
+        assert_eq!(ledger.accounts.get_balance(&carol).ok(), before.checked_add(receipt.tip).ok());
+        assert_eq!(ledger.consensus.blockchain.last().map(|block| block.base_fee), Some(base_fee));
+        assert_eq!(ledger.fee_market.base_fee(), BaseFeeMarket::default().next_base_fee(1));
+        assert!(ledger.fee_market.base_fee() < base_fee);
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::amount::Amount;
+use crate::assets::AssetId;
+use crate::encoding::{Action, AuthorizationPayload};
+use crate::storage::{StateStore, StorageError};
+
+// Prefix of history entries in the store
+const HISTORY_KEY_PREFIX: &str = "history/";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum HistoryEntryKind {
+    Transfer,
+    Issuance,
+    Fee, // Policy fee paid to the fees account.
+    Burn, // Base fee removed from circulation.
+    Tip, // Priority fee paid to a block producer.
+    Sweep, // Balance moved out of an account being closed.
+    Vesting, // Tranche granted to `to`, locked until it vests.
+    RecoveryStarted, // A guardian started replacing the key of `to`.
+    RecoveryApproved, // Another guardian approved it.
+    RecoveryCancelled, // The account stopped it with its current key.
+    KeyRecovered, // The new key was installed.
+}
+
+/// One balance movement. For issuances `from` is the issuer; `to` is None for burns, and
+/// for tips until the block producer is known. Recovery steps move nothing: their amount
+/// is zero, `from` is the guardian or the cancelling account and `to` the recovered one.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64, // Position in the index; also the pagination cursor.
+    pub kind: HistoryEntryKind,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub asset: AssetId,
+    pub amount: Amount,
+    pub timestamp: u64,
+    pub transaction_hash: String,
+}
+
+impl HistoryEntry {
+    fn involves(&self, account: &String) -> bool {
+        self.from.as_ref() == Some(account) || self.to.as_ref() == Some(account)
+    }
+
+    // Other side of the movement as seen from `account`.
+    fn counterparty_of(&self, account: &String) -> Option<&String> {
+        if self.from.as_ref() == Some(account) {
+            self.to.as_ref()
+        } else {
+            self.from.as_ref()
+        }
+    }
+}
+
+/// Criteria for `HistoryIndex::query`. Every field left as None matches everything;
+/// the time and amount bounds are inclusive.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct HistoryFilter {
+    pub account: Option<String>,
+    pub counterparty: Option<String>,
+    pub from_time: Option<u64>,
+    pub to_time: Option<u64>,
+    pub min_amount: Option<Amount>,
+    pub max_amount: Option<Amount>,
+    pub kinds: Option<Vec<HistoryEntryKind>>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        if let Some(account) = &self.account {
+            if !entry.involves(account) {
+                return false;
+            }
+        }
+        if let Some(counterparty) = &self.counterparty {
+            let matched = match &self.account {
+                Some(account) => entry.counterparty_of(account) == Some(counterparty),
+                None => entry.involves(counterparty),
+            };
+            if !matched {
+                return false;
+            }
+        }
+        self.from_time.is_none_or(|from| entry.timestamp >= from)
+            && self.to_time.is_none_or(|to| entry.timestamp <= to)
+            && self.min_amount.is_none_or(|min| entry.amount >= min)
+            && self.max_amount.is_none_or(|max| entry.amount <= max)
+            && self.kinds.as_ref().is_none_or(|kinds| kinds.contains(&entry.kind))
+    }
+}
+
+// Opaque position after which the next page starts.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct HistoryCursor(u64);
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryEntry>,
+    pub next_cursor: Option<HistoryCursor>, // None once there is nothing left to read.
+}
+
+/// Every balance movement and recovery step on the ledger, oldest first, indexed by account.
+///
+/// Unlike `AccountDetails::transactions`, which keeps only the last few entries per account,
+/// nothing is ever dropped from the index.
+#[derive(Debug, PartialEq)]
+pub struct HistoryIndex {
+    entries: Vec<HistoryEntry>,
+    by_account: HashMap<String, Vec<u64>>, // Ids of the entries involving each account, ascending.
+    persisted: usize, // Entries already written to the store.
+}
+
+impl Default for HistoryIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HistoryIndex {
+    // Data the caller signs to query the history, at its next nonce. Queries change
+    // nothing, so the nonce is not consumed and the signature holds until it is.
+    pub fn query_data(caller: &str, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::ViewHistory).string(caller).u64(nonce).signing_data()
+    }
+
+    pub fn new() -> Self {
+        HistoryIndex {
+            entries: Vec::new(),
+            by_account: HashMap::new(),
+            persisted: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(&mut self, kind: HistoryEntryKind, from: Option<&String>, to: Option<&String>, asset: &AssetId, amount: Amount, timestamp: u64, transaction_hash: &str) {
+        let id = self.entries.len() as u64;
+        let entry = HistoryEntry {
+            id,
+            kind,
+            from: from.cloned(),
+            to: to.cloned(),
+            asset: asset.clone(),
+            amount,
+            timestamp,
+            transaction_hash: transaction_hash.to_string(),
+        };
+        self.index_entry(&entry);
+        self.entries.push(entry);
+    }
+
+    fn index_entry(&mut self, entry: &HistoryEntry) {
+        for account in [&entry.from, &entry.to].into_iter().flatten() {
+            let ids = self.by_account.entry(account.clone()).or_default();
+            // Tips are indexed for their producer late, so insert in order rather than push.
+            if let Err(position) = ids.binary_search(&entry.id) {
+                ids.insert(position, entry.id);
+            }
+        }
+    }
+
+    // Tips are recorded when a transfer is applied, before the block producer is known.
+    fn assign_tips(&mut self, producer: &str) {
+        for index in self.persisted..self.entries.len() {
+            if self.entries[index].kind == HistoryEntryKind::Tip && self.entries[index].to.is_none() {
+                self.entries[index].to = Some(producer.to_string());
+                let entry = self.entries[index].clone();
+                self.index_entry(&entry);
+            }
+        }
+    }
+
+    /// Returns up to `limit` entries matching `filter`, oldest first, starting after `after`.
+    pub fn query(&self, filter: &HistoryFilter, after: Option<HistoryCursor>, limit: usize) -> HistoryPage {
+        let start = after.map_or(0, |HistoryCursor(id)| id + 1);
+        // With an account filter only that account's entries are scanned.
+        let candidates: Box<dyn Iterator<Item = &HistoryEntry>> = match &filter.account {
+            Some(account) => {
+                let ids = self.by_account.get(account).map(|ids| ids.as_slice()).unwrap_or(&[]);
+                let first = ids.partition_point(|&id| id < start);
+                Box::new(ids[first..].iter().map(move |&id| &self.entries[id as usize]))
+            }
+            None => Box::new(self.entries.iter().skip(start as usize)),
+        };
+
+        let mut entries = Vec::new();
+        let mut has_more = false;
+        for entry in candidates.filter(|entry| filter.matches(entry)) {
+            if entries.len() == limit {
+                has_more = true;
+                break;
+            }
+            entries.push(entry.clone());
+        }
+        let next_cursor = if has_more { entries.last().map(|entry| HistoryCursor(entry.id)) } else { None };
+        HistoryPage { entries, next_cursor }
+    }
+
+    // Writes the entries recorded since the last block, with its tips credited to `producer`.
+    // Nothing changes in memory until `seal` is called once the block is committed.
+    pub fn persist(&self, store: &mut dyn StateStore, producer: &str) -> Result<(), StorageError> {
+        for entry in &self.entries[self.persisted..] {
+            let mut entry = entry.clone();
+            if entry.kind == HistoryEntryKind::Tip && entry.to.is_none() {
+                entry.to = Some(producer.to_string());
+            }
+            let value = serde_json::to_vec(&entry).map_err(|_| StorageError::Serialization)?;
+            store.put(&format!("{}{:020}", HISTORY_KEY_PREFIX, entry.id), value);
+        }
+        Ok(())
+    }
+
+    // Credits the block's tips to `producer` and marks its entries as persisted.
+    pub fn seal(&mut self, producer: &str) {
+        self.assign_tips(producer);
+        self.persisted = self.entries.len();
+    }
+
+    pub fn restore(store: &dyn StateStore) -> Result<Self, StorageError> {
+        let mut index = HistoryIndex::new();
+        // Keys are zero-padded, so the store returns them in id order.
+        for key in store.keys_with_prefix(HISTORY_KEY_PREFIX) {
+            let value = store.get(&key).ok_or(StorageError::Corrupted)?;
+            let entry: HistoryEntry = serde_json::from_slice(&value).map_err(|_| StorageError::Serialization)?;
+            if entry.id != index.entries.len() as u64 {
+                return Err(StorageError::Corrupted);
+            }
+            index.index_entry(&entry);
+            index.entries.push(entry);
+        }
+        index.persisted = index.entries.len();
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStore;
+
+    fn account(name: &str) -> String {
+        name.to_string()
+    }
+
+    // Alice pays bob 1..=5 units at times 10..=50, and pays carol 100 units at time 60.
+    fn index() -> HistoryIndex {
+        let mut index = HistoryIndex::new();
+        let (alice, bob, carol) = (account("alice"), account("bob"), account("carol"));
+        let asset = account("LUKS");
+        for n in 1..=5u64 {
+            index.record(HistoryEntryKind::Transfer, Some(&alice), Some(&bob), &asset, Amount::from_units(n), n * 10, &format!("tx{}", n));
+        }
+        index.record(HistoryEntryKind::Transfer, Some(&alice), Some(&carol), &asset, Amount::from_units(100), 60, "tx6");
+        index.record(HistoryEntryKind::Tip, Some(&alice), None, &asset, Amount::from_units(1), 60, "tx6");
+        index
+    }
+
+    fn hashes(page: &HistoryPage) -> Vec<&str> {
+        page.entries.iter().map(|entry| entry.transaction_hash.as_str()).collect()
+    }
+
+    #[test]
+    fn cursors_walk_every_match_once_in_order() {
+        let index = index();
+        let filter = HistoryFilter { account: Some(account("bob")), ..HistoryFilter::default() };
+        let first = index.query(&filter, None, 2);
+        assert_eq!(hashes(&first), vec!["tx1", "tx2"]);
+        let second = index.query(&filter, first.next_cursor, 2);
+        assert_eq!(hashes(&second), vec!["tx3", "tx4"]);
+        let last = index.query(&filter, second.next_cursor, 2);
+        assert_eq!(hashes(&last), vec!["tx5"]);
+        assert_eq!(last.next_cursor, None);
+
+        // A page that ends exactly on the last match has no next cursor either.
+        assert_eq!(index.query(&filter, second.next_cursor, 1).next_cursor, None);
+        assert_eq!(index.query(&filter, None, 5).next_cursor, None);
+    }
+
+    #[test]
+    fn filters_combine_counterparty_time_amount_and_kind() {
+        let index = index();
+        let alice = Some(account("alice"));
+        let to_carol = HistoryFilter { account: alice.clone(), counterparty: Some(account("carol")), ..HistoryFilter::default() };
+        assert_eq!(hashes(&index.query(&to_carol, None, 10)), vec!["tx6"]);
+
+        let window = HistoryFilter { account: alice.clone(), from_time: Some(20), to_time: Some(40), min_amount: Some(Amount::from_units(3)), ..HistoryFilter::default() };
+        assert_eq!(hashes(&index.query(&window, None, 10)), vec!["tx3", "tx4"]);
+
+        let tips = HistoryFilter { account: alice, kinds: Some(vec![HistoryEntryKind::Tip]), ..HistoryFilter::default() };
+        assert_eq!(index.query(&tips, None, 10).entries.len(), 1);
+        assert!(index.query(&HistoryFilter { account: Some(account("dave")), ..HistoryFilter::default() }, None, 10).entries.is_empty());
+    }
+
+    #[test]
+    fn tips_reach_the_producer_and_the_index_survives_a_restart() {
+        let mut index = index();
+        let producer = HistoryFilter { account: Some(account("carol")), kinds: Some(vec![HistoryEntryKind::Tip]), ..HistoryFilter::default() };
+        assert!(index.query(&producer, None, 10).entries.is_empty());
+
+        let mut store = MemoryStore::new();
+        assert!(index.persist(&mut store, "carol").is_ok());
+        assert!(store.commit(0).is_ok());
+        index.seal("carol");
+        assert_eq!(hashes(&index.query(&producer, None, 10)), vec!["tx6"]);
+
+        let restored = HistoryIndex::restore(&store).ok().unwrap();
+        assert_eq!(restored.len(), index.len());
+        assert_eq!(restored.query(&producer, None, 10), index.query(&producer, None, 10));
+    }
+}
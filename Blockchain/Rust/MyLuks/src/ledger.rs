@@ -0,0 +1,1119 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::accounts::{Accounts, AccountError, AccountSignature, PublicKey, TransactionHistory};
+use crate::address::{self, AddressError};
+use crate::amount::{Amount, AmountError};
+use crate::assets::{AssetError, AssetId, AssetRegistry, NATIVE_ASSET};
+use crate::coin_issue::{CoinIssue, CoinIssueError};
+use crate::events::{EventBus, LedgerEvent, SubscriberId};
+use crate::concensus::{self, Consensus, ConsensusError, TransactionKind};
+use crate::transfer::TransferType;
+use crate::nft::{ItemId, NftError, NftRegistry};
+use crate::notifications::Notifier;
+use crate::recovery::{GuardianConfig, Recovery, RecoveryError};
+use crate::security::{Security, SecurityError};
+use crate::storage::{StateStore, StorageError};
+use crate::fees::{BaseFeeMarket, FeeError, FeePolicy, FeeQuote};
+use crate::metrics::metrics;
+use tracing::{instrument, warn};
+use crate::history::{HistoryCursor, HistoryEntryKind, HistoryFilter, HistoryIndex, HistoryPage};
+use crate::roles::{Permission, Role, RoleError, Roles};
+use crate::validate_transaction::{ValidateTransaction, ValidationError};
+use crate::vesting::VestingSchedule;
+
+// Blocks of state history kept for proofs by default, and how often older history is pruned.
+const DEFAULT_STATE_RETENTION: u64 = 10_000;
+const STATE_PRUNE_INTERVAL: u64 = 100;
+
+/// Errors returned by `Ledger::apply`. Whatever the variant, no state was modified.
+pub enum LedgerError {
+    Validation(ValidationError),
+    Account(AccountError),
+    Security(SecurityError),
+    CoinIssue(CoinIssueError),
+    Consensus(ConsensusError),
+    Storage(StorageError),
+    Asset(AssetError),
+    Nft(NftError),
+    Address(AddressError),
+    Amount(AmountError),
+    Fee(FeeError),
+    Role(RoleError),
+    Recovery(RecoveryError),
+    InvalidAmount,
+    FeesAccountNotFound,
+    ProducerNotFound,
+    SingleKeyTreasury,
+    TreasuryCannotClose,
+    CentralBankCannotClose, // Issuance would be stranded.
+    GuardianCannotClose, // Recoveries the account guards would be stranded.
+    AccountHoldsItems, // NFTs must be transferred before their owner can close.
+}
+
+impl From<ValidationError> for LedgerError {
+    fn from(error: ValidationError) -> Self {
+        LedgerError::Validation(error)
+    }
+}
+
+impl From<AccountError> for LedgerError {
+    fn from(error: AccountError) -> Self {
+        LedgerError::Account(error)
+    }
+}
+
+impl From<SecurityError> for LedgerError {
+    fn from(error: SecurityError) -> Self {
+        LedgerError::Security(error)
+    }
+}
+
+impl From<CoinIssueError> for LedgerError {
+    fn from(error: CoinIssueError) -> Self {
+        LedgerError::CoinIssue(error)
+    }
+}
+
+impl From<ConsensusError> for LedgerError {
+    fn from(error: ConsensusError) -> Self {
+        LedgerError::Consensus(error)
+    }
+}
+
+impl From<AssetError> for LedgerError {
+    fn from(error: AssetError) -> Self {
+        LedgerError::Asset(error)
+    }
+}
+
+impl From<NftError> for LedgerError {
+    fn from(error: NftError) -> Self {
+        LedgerError::Nft(error)
+    }
+}
+
+impl From<AddressError> for LedgerError {
+    fn from(error: AddressError) -> Self {
+        LedgerError::Address(error)
+    }
+}
+
+impl From<AmountError> for LedgerError {
+    fn from(error: AmountError) -> Self {
+        LedgerError::Amount(error)
+    }
+}
+
+impl From<FeeError> for LedgerError {
+    fn from(error: FeeError) -> Self {
+        LedgerError::Fee(error)
+    }
+}
+
+impl From<RoleError> for LedgerError {
+    fn from(error: RoleError) -> Self {
+        LedgerError::Role(error)
+    }
+}
+
+impl From<RecoveryError> for LedgerError {
+    fn from(error: RecoveryError) -> Self {
+        LedgerError::Recovery(error)
+    }
+}
+
+impl From<StorageError> for LedgerError {
+    fn from(error: StorageError) -> Self {
+        LedgerError::Storage(error)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LedgerTransactionKind {
+    Transfer,
+    Issuance,
+}
+
+/// A state transition submitted to the ledger.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LedgerTransaction {
+    pub kind: LedgerTransactionKind,
+    pub from: String,
+    pub to: String,
+    pub asset: AssetId,
+    pub amount: Amount,
+    pub tip: Amount, // Priority fee for the block producer, covered by the signatures. Zero for issuances.
+    pub nonce: u64, // Must equal the sender's next nonce (the issue nonce for issuances).
+    pub signatures: Vec<AccountSignature>,
+}
+
+/// Summary of a transaction that was applied to the ledger.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Receipt {
+    pub transaction_hash: String,
+    pub from: String,
+    pub to: String,
+    pub asset: AssetId,
+    pub amount: Amount,
+    pub fee: Amount, // Charged in the same asset as the transfer.
+    pub base_fee: Amount, // Burned.
+    pub tip: Amount, // Credited to the producer of the next block.
+    pub timestamp: u64,
+}
+
+// A balance movement recorded so that it can be undone.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum BalanceChange {
+    Credit(Amount),
+    Debit(Amount),
+}
+
+/// Single entry point for state transitions. Owns every module that holds ledger
+/// state so that a transaction is either applied everywhere or nowhere.
+pub struct Ledger {
+    pub accounts: Accounts,
+    pub coin_issue: CoinIssue,
+    pub consensus: Consensus,
+    pub assets: AssetRegistry,
+    pub nfts: NftRegistry,
+    pub fees_account: String,
+    pub fee_market: BaseFeeMarket,
+    recovery: Recovery,
+    fee_policy: FeePolicy,
+    history: HistoryIndex,
+    roles: Roles,
+    events: EventBus,
+    validator: ValidateTransaction,
+    pending_transactions: Vec<concensus::Transaction>,
+    pending_tips: BTreeMap<AssetId, Amount>, // Tips collected for the producer of the next block.
+    notifier: Option<Arc<Mutex<Notifier>>>, // Persisted with every block once attached.
+    state_retention: u64, // Blocks of state history kept for proofs.
+}
+
+impl Ledger {
+    pub fn new(
+        accounts: Accounts,
+        validator: ValidateTransaction,
+        coin_issue: CoinIssue,
+        consensus: Consensus,
+        fees_account: String,
+        roles: Roles,
+    ) -> Result<Self, LedgerError> {
+        let treasury = accounts.get_account_details(&fees_account).map_err(|_| LedgerError::FeesAccountNotFound)?;
+        // The fees account is the treasury and must be controlled by a key set.
+        if treasury.signing_policy().is_single_key() {
+            return Err(LedgerError::SingleKeyTreasury);
+        }
+        Ok(Ledger {
+            accounts,
+            coin_issue,
+            consensus,
+            assets: AssetRegistry::new(),
+            nfts: NftRegistry::new(),
+            fees_account,
+            fee_market: BaseFeeMarket::default(),
+            recovery: Recovery::new(),
+            fee_policy: FeePolicy::default(),
+            history: HistoryIndex::new(),
+            roles,
+            events: EventBus::new(),
+            validator,
+            pending_transactions: Vec::new(),
+            pending_tips: BTreeMap::new(),
+            notifier: None,
+            state_retention: DEFAULT_STATE_RETENTION,
+        })
+    }
+
+    /// Rebuilds a ledger from the last block committed to `store`. `owner` only becomes
+    /// the first owner if no roles were ever committed, and `coin_issue` is only used if
+    /// no issuance state was. Webhook endpoints are restored with `Notifier::restore`.
+    pub fn recover(
+        store: &dyn StateStore,
+        max_transaction_history: usize,
+        coin_issue: CoinIssue,
+        hash_prefix: String,
+        fees_account: String,
+        owner: &str,
+    ) -> Result<Self, LedgerError> {
+        let accounts = Accounts::restore(store, max_transaction_history)?;
+        let validator = ValidateTransaction::new(Security::new());
+        let consensus = Consensus::restore(hash_prefix, store)?;
+        let roles = Roles::restore(store)?.unwrap_or_else(|| Roles::new(owner));
+        // `coin_issue` only seeds a fresh store: once committed, the issuance state is restored.
+        let coin_issue = CoinIssue::restore(store)?.unwrap_or(coin_issue);
+        let mut ledger = Ledger::new(accounts, validator, coin_issue, consensus, fees_account, roles)?;
+        ledger.history = HistoryIndex::restore(store)?;
+        ledger.assets = AssetRegistry::restore(store, &mut ledger.accounts)?;
+        ledger.nfts = NftRegistry::restore(store, &mut ledger.accounts)?;
+        ledger.accounts.checkpoint(store.committed_height().unwrap_or(0));
+        ledger.recovery = Recovery::restore(store)?;
+        if let Some(fee_policy) = FeePolicy::restore(store)? {
+            ledger.fee_policy = fee_policy;
+        }
+        // The next base fee follows from the last block's base fee and fullness.
+        if let Some(block) = ledger.consensus.blockchain.last().filter(|block| block.index > 0) {
+            ledger.fee_market.restore_base_fee(block.base_fee);
+            ledger.fee_market.adjust(block.transactions.len());
+        }
+        Ok(ledger)
+    }
+
+    /// Registers `handler` to be called with every event the ledger publishes from now on.
+    pub fn subscribe<F>(&mut self, handler: F) -> SubscriberId
+    where
+        F: FnMut(&LedgerEvent) + Send + 'static,
+    {
+        self.events.subscribe(handler)
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriberId) -> bool {
+        self.events.unsubscribe(id)
+    }
+
+    /// How many blocks back state roots and account proofs are served. Older history is
+    /// pruned every 100 blocks.
+    pub fn set_state_retention(&mut self, blocks: u64) {
+        self.state_retention = blocks;
+    }
+
+    // Lets modules built on the ledger, such as the main contract, publish their own events.
+    pub(crate) fn publish(&mut self, event: LedgerEvent) {
+        self.events.publish(event);
+    }
+
+    // Set by `notifications::attach`, so that the endpoints are persisted with each block.
+    pub(crate) fn set_notifier(&mut self, notifier: Arc<Mutex<Notifier>>) {
+        self.notifier = Some(notifier);
+    }
+
+    /// What a transfer of `amount` will cost the sender in the block being built, before
+    /// it is signed or applied. Ledger transfers are between accounts, so the peer-to-peer
+    /// schedule applies, plus the current base fee and the sender's tip.
+    pub fn quote_fee(&self, amount: Amount, tip: Amount) -> Result<FeeQuote, LedgerError> {
+        let quote = self.fee_policy.quote(&TransferType::PeerToPeer, amount)?;
+        Ok(quote.with_network_fees(self.fee_market.base_fee(), tip)?)
+    }
+
+    /// Validates and applies a transaction. On error every partial change is rolled back.
+    #[instrument(name = "ledger.apply", skip_all, fields(kind = ?tx.kind, from = %tx.from, to = %tx.to, asset = %tx.asset, amount = %tx.amount, nonce = tx.nonce))]
+    pub fn apply(&mut self, tx: LedgerTransaction) -> Result<Receipt, LedgerError> {
+        let result = self.apply_checked(tx);
+        match &result {
+            Ok(_) => metrics().transactions_validated.inc(),
+            Err(LedgerError::Validation(error)) => metrics().record_rejection(Some(error)),
+            Err(_) => metrics().record_rejection(None),
+        }
+        if result.is_err() {
+            warn!("transaction rejected");
+        }
+        self.update_gauges();
+        result
+    }
+
+    fn apply_checked(&mut self, tx: LedgerTransaction) -> Result<Receipt, LedgerError> {
+        if tx.amount.is_zero() {
+            return Err(LedgerError::InvalidAmount);
+        }
+        address::validate_address(&tx.from)?;
+        address::validate_address(&tx.to)?;
+        if !self.assets.is_known(&tx.asset) {
+            return Err(LedgerError::Asset(AssetError::AssetNotFound));
+        }
+        match tx.kind {
+            LedgerTransactionKind::Transfer => self.apply_transfer(tx),
+            LedgerTransactionKind::Issuance => self.apply_issuance(tx),
+        }
+    }
+
+    fn apply_transfer(&mut self, tx: LedgerTransaction) -> Result<Receipt, LedgerError> {
+        let quote = self.quote_fee(tx.amount, tx.tip)?;
+        let (fee, total) = (quote.fee, quote.total);
+
+        // All checks happen before the first write.
+        let transaction_hash = self.validator.check_transaction(&self.accounts, &tx.from, &tx.to, &tx.asset, tx.amount, tx.tip, tx.nonce, &tx.signatures)?;
+        if self.accounts.get_spendable_asset_balance(&tx.from, &tx.asset)? < total {
+            return Err(LedgerError::Validation(ValidationError::InsufficientSenderBalance));
+        }
+        if self.accounts.get_account_details(&self.fees_account).is_err() {
+            return Err(LedgerError::FeesAccountNotFound);
+        }
+        let can_burn = if tx.asset == NATIVE_ASSET {
+            self.coin_issue.can_burn(quote.base_fee)
+        } else {
+            self.assets.can_burn(&tx.asset, quote.base_fee)
+        };
+        if !can_burn {
+            return Err(LedgerError::CoinIssue(CoinIssueError::BurnExceedsSupply));
+        }
+        let collected_tips = self.pending_tips.get(&tx.asset).copied().unwrap_or(Amount::ZERO).checked_add(quote.tip)?;
+
+        let mut journal: Vec<(String, AssetId, BalanceChange)> = Vec::new();
+        let moved = self
+            .move_balance(&mut journal, &tx.from, &tx.asset, BalanceChange::Debit(total))
+            .and_then(|_| self.move_balance(&mut journal, &tx.to, &tx.asset, BalanceChange::Credit(tx.amount)))
+            .and_then(|_| {
+                let fees_account = self.fees_account.clone();
+                self.move_balance(&mut journal, &fees_account, &tx.asset, BalanceChange::Credit(fee))
+            });
+        if let Err(e) = moved {
+            self.rollback(journal);
+            return Err(e.into());
+        }
+
+        // The base fee leaves circulation; the tip waits for the block producer. Both
+        // were checked above and cannot fail here.
+        if tx.asset == NATIVE_ASSET {
+            self.coin_issue.burn(quote.base_fee)?;
+        } else {
+            self.assets.burn(&mut self.accounts, &tx.asset, quote.base_fee)?;
+        }
+        self.pending_tips.insert(tx.asset.clone(), collected_tips);
+
+        // The sender was checked to exist, so consuming its nonce cannot fail.
+        self.accounts.increment_nonce(&tx.from)?;
+
+        let timestamp = current_timestamp();
+        self.record_history(&tx, &transaction_hash, timestamp);
+        self.record_fees(&tx, &quote, &transaction_hash, timestamp);
+        self.events.publish(LedgerEvent::TransferCompleted {
+            from: tx.from.clone(),
+            to: tx.to.clone(),
+            asset: tx.asset.clone(),
+            amount: tx.amount,
+            transfer_type: TransferType::PeerToPeer,
+            transaction_hash: Some(transaction_hash.clone()),
+        });
+        if !(fee.is_zero() && quote.base_fee.is_zero() && quote.tip.is_zero()) {
+            self.events.publish(LedgerEvent::FeeCharged {
+                account: tx.from.clone(),
+                asset: tx.asset.clone(),
+                fee,
+                base_fee: quote.base_fee,
+                tip: quote.tip,
+            });
+        }
+        self.pending_transactions.push(concensus::Transaction {
+            kind: TransactionKind::Transfer,
+            from: tx.from.clone(),
+            to: tx.to.clone(),
+            amount: tx.amount,
+            asset: tx.asset.clone(),
+            tip: tx.tip,
+            nonce: tx.nonce,
+            signatures: tx.signatures.clone(),
+        });
+
+        Ok(Receipt {
+            transaction_hash,
+            from: tx.from,
+            to: tx.to,
+            asset: tx.asset,
+            amount: tx.amount,
+            fee,
+            base_fee: quote.base_fee,
+            tip: quote.tip,
+            timestamp,
+        })
+    }
+
+    fn apply_issuance(&mut self, tx: LedgerTransaction) -> Result<Receipt, LedgerError> {
+        // Registered assets are issued through `issue_asset`; this path mints Luks coins.
+        if tx.asset != NATIVE_ASSET {
+            return Err(LedgerError::Asset(AssetError::InvalidAsset));
+        }
+        if !tx.tip.is_zero() {
+            return Err(LedgerError::InvalidAmount);
+        }
+        let transaction_hash = self.validator.generate_transaction_hash(&TransactionKind::Issuance, &tx.from, &tx.to, &tx.asset, tx.amount, tx.tip, tx.nonce);
+        let recipient = self.accounts.get_account_details(&tx.to)?;
+        if recipient.locked {
+            return Err(LedgerError::Account(AccountError::AccountLocked));
+        }
+
+        // The central bank signs the amount that is credited and stored in the block, so it
+        // must be exactly the next emission.
+        if tx.amount != self.coin_issue.next_emission()? {
+            return Err(LedgerError::CoinIssue(CoinIssueError::AmountMismatch));
+        }
+
+        // The credit is journaled; `issue_coins` changes nothing when it fails, so undoing
+        // the credit is enough.
+        let mut journal: Vec<(String, AssetId, BalanceChange)> = Vec::new();
+        self.move_balance(&mut journal, &tx.to, &tx.asset, BalanceChange::Credit(tx.amount))?;
+        let signed_data = ValidateTransaction::signing_data(&TransactionKind::Issuance, &tx.from, &tx.to, &tx.asset, tx.amount, tx.tip, tx.nonce);
+        if let Err(e) = self.coin_issue.issue_coins(transaction_hash.clone(), tx.nonce, tx.amount, &self.roles, &tx.from, &signed_data, &tx.signatures) {
+            self.rollback(journal);
+            return Err(e.into());
+        }
+
+        let timestamp = current_timestamp();
+        self.record_history(&tx, &transaction_hash, timestamp);
+        self.events.publish(LedgerEvent::CoinsIssued {
+            to: tx.to.clone(),
+            amount: tx.amount,
+            total_supply: self.coin_issue.total_supply(),
+        });
+        self.pending_transactions.push(concensus::Transaction {
+            kind: TransactionKind::Issuance,
+            from: tx.from.clone(),
+            to: tx.to.clone(),
+            amount: tx.amount,
+            asset: tx.asset.clone(),
+            tip: Amount::ZERO,
+            nonce: tx.nonce,
+            signatures: tx.signatures.clone(),
+        });
+
+        Ok(Receipt {
+            transaction_hash,
+            from: tx.from,
+            to: tx.to,
+            asset: tx.asset,
+            amount: tx.amount,
+            fee: Amount::ZERO,
+            base_fee: Amount::ZERO,
+            tip: Amount::ZERO,
+            timestamp,
+        })
+    }
+
+    /// Issues units of a registered asset; only its issuer may do so.
+    #[instrument(name = "ledger.issue_asset", skip_all, fields(asset = %asset_id, issuer = %issuer, to = %to, amount = %amount))]
+    pub fn issue_asset(&mut self, asset_id: &AssetId, issuer: &String, to: &String, amount: Amount, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        if amount.is_zero() {
+            return Err(LedgerError::InvalidAmount);
+        }
+        self.assets.issue(&mut self.accounts, asset_id, issuer, to, amount, nonce, signatures)?;
+        let transaction_hash = self.push_transaction(TransactionKind::AssetIssuance, issuer, to, asset_id, amount, nonce, signatures);
+        self.record_movement(HistoryEntryKind::Issuance, issuer, to, asset_id, amount, &transaction_hash, current_timestamp());
+        self.events.publish(LedgerEvent::AssetIssued { asset: asset_id.clone(), to: to.clone(), amount });
+        Ok(())
+    }
+
+    /// Mints a non-fungible item owned by `minter`.
+    #[instrument(name = "ledger.mint_nft", skip_all, fields(minter = %minter))]
+    pub fn mint_nft(&mut self, minter: &String, item_id: ItemId, metadata_hash: String, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        let kind = NftRegistry::mint_kind(&metadata_hash)?;
+        self.nfts.mint(&mut self.accounts, &self.validator, minter, item_id.clone(), metadata_hash, nonce, signatures, current_timestamp())?;
+        let transaction_hash = self.push_transaction(kind, minter, minter, &item_id, Amount::ZERO, nonce, signatures);
+        self.events.publish(LedgerEvent::NftMinted { item: item_id, owner: minter.clone(), transaction_hash });
+        Ok(())
+    }
+
+    /// Transfers a non-fungible item through the same validation as coin transfers.
+    #[instrument(name = "ledger.transfer_nft", skip_all, fields(from = %from, to = %to))]
+    pub fn transfer_nft(&mut self, from: &String, to: &String, item_id: &ItemId, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        self.nfts.transfer(&mut self.accounts, &self.validator, from, to, item_id, nonce, signatures, current_timestamp())?;
+        let transaction_hash = self.push_transaction(TransactionKind::NftTransfer, from, to, item_id, Amount::ZERO, nonce, signatures);
+        self.events.publish(LedgerEvent::NftTransferred { item: item_id.clone(), from: from.clone(), to: to.clone(), transaction_hash });
+        Ok(())
+    }
+
+    /// Locks `schedule.total` of the grantor's LUKS in a tranche of `beneficiary`, released
+    /// on the schedule. The grantor signs `VestingSchedule::grant_data`; the grant is a
+    /// block transaction like a transfer, but pays no fee.
+    #[instrument(name = "ledger.grant_vesting", skip_all, fields(grantor = %grantor, beneficiary = %beneficiary, amount = %schedule.total))]
+    pub fn grant_vesting(&mut self, grantor: &String, beneficiary: &String, schedule: VestingSchedule, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        if grantor == beneficiary {
+            return Err(LedgerError::Validation(ValidationError::InvalidRecipient));
+        }
+        let data = schedule.grant_data(grantor, beneficiary, nonce);
+        self.validator.check_authorization(&self.accounts, grantor, beneficiary, &data, nonce, signatures)?;
+        let (kind, amount) = (schedule.grant_kind(), schedule.total);
+        self.accounts.grant_vesting(grantor, beneficiary, schedule)?;
+        self.accounts.increment_nonce(grantor)?;
+
+        let asset = NATIVE_ASSET.to_string();
+        let transaction_hash = self.push_transaction(kind, grantor, beneficiary, &asset, amount, nonce, signatures);
+        self.record_movement(HistoryEntryKind::Vesting, grantor, beneficiary, &asset, amount, &transaction_hash, current_timestamp());
+        self.events.publish(LedgerEvent::VestingGranted { grantor: grantor.clone(), beneficiary: beneficiary.clone(), amount, transaction_hash });
+        Ok(())
+    }
+
+    // Queues an operation applied outside `apply` for the next block and returns its hash.
+    #[allow(clippy::too_many_arguments)]
+    fn push_transaction(&mut self, kind: TransactionKind, from: &str, to: &str, asset: &AssetId, amount: Amount, nonce: u64, signatures: &[AccountSignature]) -> String {
+        let transaction_hash = self.validator.generate_transaction_hash(&kind, from, to, asset, amount, Amount::ZERO, nonce);
+        self.pending_transactions.push(concensus::Transaction {
+            kind,
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+            asset: asset.clone(),
+            tip: Amount::ZERO,
+            nonce,
+            signatures: signatures.to_vec(),
+        });
+        transaction_hash
+    }
+
+    /// Rotates an account key, recording the rotation at the height of the block being built.
+    #[instrument(name = "ledger.rotate_key", skip_all, fields(account = %account_id))]
+    pub fn rotate_key(&mut self, account_id: &String, new_key: PublicKey, signature: &AccountSignature) -> Result<(), LedgerError> {
+        let height = self.consensus.height() + 1;
+        self.accounts.rotate_key(account_id, new_key, signature, current_timestamp(), height)?;
+        self.events.publish(LedgerEvent::KeyRotated { account: account_id.clone() });
+        Ok(())
+    }
+
+    /// Closes `account_id` at its own request, sweeping whatever it holds to `sweep_to`.
+    /// The id is tombstoned and its history stays queryable.
+    #[instrument(name = "ledger.close_account", skip_all, fields(account = %account_id, sweep_to = ?sweep_to))]
+    pub fn close_account(&mut self, account_id: &String, sweep_to: Option<&String>, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        self.check_closable(account_id)?;
+        let data = Accounts::closure_data(account_id, sweep_to, nonce);
+        self.validator.check_authorization(&self.accounts, account_id, sweep_to.unwrap_or(account_id), &data, nonce, signatures)?;
+        let swept = self.accounts.close_account(account_id, sweep_to, current_timestamp())?;
+        self.recovery.forget(account_id);
+        let transaction_hash = self.validator.security().hash(&data);
+        self.record_sweep(account_id, sweep_to, &swept, &transaction_hash);
+        self.events.publish(LedgerEvent::AccountClosed { account: account_id.clone(), swept_to: sweep_to.cloned() });
+        Ok(())
+    }
+
+    /// Closes an account that cannot sign for itself; requires the `DeleteAccount` permission
+    /// and the caller's signatures over `Accounts::deletion_data`.
+    #[instrument(name = "ledger.delete_account", skip_all, fields(caller = %caller, account = %account_id, sweep_to = ?sweep_to))]
+    pub fn delete_account(&mut self, caller: &String, account_id: &String, sweep_to: Option<&String>, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        self.check_closable(account_id)?;
+        let data = Accounts::deletion_data(caller, account_id, sweep_to, nonce);
+        self.authorize(caller, Permission::DeleteAccount, &data, nonce, signatures)?;
+        let swept = self.accounts.delete_account(&self.roles, caller, account_id, sweep_to, current_timestamp())?;
+        self.accounts.increment_nonce(caller)?;
+        self.recovery.forget(account_id);
+        let transaction_hash = self.validator.security().hash(&data);
+        self.record_sweep(account_id, sweep_to, &swept, &transaction_hash);
+        self.events.publish(LedgerEvent::AccountClosed { account: account_id.clone(), swept_to: sweep_to.cloned() });
+        Ok(())
+    }
+
+    pub fn recovery(&self) -> &Recovery {
+        &self.recovery
+    }
+
+    /// Nominates the guardians of `account_id`, which signs `Recovery::guardians_data`.
+    #[instrument(name = "ledger.set_guardians", skip_all, fields(account = %account_id))]
+    pub fn set_guardians(&mut self, account_id: &String, config: GuardianConfig, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        self.recovery.set_guardians(&mut self.accounts, account_id, config, signatures)?;
+        Ok(())
+    }
+
+    /// Starts a guardian recovery of `account_id` towards `new_key`; the guardian signs
+    /// `Recovery::approval_data`. The account can still receive but not send until the
+    /// recovery is finalised or cancelled.
+    #[instrument(name = "ledger.initiate_recovery", skip_all, fields(account = %account_id, guardian = %guardian_id))]
+    pub fn initiate_recovery(&mut self, account_id: &String, guardian_id: &String, new_key: PublicKey, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        let data = Recovery::approval_data(account_id, &new_key, self.accounts.get_nonce(guardian_id)?);
+        let now = current_timestamp();
+        self.recovery.initiate_recovery(&mut self.accounts, account_id, guardian_id, new_key, signatures, now)?;
+        self.record_recovery(HistoryEntryKind::RecoveryStarted, Some(guardian_id), Some(account_id), &data, now);
+        self.events.publish(LedgerEvent::AccountLocked { account: account_id.clone() });
+        Ok(())
+    }
+
+    /// Adds the approval of another guardian to the recovery of `account_id`.
+    #[instrument(name = "ledger.approve_recovery", skip_all, fields(account = %account_id, guardian = %guardian_id))]
+    pub fn approve_recovery(&mut self, account_id: &String, guardian_id: &String, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        let nonce = self.accounts.get_nonce(guardian_id)?;
+        self.recovery.approve_recovery(&mut self.accounts, account_id, guardian_id, signatures)?;
+        let new_key = self.recovery.get_pending_recovery(account_id).map(|pending| pending.new_key.clone());
+        if let Some(new_key) = new_key {
+            let data = Recovery::approval_data(account_id, &new_key, nonce);
+            self.record_recovery(HistoryEntryKind::RecoveryApproved, Some(guardian_id), Some(account_id), &data, current_timestamp());
+        }
+        Ok(())
+    }
+
+    /// Cancels the recovery of `account_id`, which signs `Recovery::cancel_data` with its current key.
+    #[instrument(name = "ledger.cancel_recovery", skip_all, fields(account = %account_id))]
+    pub fn cancel_recovery(&mut self, account_id: &String, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        let data = Recovery::cancel_data(account_id, self.accounts.get_nonce(account_id)?);
+        self.recovery.cancel_recovery(&mut self.accounts, account_id, signatures)?;
+        self.record_recovery(HistoryEntryKind::RecoveryCancelled, Some(account_id), None, &data, current_timestamp());
+        self.publish_unfrozen(account_id);
+        Ok(())
+    }
+
+    /// Installs the recovered key once enough guardians approved and the delay has passed.
+    /// The new key signs `Recovery::finalize_data`.
+    #[instrument(name = "ledger.finalize_recovery", skip_all, fields(account = %account_id))]
+    pub fn finalize_recovery(&mut self, account_id: &String, signature: &AccountSignature) -> Result<(), LedgerError> {
+        let nonce = self.accounts.get_nonce(account_id)?;
+        let new_key = self.recovery.get_pending_recovery(account_id).map(|pending| pending.new_key.clone()).ok_or(RecoveryError::NoRecoveryInProgress)?;
+        let now = current_timestamp();
+        let height = self.consensus.height() + 1;
+        self.recovery.finalize_recovery(&mut self.accounts, account_id, signature, now, height)?;
+        let data = Recovery::finalize_data(account_id, &new_key, nonce);
+        self.record_recovery(HistoryEntryKind::KeyRecovered, None, Some(account_id), &data, now);
+        self.events.publish(LedgerEvent::KeyRotated { account: account_id.clone() });
+        self.publish_unfrozen(account_id);
+        Ok(())
+    }
+
+    // Announces that an account can send again, unless it is still locked by compliance.
+    fn publish_unfrozen(&mut self, account_id: &String) {
+        if self.accounts.is_account_locked(account_id).is_ok() {
+            self.events.publish(LedgerEvent::AccountUnlocked { account: account_id.clone() });
+        }
+    }
+
+    fn check_closable(&self, account_id: &String) -> Result<(), LedgerError> {
+        if account_id == &self.fees_account {
+            return Err(LedgerError::TreasuryCannotClose);
+        }
+        if account_id == self.coin_issue.central_bank() {
+            return Err(LedgerError::CentralBankCannotClose);
+        }
+        if self.recovery.is_guardian(account_id) {
+            return Err(LedgerError::GuardianCannotClose);
+        }
+        if !self.nfts.items_owned_by(account_id).is_empty() {
+            return Err(LedgerError::AccountHoldsItems);
+        }
+        Ok(())
+    }
+
+    pub fn roles(&self) -> &Roles {
+        &self.roles
+    }
+
+    // Checks that `caller` holds `permission` and proved who it is: its account keys signed
+    // `data` at its next nonce. The caller consumes the nonce once the operation succeeded.
+    pub(crate) fn authorize(&self, caller: &String, permission: Permission, data: &String, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        self.roles.check_permission(caller, permission)?;
+        self.validator.check_authorization(&self.accounts, caller, caller, data, nonce, signatures)?;
+        Ok(())
+    }
+
+    /// Grants `role` to `account`; the caller signs `Roles::grant_data` and needs `ManageRoles`.
+    #[instrument(name = "ledger.grant_role", skip_all, fields(caller = %caller, account = %account, role = ?role))]
+    pub fn grant_role(&mut self, caller: &String, account: &String, role: Role, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        address::validate_address(account)?;
+        self.authorize(caller, Permission::ManageRoles, &Roles::grant_data(caller, account, role, nonce), nonce, signatures)?;
+        self.roles.grant_role(caller, account, role)?;
+        self.accounts.increment_nonce(caller)?;
+        self.events.publish(LedgerEvent::RoleGranted { account: account.clone(), role });
+        Ok(())
+    }
+
+    /// Revokes `role` from `account`; the caller signs `Roles::revoke_data` and needs `ManageRoles`.
+    #[instrument(name = "ledger.revoke_role", skip_all, fields(caller = %caller, account = %account, role = ?role))]
+    pub fn revoke_role(&mut self, caller: &String, account: &String, role: Role, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        self.authorize(caller, Permission::ManageRoles, &Roles::revoke_data(caller, account, role, nonce), nonce, signatures)?;
+        self.roles.revoke_role(caller, account, role)?;
+        self.accounts.increment_nonce(caller)?;
+        self.events.publish(LedgerEvent::RoleRevoked { account: account.clone(), role });
+        Ok(())
+    }
+
+    // Moves the owner role from `previous` to `new_owner` once a two-step ownership transfer
+    // was proposed and accepted, each step signed by its side.
+    pub(crate) fn hand_over_ownership(&mut self, previous: &String, new_owner: &String) -> Result<(), LedgerError> {
+        if !self.roles.has_role(new_owner, Role::Owner) {
+            self.roles.grant_role(previous, new_owner, Role::Owner)?;
+            self.events.publish(LedgerEvent::RoleGranted { account: new_owner.clone(), role: Role::Owner });
+        }
+        if self.roles.has_role(previous, Role::Owner) {
+            self.roles.revoke_role(new_owner, previous, Role::Owner)?;
+            self.events.publish(LedgerEvent::RoleRevoked { account: previous.clone(), role: Role::Owner });
+        }
+        Ok(())
+    }
+
+    /// Locks `account_id`; the caller signs `Accounts::lock_data` and needs `LockAccount`.
+    #[instrument(name = "ledger.lock_account", skip_all, fields(caller = %caller, account = %account_id))]
+    pub fn lock_account(&mut self, caller: &String, account_id: &String, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        self.authorize(caller, Permission::LockAccount, &Accounts::lock_data(caller, account_id, true, nonce), nonce, signatures)?;
+        self.accounts.lock_account(&self.roles, caller, account_id)?;
+        self.accounts.increment_nonce(caller)?;
+        self.events.publish(LedgerEvent::AccountLocked { account: account_id.clone() });
+        Ok(())
+    }
+
+    #[instrument(name = "ledger.unlock_account", skip_all, fields(caller = %caller, account = %account_id))]
+    pub fn unlock_account(&mut self, caller: &String, account_id: &String, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        self.authorize(caller, Permission::LockAccount, &Accounts::lock_data(caller, account_id, false, nonce), nonce, signatures)?;
+        self.accounts.unlock_account(&self.roles, caller, account_id)?;
+        self.accounts.increment_nonce(caller)?;
+        self.events.publish(LedgerEvent::AccountUnlocked { account: account_id.clone() });
+        Ok(())
+    }
+
+    pub fn fee_policy(&self) -> &FeePolicy {
+        &self.fee_policy
+    }
+
+    /// Replaces the fee policy; the caller signs `FeePolicy::update_data` and needs `ConfigureFees`.
+    #[instrument(name = "ledger.set_fee_policy", skip_all, fields(caller = %caller))]
+    pub fn set_fee_policy(&mut self, caller: &String, fee_policy: FeePolicy, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        self.authorize(caller, Permission::ConfigureFees, &FeePolicy::update_data(caller, &fee_policy, nonce), nonce, signatures)?;
+        self.fee_policy = fee_policy;
+        self.accounts.increment_nonce(caller)?;
+        Ok(())
+    }
+
+    /// Registers an asset; the caller signs `AssetRegistry::registration_data` and needs `RegisterAsset`.
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(name = "ledger.register_asset", skip_all, fields(caller = %caller, asset = %id))]
+    pub fn register_asset(&mut self, caller: &String, id: AssetId, symbol: String, decimals: u8, issuer: String, supply_cap: Option<Amount>, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LedgerError> {
+        let data = AssetRegistry::registration_data(caller, &id, &symbol, decimals, &issuer, supply_cap, nonce);
+        self.authorize(caller, Permission::RegisterAsset, &data, nonce, signatures)?;
+        let kind = AssetRegistry::registration_kind(&symbol, decimals, supply_cap);
+        self.assets.register_asset(&mut self.accounts, id.clone(), symbol, decimals, issuer.clone(), supply_cap)?;
+        self.accounts.increment_nonce(caller)?;
+        self.push_transaction(kind, caller, &issuer, &id, Amount::ZERO, nonce, signatures);
+        Ok(())
+    }
+
+    /// Searches the full history. Accounts may read their own movements; anything wider
+    /// needs a role allowed to view history, such as an auditor. Either way the caller
+    /// signs `HistoryIndex::query_data` at its next nonce.
+    pub fn query_history(&self, caller: &String, filter: &HistoryFilter, after: Option<HistoryCursor>, limit: usize, signatures: &[AccountSignature]) -> Result<HistoryPage, LedgerError> {
+        let nonce = self.accounts.get_nonce(caller)?;
+        let data = HistoryIndex::query_data(caller, nonce);
+        if filter.account.as_ref() == Some(caller) {
+            self.validator.check_authorization(&self.accounts, caller, caller, &data, nonce, signatures)?;
+        } else {
+            self.authorize(caller, Permission::ViewHistory, &data, nonce, signatures)?;
+        }
+        Ok(self.history.query(filter, after, limit))
+    }
+
+    /// Seals every applied transaction into a new block produced by `producer`, credits
+    /// it the collected tips, and durably commits the resulting state, so a restart
+    /// resumes exactly from this block. The base fee then adjusts to the block's fullness.
+    #[instrument(name = "ledger.commit_block", skip_all, fields(producer = %producer, transactions = self.pending_transactions.len()))]
+    pub fn commit_block(&mut self, store: &mut dyn StateStore, producer: &String) -> Result<(), LedgerError> {
+        let _timer = metrics().block_production_seconds.start_timer();
+        if self.accounts.get_account_details(producer).is_err() {
+            return Err(LedgerError::ProducerNotFound);
+        }
+        let tips = std::mem::take(&mut self.pending_tips);
+        let mut journal: Vec<(String, AssetId, BalanceChange)> = Vec::new();
+        for (asset, tip) in &tips {
+            if let Err(e) = self.move_balance(&mut journal, producer, asset, BalanceChange::Credit(*tip)) {
+                self.rollback(journal);
+                self.pending_tips = tips;
+                return Err(e.into());
+            }
+        }
+        // Fully vested schedules leave the state with the block, never in the middle of one.
+        let pruned = self.accounts.prune_vested(current_timestamp());
+
+        // The pending transactions stay queued until the block is committed.
+        let block_transactions = self.pending_transactions.len();
+        let state_root = hex::encode(self.accounts.state_root());
+        let proposed = match self.consensus.propose_block(self.pending_transactions.clone(), state_root, self.fee_market.base_fee(), producer.clone(), &mut self.events) {
+            Ok(proposed) => proposed,
+            Err(e) => {
+                self.accounts.restore_vesting(pruned);
+                self.rollback(journal);
+                self.pending_tips = tips;
+                return Err(e.into());
+            }
+        };
+
+        // The block is durably committed before anything else in memory changes, so a
+        // failed write leaves the ledger as it was before the call.
+        if let Err(e) = self.persist_block(store, &proposed.block, producer) {
+            store.discard();
+            self.accounts.restore_vesting(pruned);
+            self.rollback(journal);
+            self.pending_tips = tips;
+            return Err(e);
+        }
+        let height = proposed.block.index;
+        let block_hash = proposed.block.block_hash.clone();
+        self.consensus.append_block(proposed);
+        self.pending_transactions.clear();
+        self.accounts.checkpoint(height);
+        if height % STATE_PRUNE_INTERVAL == 0 && height > self.state_retention {
+            self.accounts.prune_state_history(height - self.state_retention);
+        }
+        self.fee_market.adjust(block_transactions);
+        self.history.seal(producer);
+
+        self.events.publish(LedgerEvent::BlockCommitted {
+            height,
+            block_hash,
+            transactions: block_transactions,
+            producer: producer.clone(),
+        });
+        self.update_gauges();
+        Ok(())
+    }
+
+    // Writes the state resulting from `block` and commits it at the block's height.
+    fn persist_block(&self, store: &mut dyn StateStore, block: &concensus::Block, producer: &str) -> Result<(), LedgerError> {
+        self.accounts.persist(store)?;
+        Consensus::persist_block(block, store);
+        self.history.persist(store, producer)?;
+        self.roles.persist(store)?;
+        self.coin_issue.persist(store)?;
+        self.assets.persist(store)?;
+        self.nfts.persist(store)?;
+        self.recovery.persist(store)?;
+        self.fee_policy.persist(store)?;
+        if let Some(notifier) = &self.notifier {
+            notifier.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).persist(store)?;
+        }
+        store.commit(block.index)?;
+        Ok(())
+    }
+
+    fn update_gauges(&self) {
+        let metrics = metrics();
+        metrics.mempool_size.set(self.pending_transactions.len() as u64);
+        metrics.total_supply.set(self.coin_issue.total_supply().units());
+        metrics.total_burned.set(self.coin_issue.total_burned().units());
+        metrics.base_fee.set(self.fee_market.base_fee().units());
+    }
+
+    fn move_balance(&mut self, journal: &mut Vec<(String, AssetId, BalanceChange)>, account_id: &String, asset: &AssetId, change: BalanceChange) -> Result<(), AccountError> {
+        match change {
+            BalanceChange::Credit(amount) => self.accounts.credit_asset_balance(account_id, asset, amount)?,
+            BalanceChange::Debit(amount) => self.accounts.debit_asset_balance(account_id, asset, amount)?,
+        }
+        journal.push((account_id.clone(), asset.clone(), change));
+        Ok(())
+    }
+
+    fn rollback(&mut self, journal: Vec<(String, AssetId, BalanceChange)>) {
+        for (account_id, asset, change) in journal.into_iter().rev() {
+            // Reverting a change that was just applied cannot fail.
+            let _ = match change {
+                BalanceChange::Credit(amount) => self.accounts.debit_asset_balance(&account_id, &asset, amount),
+                BalanceChange::Debit(amount) => self.accounts.credit_asset_balance(&account_id, &asset, amount),
+            };
+        }
+    }
+
+    // Records the transfer or issuance in the account histories and the history index.
+    fn record_history(&mut self, tx: &LedgerTransaction, transaction_hash: &str, timestamp: u64) {
+        let kind = match tx.kind {
+            LedgerTransactionKind::Transfer => HistoryEntryKind::Transfer,
+            LedgerTransactionKind::Issuance => HistoryEntryKind::Issuance,
+        };
+        self.record_movement(kind, &tx.from, &tx.to, &tx.asset, tx.amount, transaction_hash, timestamp);
+    }
+
+    // Records a balance movement in both account histories and the history index.
+    #[allow(clippy::too_many_arguments)]
+    fn record_movement(&mut self, kind: HistoryEntryKind, from: &String, to: &String, asset: &AssetId, amount: Amount, transaction_hash: &str, timestamp: u64) {
+        self.history.record(kind, Some(from), Some(to), asset, amount, timestamp, transaction_hash);
+
+        let entry = TransactionHistory {
+            from: from.clone(),
+            to: to.clone(),
+            amount,
+            timestamp,
+            asset: asset.clone(),
+        };
+        // Both accounts were checked to exist before any balance moved.
+        let _ = self.accounts.add_transaction(from, entry.clone());
+        let _ = self.accounts.add_transaction(to, entry);
+    }
+
+    // Records the balances moved out of a closed account.
+    fn record_sweep(&mut self, account_id: &String, sweep_to: Option<&String>, swept: &[(AssetId, Amount)], transaction_hash: &str) {
+        let Some(target) = sweep_to else { return };
+        let timestamp = current_timestamp();
+        for (asset, amount) in swept {
+            self.history.record(HistoryEntryKind::Sweep, Some(account_id), Some(target), asset, *amount, timestamp, transaction_hash);
+            let entry = TransactionHistory {
+                from: account_id.clone(),
+                to: target.clone(),
+                amount: *amount,
+                timestamp,
+                asset: asset.clone(),
+            };
+            // The closed account keeps its history in the tombstone; only the target records it.
+            let _ = self.accounts.add_transaction(target, entry);
+        }
+    }
+
+    // Records a step of a guardian recovery; it moves no balance.
+    fn record_recovery(&mut self, kind: HistoryEntryKind, from: Option<&String>, to: Option<&String>, data: &String, timestamp: u64) {
+        let transaction_hash = self.validator.security().hash(data);
+        self.history.record(kind, from, to, &NATIVE_ASSET.to_string(), Amount::ZERO, timestamp, &transaction_hash);
+    }
+
+    // Records what a transfer paid on top of its amount: policy fee, burned base fee and tip.
+    fn record_fees(&mut self, tx: &LedgerTransaction, quote: &FeeQuote, transaction_hash: &str, timestamp: u64) {
+        let fees_account = self.fees_account.clone();
+        let charges = [
+            (HistoryEntryKind::Fee, Some(&fees_account), quote.fee),
+            (HistoryEntryKind::Burn, None, quote.base_fee),
+            (HistoryEntryKind::Tip, None, quote.tip),
+        ];
+        for (kind, to, amount) in charges {
+            if !amount.is_zero() {
+                self.history.record(kind, Some(&tx.from), to, &tx.asset, amount, timestamp, transaction_hash);
+            }
+        }
+    }
+}
+
+fn current_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::MultiSigPolicy;
+    use crate::storage::MemoryStore;
+    use crate::testing::{self, ALICE, BOB, OWNER};
+
+    // Store whose commits always fail, like a full disk.
+    struct FailingStore(MemoryStore);
+
+    impl StateStore for FailingStore {
+        fn get(&self, key: &str) -> Option<Vec<u8>> {
+            self.0.get(key)
+        }
+
+        fn put(&mut self, key: &str, value: Vec<u8>) {
+            self.0.put(key, value)
+        }
+
+        fn delete(&mut self, key: &str) {
+            self.0.delete(key)
+        }
+
+        fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+            self.0.keys_with_prefix(prefix)
+        }
+
+        fn commit(&mut self, _height: u64) -> Result<(), StorageError> {
+            Err(StorageError::Io(std::io::Error::other("disk full")))
+        }
+
+        fn committed_height(&self) -> Option<u64> {
+            self.0.committed_height()
+        }
+
+        fn discard(&mut self) {
+            self.0.discard()
+        }
+    }
+
+    fn balances(ledger: &Ledger) -> Vec<Amount> {
+        [testing::address(&ALICE), testing::address(&BOB), ledger.fees_account.clone()]
+            .iter()
+            .map(|account| ledger.accounts.get_balance(account).unwrap_or(Amount::ZERO))
+            .collect()
+    }
+
+    #[test]
+    fn failed_block_commit_leaves_the_ledger_unchanged() {
+        let mut ledger = testing::ledger();
+        let tx = testing::transfer(&ledger, &ALICE, &testing::address(&BOB), Amount::from_units(100));
+        assert!(ledger.apply(tx).is_ok());
+
+        let before = balances(&ledger);
+        let root = ledger.accounts.state_root();
+        let height = ledger.consensus.blockchain.len();
+        let producer = testing::address(&BOB);
+        let mut failing = FailingStore(MemoryStore::new());
+        assert!(matches!(ledger.commit_block(&mut failing, &producer), Err(LedgerError::Storage(_))));
+        assert_eq!(balances(&ledger), before);
+        assert_eq!(ledger.accounts.state_root(), root);
+        assert_eq!(ledger.consensus.blockchain.len(), height);
+        assert_eq!(ledger.pending_transactions.len(), 1);
+        assert_eq!(ledger.pending_tips.get(NATIVE_ASSET), Some(&Amount::from_units(10)));
+
+        // Nothing was lost: the same block commits once the store works again.
+        let mut store = MemoryStore::new();
+        assert!(ledger.commit_block(&mut store, &producer).is_ok());
+        assert_eq!(ledger.consensus.blockchain.len(), height + 1);
+        assert_eq!(ledger.accounts.get_balance(&producer).ok(), Some(before[1].saturating_add(Amount::from_units(10))));
+        assert!(ledger.pending_transactions.is_empty());
+        assert_eq!(store.committed_height(), Some(height as u64));
+    }
+
+    #[test]
+    fn failed_transfer_rolls_back_every_balance() {
+        // The credit to bob overflows after alice has already been debited.
+        let mut accounts = testing::accounts();
+        assert!(accounts.credit_balance(&testing::address(&BOB), Amount::MAX.saturating_sub(testing::INITIAL_BALANCE)).is_ok());
+        let mut ledger = testing::ledger_with(accounts);
+        let before = balances(&ledger);
+        let root = ledger.accounts.state_root();
+        let supply = ledger.coin_issue.total_supply();
+
+        let tx = testing::transfer(&ledger, &ALICE, &testing::address(&BOB), Amount::from_units(100));
+        assert!(matches!(ledger.apply(tx), Err(LedgerError::Account(AccountError::BalanceOverflow))));
+        assert_eq!(balances(&ledger), before);
+        assert_eq!(ledger.accounts.state_root(), root);
+        assert_eq!(ledger.accounts.get_nonce(&testing::address(&ALICE)).ok(), Some(0));
+        assert_eq!(ledger.coin_issue.total_supply(), supply);
+        assert!(ledger.pending_transactions.is_empty());
+        assert!(ledger.pending_tips.is_empty());
+    }
+
+    #[test]
+    fn fully_vested_schedules_are_pruned_with_the_block_only() {
+        // Alice holds a schedule that is already fully vested, and bob cannot receive more.
+        let (alice, bob) = (testing::address(&ALICE), testing::address(&BOB));
+        let mut accounts = testing::accounts();
+        let schedule = VestingSchedule::new(Amount::from_units(1_000), 0, 0, 1).ok().unwrap();
+        assert!(accounts.grant_vesting(&testing::address(&OWNER), &alice, schedule).is_ok());
+        assert!(accounts.credit_balance(&bob, Amount::MAX.saturating_sub(testing::INITIAL_BALANCE)).is_ok());
+        let mut ledger = testing::ledger_with(accounts);
+        let root = ledger.accounts.state_root();
+
+        // Alice's debit succeeds before the credit to bob fails; the rollback restores everything.
+        let tx = testing::transfer(&ledger, &ALICE, &bob, Amount::from_units(100));
+        assert!(matches!(ledger.apply(tx), Err(LedgerError::Account(AccountError::BalanceOverflow))));
+        assert_eq!(ledger.accounts.state_root(), root);
+
+        let mut failing = FailingStore(MemoryStore::new());
+        assert!(ledger.commit_block(&mut failing, &alice).is_err());
+        assert_eq!(ledger.accounts.state_root(), root);
+        assert_eq!(ledger.accounts.get_account_details(&alice).map(|account| account.vesting.len()).ok(), Some(1));
+
+        let mut store = MemoryStore::new();
+        assert!(ledger.commit_block(&mut store, &alice).is_ok());
+        assert_eq!(ledger.accounts.get_account_details(&alice).map(|account| account.vesting.len()).ok(), Some(0));
+        assert_ne!(ledger.accounts.state_root(), root);
+    }
+
+    #[test]
+    fn accounts_that_others_depend_on_cannot_close() {
+        // A central bank apart from the treasury, controlled by alice and bob.
+        let (owner, alice, bob, carol) = (testing::address(&OWNER), testing::address(&ALICE), testing::address(&BOB), testing::address(&testing::CAROL));
+        let policy = MultiSigPolicy::new(vec![testing::public_key(&ALICE), testing::public_key(&BOB)], 2).ok().unwrap();
+        let central_bank = address::LuksAddress::from_multisig(&policy).to_string();
+        let mut accounts = testing::accounts();
+        assert!(accounts.create_multisig_account(central_bank.clone(), Amount::ZERO, policy.clone()).is_ok());
+        let coin_issue = CoinIssue::new(testing::INITIAL_SUPPLY, Amount::ONE, 0, Amount::MAX, central_bank.clone(), policy).ok().unwrap();
+        let validator = ValidateTransaction::new(Security::new());
+        let mut ledger = Ledger::new(accounts, validator, coin_issue, Consensus::new("00".repeat(32)), testing::treasury(), Roles::new(&owner)).ok().unwrap();
+
+        let delete = |ledger: &mut Ledger, account: &String| {
+            let data = Accounts::deletion_data(&owner, account, Some(&bob), 0);
+            ledger.delete_account(&owner, account, Some(&bob), 0, &[testing::sign(&OWNER, &data)])
+        };
+        assert!(matches!(delete(&mut ledger, &testing::treasury()), Err(LedgerError::TreasuryCannotClose)));
+        assert!(matches!(delete(&mut ledger, &central_bank), Err(LedgerError::CentralBankCannotClose)));
+
+        // Carol guards alice, so she stays open as long as alice does.
+        let config = GuardianConfig { guardians: vec![carol.clone()], threshold: 1, delay_secs: 0 };
+        let signature = testing::sign(&ALICE, &Recovery::guardians_data(&alice, &config, 0));
+        assert!(ledger.set_guardians(&alice, config, &[signature]).is_ok());
+        let close = |ledger: &mut Ledger, key: &[u8; 32]| {
+            let account = testing::address(key);
+            let nonce = ledger.accounts.get_nonce(&account).unwrap_or(0);
+            let data = Accounts::closure_data(&account, Some(&bob), nonce);
+            ledger.close_account(&account, Some(&bob), nonce, &[testing::sign(key, &data)])
+        };
+        assert!(matches!(close(&mut ledger, &testing::CAROL), Err(LedgerError::GuardianCannotClose)));
+        assert!(ledger.accounts.get_account_details(&carol).is_ok());
+        assert!(close(&mut ledger, &ALICE).is_ok());
+        assert!(ledger.recovery().get_guardians(&alice).is_none());
+        assert!(close(&mut ledger, &testing::CAROL).is_ok());
+    }
+}
@@ -0,0 +1,29 @@
+// Crate root: every module of the Luks ledger and its main contract.
+
+pub mod accounts;
+pub mod transfer;
+pub mod validate_transaction;
+pub mod concensus;
+pub mod coin_issue;
+pub mod security;
+pub mod ledger;
+pub mod storage;
+pub mod merkle;
+pub mod recovery;
+pub mod vesting;
+pub mod assets;
+pub mod nft;
+pub mod address;
+pub mod amount;
+pub mod fees;
+pub mod history;
+pub mod roles;
+pub mod events;
+pub mod notifications;
+pub mod metrics;
+pub mod encoding;
+pub mod signature_scheme;
+pub mod luks_main_contract;
+
+#[cfg(test)]
+mod testing;
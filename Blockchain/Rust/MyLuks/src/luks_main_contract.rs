@@ -1,12 +1,17 @@
-// Importing necessary modules
-mod accounts;
-mod transfer;
-mod validate_transaction;
-mod consensus;
-mod coin_issue;
-mod security;
-
-use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::{accounts, assets, security};
+use crate::accounts::{AccountDetails, AccountSignature, TransactionHistory};
+use crate::concensus::TransactionKind;
+use crate::assets::AssetId;
+use crate::encoding::{Action, AuthorizationPayload};
+use crate::events::LedgerEvent;
+use crate::address::LuksAddress;
+use crate::amount::Amount;
+use crate::fees::FeeQuote;
+use crate::ledger::{Ledger, LedgerError, LedgerTransaction, LedgerTransactionKind, Receipt};
+use crate::validate_transaction::ValidateTransaction;
+use crate::roles::{Permission, Role};
 
 // Enum for error handling
 pub enum LuksError {
@@ -18,151 +23,452 @@ pub enum LuksError {
     Unauthorized,
     CoinIssueError,
     TransactionNotSecured,
+    InvalidAddress,
+    AmountOverflow,
+    InvalidFee,
+    NoPendingOwnershipTransfer,
+    OwnershipDelayNotElapsed,
+    InvalidSignature,
+    AccountClosed,
+    NonZeroBalance,
+    Ledger(LedgerError), // The ledger refused the operation; nothing was changed.
 }
 
-#[derive(Debug, PartialEq, Clone)]
+impl From<LedgerError> for LuksError {
+    fn from(error: LedgerError) -> Self {
+        LuksError::Ledger(error)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Address {
     pub value: String, // Represents the address on the blockchain.
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Account {
-    pub balance: u64,
-    pub public_key: Option<String>, // For better security
-    // Other fields can be added for more features (transaction history, private keys if needed, etc.)
+impl Address {
+    /// Parses a `luks1...` address, rejecting bad prefixes, characters and checksums.
+    pub fn parse(value: &str) -> Result<Self, LuksError> {
+        let address = LuksAddress::parse(value).map_err(|_| LuksError::InvalidAddress)?;
+        Ok(Address { value: address.to_string() })
+    }
+
+    pub fn is_valid(&self) -> bool {
+        LuksAddress::parse(&self.value).is_ok()
+    }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct TransactionRecord {
-    from: Address,
-    to: Address,
-    amount: u64,
-    timestamp: u64, // Use a timestamp to record when the transaction occurred.
+/// An ownership transfer proposed by the owner and not yet accepted by the new owner.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PendingOwnershipTransfer {
+    pub proposed_by: Address,
+    pub new_owner: Address,
+    pub proposed_at: u64,
+    pub accept_after: u64, // Earliest timestamp at which the new owner can accept.
 }
 
-#[derive(Debug, PartialEq)]
+/// Administrative front end of a `Ledger`. The contract keeps no balances of its own:
+/// issuance, transfers and closures are ledger transactions, so they are signed,
+/// journaled, charged and recorded exactly like any other. Its owners are the holders of
+/// the ledger's `Owner` role, and its events go to the ledger's subscribers.
 pub struct LuksMainContract {
-    pub owner: Address,
-    pub accounts: HashMap<Address, Account>,
-    pub consensus_module: consensus::Consensus,
+    pub ledger: Ledger, // Accounts, fees, history and roles
+    pub ownership_delay: u64, // Seconds between proposing and accepting a new owner
+    pub pending_ownership: Option<PendingOwnershipTransfer>,
 }
 
 impl LuksMainContract {
-    
-    // 1. Historial de Transacciones
-    pub accounts_transaction_history: HashMap<Address, Vec<TransactionRecord>>,
-
-    // 2. Tarifas por Transacción
-    const TRANSACTION_FEE: u64 = 10; // Let's say a flat fee of 10 units.
-    pub fees_account: Address,
-    
-    /// Creates a new account with an optional public key.
-    pub fn create_account(&mut self, address: Address, initial_balance: u64, public_key: Option<String>) -> Result<(), LuksError> {
-        if self.accounts.contains_key(&address) {
-            return Err(LuksError::AccountAlreadyExists);
+    /// Creates a contract over `ledger`, administered by its owners.
+    pub fn new(ledger: Ledger, ownership_delay: u64) -> Self {
+        LuksMainContract {
+            ledger,
+            ownership_delay,
+            pending_ownership: None,
         }
-        self.accounts.insert(address, Account { balance: initial_balance, public_key });
-        Ok(())
     }
 
-    /// Deletes an account; only the contract owner can perform this action.
-    pub fn delete_account(&mut self, address: &Address, caller: &Address) -> Result<(), LuksError> {
-        if &self.owner != caller {
-            return Err(LuksError::Unauthorized);
-        }
-        self.accounts.remove(address).ok_or(LuksError::AccountDeletionError)?;
-        Ok(())
+    /// Holders of the `Owner` role, in address order.
+    pub fn owners(&self) -> Vec<Address> {
+        let mut owners: Vec<Address> = self.ledger.roles().members(Role::Owner).into_iter().map(|value| Address { value: value.clone() }).collect();
+        owners.sort_by(|a, b| a.value.cmp(&b.value));
+        owners
     }
 
-    /// Retrieves account details for a given address.
-    pub fn get_account_details(&self, address: &Address) -> Option<&Account> {
-        self.accounts.get(address)
+    pub fn is_owner(&self, address: &Address) -> bool {
+        self.ledger.roles().has_role(&address.value, Role::Owner)
     }
 
-    /// Issues coins to a specified address.
-    pub fn issue_coins(&mut self, address: &Address, amount: u64) -> Result<(), LuksError> {
-        let account = self.accounts.get_mut(address).ok_or(LuksError::AccountNotFound)?;
-        account.balance += amount;
-        Ok(())
+    // Fails unless `caller` holds a role granting `permission`.
+    fn require(&self, caller: &Address, permission: Permission) -> Result<(), LuksError> {
+        self.ledger.roles().check_permission(&caller.value, permission).map_err(|_| LuksError::Unauthorized)
     }
 
-    /// Initiates a transfer from one account to another, ensuring security checks and consensus validations.
-    pub fn initiate_transfer(&mut self, from: &Address, to: &Address, amount: u64, signature: String) -> Result<(), LuksError> {
-        if !security::is_transaction_secured(from, &signature) {
-            return Err(LuksError::TransactionNotSecured);
-        }
+    // Fails unless `caller` holds `permission` and signed `data` at its next nonce, which
+    // is consumed when the whole operation succeeds.
+    fn authorize(&self, caller: &Address, permission: Permission, data: &String, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LuksError> {
+        self.ledger.authorize(&caller.value, permission, data, nonce, signatures).map_err(Self::role_error)
+    }
 
-        let is_valid = validate_transaction::validate(from, to, amount)?;
+    // A missing role is reported as `Unauthorized`, like before signatures were required.
+    fn role_error(error: LedgerError) -> LuksError {
+        match error {
+            LedgerError::Role(_) => LuksError::Unauthorized,
+            other => other.into(),
+        }
+    }
 
-        if !is_valid || !self.consensus_module.validate_transaction(&from, &to, amount) {
-            return Err(LuksError::InvalidTransaction);
+    /// Grants `role` to `account`; requires a role that manages roles (the owner) and its
+    /// signatures over `Roles::grant_data`.
+    pub fn grant_role(&mut self, caller: &Address, account: &Address, role: Role, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LuksError> {
+        if !account.is_valid() {
+            return Err(LuksError::InvalidAddress);
         }
-        
-        let sender = self.accounts.get_mut(from).ok_or(LuksError::AccountNotFound)?;
-        let receiver = self.accounts.get_mut(to).ok_or(LuksError::AccountNotFound)?;
+        self.ledger.grant_role(&caller.value, &account.value, role, nonce, signatures).map_err(Self::role_error)
+    }
+
+    /// Revokes `role` from `account`, signed over `Roles::revoke_data`. The last owner
+    /// cannot be removed.
+    pub fn revoke_role(&mut self, caller: &Address, account: &Address, role: Role, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LuksError> {
+        self.ledger.revoke_role(&caller.value, &account.value, role, nonce, signatures).map_err(Self::role_error)
+    }
+
+    /// Fee and total a sender will be charged for a transfer of `amount` with `tip`.
+    pub fn quote_fee(&self, amount: Amount, tip: Amount) -> Result<FeeQuote, LuksError> {
+        self.ledger.quote_fee(amount, tip).map_err(|_| LuksError::InvalidFee)
+    }
 
-        if sender.balance < amount {
-            return Err(LuksError::InsufficientBalance);
+    /// Creates an empty account controlled by `public_key` (`<scheme>:<hex>`, bare hex for
+    /// secp256k1); the address must be the one derived from it. Funds only arrive through
+    /// issuance or transfers.
+    pub fn create_account(&mut self, address: Address, public_key: &str) -> Result<(), LuksError> {
+        if !address.is_valid() {
+            return Err(LuksError::InvalidAddress);
         }
+        let key = accounts::PublicKey::parse_tagged(public_key).ok_or(LuksError::InvalidAddress)?;
+        self.ledger.accounts.create_account(address.value, Amount::ZERO, key).map_err(|e| match e {
+            accounts::AccountError::AccountAlreadyExists => LuksError::AccountAlreadyExists,
+            accounts::AccountError::AccountClosed => LuksError::AccountClosed,
+            _ => LuksError::InvalidAddress,
+        })
+    }
 
-        sender.balance -= amount;
-        receiver.balance += amount;
-        Ok(())
+    /// Closes an account at its own request; `signatures` cover `Accounts::closure_data`.
+    /// Its balances must be zero or are swept to `sweep_to`; its history is kept and the
+    /// address can never be used for a new account.
+    pub fn close_account(&mut self, address: &Address, sweep_to: Option<&Address>, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LuksError> {
+        Ok(self.ledger.close_account(&address.value, sweep_to.map(|target| &target.value), nonce, signatures)?)
+    }
+
+    /// Closes an account that cannot sign for itself; requires the `DeleteAccount` permission
+    /// and the caller's signatures over `Accounts::deletion_data`.
+    pub fn delete_account(&mut self, caller: &Address, address: &Address, sweep_to: Option<&Address>, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LuksError> {
+        Ok(self.ledger.delete_account(&caller.value, &address.value, sweep_to.map(|target| &target.value), nonce, signatures)?)
+    }
+
+    /// Retrieves account details for a given address.
+    pub fn get_account_details(&self, address: &Address) -> Option<&AccountDetails> {
+        self.ledger.accounts.get_account_details(&address.value).ok()
+    }
+
+    /// Balance of any asset held by an address.
+    pub fn get_asset_balance(&self, address: &Address, asset_id: &AssetId) -> Option<Amount> {
+        self.ledger.accounts.get_asset_balance(&address.value, asset_id).ok()
+    }
+
+    /// Issues the next emission to `to`. `caller` needs the `IssueCoins` permission and
+    /// `signatures` are the central bank's over the issuance at issue nonce `nonce`.
+    pub fn issue_coins(&mut self, caller: &Address, to: &Address, amount: Amount, nonce: u64, signatures: Vec<AccountSignature>) -> Result<Receipt, LuksError> {
+        self.require(caller, Permission::IssueCoins)?;
+        Ok(self.ledger.apply(LedgerTransaction {
+            kind: LedgerTransactionKind::Issuance,
+            from: caller.value.clone(),
+            to: to.value.clone(),
+            asset: assets::native_asset_id(),
+            amount,
+            tip: Amount::ZERO,
+            nonce,
+            signatures,
+        })?)
     }
 
-    // Retrieves transaction history for a given account.
-    pub fn get_transaction_history(&self, address: &Address) -> Option<&Vec<TransactionRecord>> {
-        self.accounts_transaction_history.get(address)
+    // Retrieves the last transactions of a given account.
+    pub fn get_transaction_history(&self, address: &Address) -> Option<&VecDeque<TransactionHistory>> {
+        self.get_account_details(address).map(|account| &account.transactions)
     }
 
     // 3. Cambio de Propietario
-    pub fn transfer_ownership(&mut self, new_owner: Address, caller: &Address) -> Result<(), LuksError> {
-        if &self.owner != caller {
+    // En dos pasos: el propietario propone y el nuevo propietario acepta con su firma,
+    // así un error en la dirección no deja el contrato sin administración.
+
+    // Data the owner signs to propose `new_owner`.
+    pub fn proposal_data(caller: &Address, new_owner: &Address, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::ProposeOwnership).string(&caller.value).string(&new_owner.value).u64(nonce).signing_data()
+    }
+
+    // Data the owner signs to withdraw the pending proposal.
+    pub fn cancellation_data(caller: &Address, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::CancelOwnership).string(&caller.value).u64(nonce).signing_data()
+    }
+
+    // Data the owner signs to change the ownership delay.
+    pub fn delay_data(caller: &Address, seconds: u64, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::SetOwnershipDelay).string(&caller.value).u64(seconds).u64(nonce).signing_data()
+    }
+
+    /// Proposes `new_owner`, who can accept once `ownership_delay` has passed. A previous
+    /// proposal still pending is cancelled. Signed by the caller over `proposal_data`.
+    pub fn propose_ownership_transfer(&mut self, new_owner: Address, caller: &Address, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LuksError> {
+        if !new_owner.is_valid() || self.is_owner(&new_owner) {
+            return Err(LuksError::InvalidAddress);
+        }
+        self.authorize(caller, Permission::TransferOwnership, &Self::proposal_data(caller, &new_owner, nonce), nonce, signatures)?;
+        self.ledger.accounts.increment_nonce(&caller.value).map_err(|_| LuksError::AccountNotFound)?;
+        if let Some(previous) = self.pending_ownership.take() {
+            self.ledger.publish(LedgerEvent::OwnershipTransferCancelled { owner: previous.proposed_by.value, new_owner: previous.new_owner.value });
+        }
+        let proposed_at = self.get_current_timestamp();
+        let accept_after = proposed_at.saturating_add(self.ownership_delay);
+        self.ledger.publish(LedgerEvent::OwnershipTransferProposed { owner: caller.value.clone(), new_owner: new_owner.value.clone(), accept_after });
+        self.pending_ownership = Some(PendingOwnershipTransfer { proposed_by: caller.clone(), new_owner, proposed_at, accept_after });
+        Ok(())
+    }
+
+    /// Data the proposed owner signs to accept. It names the proposal, so a signature
+    /// cannot be replayed against a later one.
+    pub fn ownership_acceptance_data(pending: &PendingOwnershipTransfer) -> String {
+        AuthorizationPayload::new(Action::AcceptOwnership)
+            .string(&pending.proposed_by.value)
+            .string(&pending.new_owner.value)
+            .u64(pending.proposed_at)
+            .signing_data()
+    }
+
+    /// Completes the pending transfer. `public_key` (`<scheme>:<hex>`, bare hex for secp256k1)
+    /// must be the key of the proposed owner's address and `signature` its signature over
+    /// `ownership_acceptance_data`.
+    pub fn accept_ownership(&mut self, caller: &Address, public_key: &str, signature: &String) -> Result<(), LuksError> {
+        let pending = self.pending_ownership.clone().ok_or(LuksError::NoPendingOwnershipTransfer)?;
+        if caller != &pending.new_owner {
             return Err(LuksError::Unauthorized);
         }
-        self.owner = new_owner;
+        if self.get_current_timestamp() < pending.accept_after {
+            return Err(LuksError::OwnershipDelayNotElapsed);
+        }
+        let key = accounts::PublicKey::parse_tagged(public_key).ok_or(LuksError::InvalidSignature)?;
+        if LuksAddress::from_public_key(&key).to_string() != pending.new_owner.value {
+            return Err(LuksError::InvalidSignature);
+        }
+        if !security::Security::verify_key_signature(&key, &Self::ownership_acceptance_data(&pending), signature) {
+            return Err(LuksError::InvalidSignature);
+        }
+
+        // The owner role moves from the proposer; it must still be allowed to hand it over.
+        self.require(&pending.proposed_by, Permission::TransferOwnership)?;
+        self.ledger.hand_over_ownership(&pending.proposed_by.value, &pending.new_owner.value)?;
+        self.pending_ownership = None;
+        self.ledger.publish(LedgerEvent::OwnershipTransferAccepted { previous_owner: pending.proposed_by.value, new_owner: pending.new_owner.value });
+        Ok(())
+    }
+
+    /// Withdraws the pending proposal before it is accepted; signed over `cancellation_data`.
+    pub fn cancel_ownership_transfer(&mut self, caller: &Address, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LuksError> {
+        self.authorize(caller, Permission::TransferOwnership, &Self::cancellation_data(caller, nonce), nonce, signatures)?;
+        let pending = self.pending_ownership.take().ok_or(LuksError::NoPendingOwnershipTransfer)?;
+        self.ledger.accounts.increment_nonce(&caller.value).map_err(|_| LuksError::AccountNotFound)?;
+        self.ledger.publish(LedgerEvent::OwnershipTransferCancelled { owner: pending.proposed_by.value, new_owner: pending.new_owner.value });
         Ok(())
     }
 
-    // Enhanced transfer logic now with fees and transaction recording.
-    pub fn initiate_transfer(&mut self, from: &Address, to: &Address, amount: u64, signature: String) -> Result<(), LuksError> {
-        // ... (Validations remain the same)
+    /// Sets the minimum delay of later proposals; a pending one keeps its own. Signed over
+    /// `delay_data`.
+    pub fn set_ownership_delay(&mut self, caller: &Address, seconds: u64, nonce: u64, signatures: &[AccountSignature]) -> Result<(), LuksError> {
+        self.authorize(caller, Permission::TransferOwnership, &Self::delay_data(caller, seconds, nonce), nonce, signatures)?;
+        self.ledger.accounts.increment_nonce(&caller.value).map_err(|_| LuksError::AccountNotFound)?;
+        self.ownership_delay = seconds;
+        Ok(())
+    }
 
-        let sender = self.accounts.get_mut(from).ok_or(LuksError::AccountNotFound)?;
-        let receiver = self.accounts.get_mut(to).ok_or(LuksError::AccountNotFound)?;
+    /// Data the sender signs to transfer: the canonical signing payload of a Luks transfer
+    /// with the sender's next nonce.
+    pub fn transfer_data(from: &Address, to: &Address, amount: Amount, tip: Amount, nonce: u64) -> String {
+        ValidateTransaction::signing_data(&TransactionKind::Transfer, &from.value, &to.value, &assets::native_asset_id(), amount, tip, nonce)
+    }
 
-        if sender.balance < amount + Self::TRANSACTION_FEE {
-            return Err(LuksError::InsufficientBalance);
+    /// Transfers Luks through the ledger, which charges its fees and records the
+    /// transfer. `signatures` cover `transfer_data` at the sender's next nonce.
+    pub fn initiate_transfer(&mut self, from: &Address, to: &Address, amount: Amount, tip: Amount, signatures: Vec<AccountSignature>) -> Result<Receipt, LuksError> {
+        if !from.is_valid() || !to.is_valid() {
+            return Err(LuksError::InvalidAddress);
+        }
+        if from == to {
+            return Err(LuksError::InvalidTransaction);
         }
+        let nonce = self.ledger.accounts.get_nonce(&from.value).map_err(|_| LuksError::AccountNotFound)?;
+        Ok(self.ledger.apply(LedgerTransaction {
+            kind: LedgerTransactionKind::Transfer,
+            from: from.value.clone(),
+            to: to.value.clone(),
+            asset: assets::native_asset_id(),
+            amount,
+            tip,
+            nonce,
+            signatures,
+        })?)
+    }
+
+    // Seconds since the Unix epoch; ownership delays depend on it.
+    fn get_current_timestamp(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0)
+    }
+}
 
-        // Deducting transaction fee
-        sender.balance -= Self::TRANSACTION_FEE;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::roles::Roles;
+    use crate::testing::{self, ALICE, BOB, OWNER, TREASURY_KEYS};
 
-        // Add the transaction fee to the fees account
-        let fee_account = self.accounts.get_mut(&self.fees_account).ok_or(LuksError::AccountNotFound)?;
-        fee_account.balance += Self::TRANSACTION_FEE;
+    fn address(key: &[u8; 32]) -> Address {
+        Address { value: testing::address(key) }
+    }
 
-        // Perform the actual transfer
-        sender.balance -= amount;
-        receiver.balance += amount;
+    fn contract() -> LuksMainContract {
+        LuksMainContract::new(testing::ledger(), 0)
+    }
 
-        // 1. Recording the transaction in the history
-        let record = TransactionRecord {
-            from: from.clone(),
-            to: to.clone(),
-            amount,
-            timestamp: self.get_current_timestamp(), // This is a placeholder; you would need to implement the timestamp retrieval.
+    // Ownership events published from now on.
+    fn ownership_events(contract: &mut LuksMainContract) -> Arc<Mutex<Vec<LedgerEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        contract.ledger.subscribe(move |event| {
+            if matches!(event, LedgerEvent::OwnershipTransferProposed { .. } | LedgerEvent::OwnershipTransferAccepted { .. } | LedgerEvent::OwnershipTransferCancelled { .. }) {
+                sink.lock().unwrap().push(event.clone());
+            }
+        });
+        events
+    }
+
+    fn nonce(contract: &LuksMainContract, key: &[u8; 32]) -> u64 {
+        contract.ledger.accounts.get_nonce(&testing::address(key)).unwrap_or(0)
+    }
+
+    fn propose(contract: &mut LuksMainContract, new_owner: &[u8; 32]) -> Result<(), LuksError> {
+        let (owner, nonce) = (address(&OWNER), nonce(contract, &OWNER));
+        let data = LuksMainContract::proposal_data(&owner, &address(new_owner), nonce);
+        contract.propose_ownership_transfer(address(new_owner), &owner, nonce, &[testing::sign(&OWNER, &data)])
+    }
+
+    // `signer` signs the acceptance of the pending proposal with its own key.
+    fn accept(contract: &mut LuksMainContract, caller: &[u8; 32], signer: &[u8; 32]) -> Result<(), LuksError> {
+        let data = contract.pending_ownership.as_ref().map(LuksMainContract::ownership_acceptance_data).unwrap_or_default();
+        let signature = testing::sign(signer, &data);
+        contract.accept_ownership(&address(caller), &signature.public_key.to_tagged_string(), &signature.signature)
+    }
+
+    #[test]
+    fn accepting_ownership_moves_the_owner_role() {
+        let mut contract = contract();
+        let events = ownership_events(&mut contract);
+        let (owner, alice) = (address(&OWNER), address(&ALICE));
+        assert!(propose(&mut contract, &ALICE).is_ok());
+        assert_eq!(contract.owners(), vec![owner.clone()]);
+
+        // Only the proposed owner can accept, with its own key.
+        assert!(matches!(accept(&mut contract, &BOB, &BOB), Err(LuksError::Unauthorized)));
+        assert!(matches!(accept(&mut contract, &ALICE, &BOB), Err(LuksError::InvalidSignature)));
+        assert!(accept(&mut contract, &ALICE, &ALICE).is_ok());
+        assert_eq!(contract.owners(), vec![alice.clone()]);
+        assert!(contract.pending_ownership.is_none());
+
+        let accept_after = match events.lock().unwrap().first() {
+            Some(LedgerEvent::OwnershipTransferProposed { accept_after, .. }) => *accept_after,
+            _ => panic!("proposal published"),
         };
+        assert_eq!(*events.lock().unwrap(), vec![
+            LedgerEvent::OwnershipTransferProposed { owner: owner.value.clone(), new_owner: alice.value.clone(), accept_after },
+            LedgerEvent::OwnershipTransferAccepted { previous_owner: owner.value.clone(), new_owner: alice.value.clone() },
+        ]);
 
-        self.accounts_transaction_history.entry(from.clone()).or_insert_with(Vec::new).push(record);
+        // The previous owner lost every owner permission.
+        assert!(matches!(propose(&mut contract, &BOB), Err(LuksError::Unauthorized)));
+    }
 
-        Ok(())
+    #[test]
+    fn replaced_or_cancelled_proposals_cannot_be_accepted() {
+        let mut contract = contract();
+        let events = ownership_events(&mut contract);
+        let (owner, alice, bob) = (address(&OWNER), address(&ALICE), address(&BOB));
+        assert!(propose(&mut contract, &ALICE).is_ok());
+        assert!(propose(&mut contract, &BOB).is_ok());
+        assert!(matches!(accept(&mut contract, &ALICE, &ALICE), Err(LuksError::Unauthorized)));
+
+        // Cancelling needs the owner's signature.
+        let nonce = nonce(&contract, &OWNER);
+        let data = LuksMainContract::cancellation_data(&owner, nonce);
+        assert!(matches!(contract.cancel_ownership_transfer(&owner, nonce, &[testing::sign(&BOB, &data)]), Err(LuksError::Ledger(_))));
+        assert!(contract.cancel_ownership_transfer(&owner, nonce, &[testing::sign(&OWNER, &data)]).is_ok());
+        assert!(matches!(accept(&mut contract, &BOB, &BOB), Err(LuksError::NoPendingOwnershipTransfer)));
+        assert_eq!(contract.owners(), vec![owner.clone()]);
+
+        let cancelled: Vec<_> = events.lock().unwrap().iter().filter_map(|event| match event {
+            LedgerEvent::OwnershipTransferCancelled { owner, new_owner } => Some((owner.clone(), new_owner.clone())),
+            _ => None,
+        }).collect();
+        assert_eq!(cancelled, vec![(owner.value.clone(), alice.value), (owner.value, bob.value)]);
     }
 
-    // Placeholder function for getting current timestamp.
-    // You might want to use an external crate for this like 'chrono'.
-    fn get_current_timestamp(&self) -> u64 {
-        // Placeholder logic, not actual implementation
-        0
+    #[test]
+    fn acceptance_waits_for_the_delay_of_the_proposal() {
+        let mut contract = contract();
+        let owner = address(&OWNER);
+        let data = LuksMainContract::delay_data(&owner, 3_600, 0);
+        assert!(matches!(contract.set_ownership_delay(&owner, 3_600, 0, &[testing::sign(&ALICE, &data)]), Err(LuksError::Ledger(_))));
+        assert!(contract.set_ownership_delay(&owner, 3_600, 0, &[testing::sign(&OWNER, &data)]).is_ok());
+        assert!(propose(&mut contract, &ALICE).is_ok());
+        assert!(matches!(accept(&mut contract, &ALICE, &ALICE), Err(LuksError::OwnershipDelayNotElapsed)));
+
+        // A shorter delay only applies to later proposals.
+        let data = LuksMainContract::delay_data(&owner, 0, 2);
+        assert!(contract.set_ownership_delay(&owner, 0, 2, &[testing::sign(&OWNER, &data)]).is_ok());
+        assert!(matches!(accept(&mut contract, &ALICE, &ALICE), Err(LuksError::OwnershipDelayNotElapsed)));
+        assert!(propose(&mut contract, &ALICE).is_ok());
+        assert!(accept(&mut contract, &ALICE, &ALICE).is_ok());
+    }
+
+    #[test]
+    fn transfers_are_charged_and_recorded_by_the_ledger() {
+        let mut contract = contract();
+        let (alice, bob) = (address(&ALICE), address(&BOB));
+        let amount = Amount::from_units(10_000);
+        let tip = Amount::from_units(10);
+        let quote = contract.quote_fee(amount, tip).ok().unwrap();
+
+        let data = LuksMainContract::transfer_data(&alice, &bob, amount, tip, 0);
+        let receipt = contract.initiate_transfer(&alice, &bob, amount, tip, vec![testing::sign(&ALICE, &data)]).ok().unwrap();
+        assert_eq!(receipt.fee, quote.fee);
+        assert_eq!(contract.get_asset_balance(&alice, &assets::native_asset_id()), testing::INITIAL_BALANCE.checked_sub(quote.total).ok());
+        assert_eq!(contract.ledger.accounts.get_balance(&testing::treasury()).ok(), Some(quote.fee));
+        assert_eq!(contract.get_transaction_history(&bob).map(|history| history.len()), Some(1));
+
+        // The signature was for nonce 0 and cannot be replayed.
+        let replay = contract.initiate_transfer(&alice, &bob, amount, tip, vec![testing::sign(&ALICE, &data)]);
+        assert!(matches!(replay, Err(LuksError::Ledger(_))));
+    }
+
+    #[test]
+    fn issuance_needs_the_role_and_the_central_bank() {
+        let mut contract = contract();
+        let (owner, bob) = (address(&OWNER), address(&BOB));
+        let amount = contract.ledger.coin_issue.next_emission().ok().unwrap();
+        let asset = assets::native_asset_id();
+        let data = ValidateTransaction::signing_data(&TransactionKind::Issuance, &owner.value, &bob.value, &asset, amount, Amount::ZERO, 0);
+        let signatures: Vec<_> = TREASURY_KEYS.iter().map(|key| testing::sign(key, &data)).collect();
+        assert!(matches!(contract.issue_coins(&owner, &bob, amount, 0, signatures.clone()), Err(LuksError::Unauthorized)));
+
+        let grant = Roles::grant_data(&owner.value, &owner.value, Role::Issuer, 0);
+        assert!(contract.grant_role(&owner, &owner, Role::Issuer, 0, &[testing::sign(&OWNER, &grant)]).is_ok());
+        assert!(matches!(contract.issue_coins(&owner, &bob, amount, 0, signatures[..1].to_vec()), Err(LuksError::Ledger(_))));
+        assert!(contract.issue_coins(&owner, &bob, amount, 0, signatures).is_ok());
+        assert_eq!(contract.get_asset_balance(&bob, &asset), testing::INITIAL_BALANCE.checked_add(amount).ok());
     }
 }
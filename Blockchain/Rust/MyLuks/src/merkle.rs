@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use sha2::{Sha256, Digest};
+
+pub type Hash = [u8; 32];
+
+// Every version of a node or value, oldest first.
+type Versions<T> = Vec<(u64, T)>;
+
+/// Depth of the tree: one level per bit of a SHA-256 key.
+pub const TREE_DEPTH: usize = 256;
+
+// Separadores de dominio para hojas y nodos internos
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Proof that a key holds a given value (or nothing) under a root.
+///
+/// `siblings[d]` is the sibling of the path node at depth `d + 1`; `None` stands for the
+/// empty subtree of that level, which keeps proofs for sparse trees small.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MerkleProof {
+    pub key: Hash,
+    pub value: Option<Vec<u8>>,
+    pub siblings: Vec<Option<Hash>>,
+}
+
+/// Sparse Merkle tree over 256-bit keys.
+///
+/// Only nodes on paths that have been written are stored, so the tree holds roughly
+/// `TREE_DEPTH` nodes per key and each update rehashes a single path.
+/// Every node and leaf value keeps its history by version (block height), so roots and
+/// proofs can be produced for any height since `earliest_version`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SparseMerkleTree {
+    nodes: HashMap<(usize, Hash), Versions<Hash>>,
+    values: HashMap<Hash, Versions<Option<Vec<u8>>>>,
+    defaults: Vec<Hash>,
+    version: u64,
+    earliest_version: u64,
+}
+
+impl Default for SparseMerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SparseMerkleTree {
+    pub fn new() -> Self {
+        Self::starting_at(0)
+    }
+
+    /// Creates an empty tree whose first writes belong to `version`.
+    pub fn starting_at(version: u64) -> Self {
+        SparseMerkleTree {
+            nodes: HashMap::new(),
+            values: HashMap::new(),
+            defaults: default_hashes(),
+            version,
+            earliest_version: version,
+        }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.node(0, &[0u8; 32], self.version)
+    }
+
+    /// Root as it was once `version` was sealed, if that version is still known.
+    pub fn root_at(&self, version: u64) -> Option<Hash> {
+        if version < self.earliest_version || version >= self.version {
+            return None;
+        }
+        Some(self.node(0, &[0u8; 32], version))
+    }
+
+    /// Seals the current version; later writes belong to `version + 1`.
+    pub fn seal(&mut self, version: u64) {
+        self.version = version + 1;
+    }
+
+    /// Sets the value committed under `key`, or removes it when `value` is None.
+    pub fn update(&mut self, key: &Hash, value: Option<&[u8]>) {
+        let version = self.version;
+        let mut current = match value {
+            Some(value) => hash_leaf(key, &Sha256::digest(value).into()),
+            None => self.defaults[TREE_DEPTH],
+        };
+        record(self.values.entry(*key).or_default(), version, value.map(|v| v.to_vec()));
+        self.set_node(TREE_DEPTH, *key, current);
+
+        for depth in (0..TREE_DEPTH).rev() {
+            let sibling = self.node(depth + 1, &prefix(&flip_bit(key, depth), depth + 1), version);
+            current = if bit(key, depth) {
+                hash_node(&sibling, &current)
+            } else {
+                hash_node(&current, &sibling)
+            };
+            self.set_node(depth, prefix(key, depth), current);
+        }
+    }
+
+    /// Builds an inclusion or non-inclusion proof for `key` at a sealed `version`.
+    pub fn prove(&self, key: &Hash, version: u64) -> Option<MerkleProof> {
+        if version < self.earliest_version || version >= self.version {
+            return None;
+        }
+        let value = self
+            .values
+            .get(key)
+            .and_then(|history| lookup(history, version).cloned())
+            .flatten();
+        let siblings = (0..TREE_DEPTH)
+            .map(|depth| {
+                let sibling = self.node(depth + 1, &prefix(&flip_bit(key, depth), depth + 1), version);
+                if sibling == self.defaults[depth + 1] { None } else { Some(sibling) }
+            })
+            .collect();
+        Some(MerkleProof { key: *key, value, siblings })
+    }
+
+    /// Forgets every version before `retain_from`: roots and proofs stay available from
+    /// `retain_from` on, and nodes and values that only held older data are freed.
+    pub fn prune(&mut self, retain_from: u64) {
+        let retain_from = retain_from.min(self.version);
+        if retain_from <= self.earliest_version {
+            return;
+        }
+        let defaults = &self.defaults;
+        self.nodes.retain(|(depth, _), history| {
+            prune_history(history, retain_from);
+            !(history.len() == 1 && history[0].0 <= retain_from && history[0].1 == defaults[*depth])
+        });
+        self.values.retain(|_, history| {
+            prune_history(history, retain_from);
+            !(history.len() == 1 && history[0].0 <= retain_from && history[0].1.is_none())
+        });
+        self.earliest_version = retain_from;
+    }
+
+    fn node(&self, depth: usize, path: &Hash, version: u64) -> Hash {
+        self.nodes
+            .get(&(depth, *path))
+            .and_then(|history| lookup(history, version).copied())
+            .unwrap_or(self.defaults[depth])
+    }
+
+    fn set_node(&mut self, depth: usize, path: Hash, hash: Hash) {
+        let version = self.version;
+        let history = self.nodes.entry((depth, path)).or_default();
+        record(history, version, hash);
+    }
+}
+
+/// Recomputes the root from a proof and checks it against `root`.
+pub fn verify_proof(root: &Hash, proof: &MerkleProof) -> bool {
+    if proof.siblings.len() != TREE_DEPTH {
+        return false;
+    }
+    let defaults = default_hashes();
+    let mut current = match &proof.value {
+        Some(value) => hash_leaf(&proof.key, &Sha256::digest(value).into()),
+        None => defaults[TREE_DEPTH],
+    };
+    for depth in (0..TREE_DEPTH).rev() {
+        let sibling = proof.siblings[depth].unwrap_or(defaults[depth + 1]);
+        current = if bit(&proof.key, depth) {
+            hash_node(&sibling, &current)
+        } else {
+            hash_node(&current, &sibling)
+        };
+    }
+    &current == root
+}
+
+// defaults[d] is the hash of an empty subtree rooted at depth d.
+fn default_hashes() -> Vec<Hash> {
+    let mut defaults = vec![[0u8; 32]; TREE_DEPTH + 1];
+    for depth in (0..TREE_DEPTH).rev() {
+        defaults[depth] = hash_node(&defaults[depth + 1], &defaults[depth + 1]);
+    }
+    defaults
+}
+
+// Appends a versioned entry, overwriting the last one if it belongs to the same version.
+fn record<T>(history: &mut Vec<(u64, T)>, version: u64, item: T) {
+    match history.last_mut() {
+        Some((last_version, last_item)) if *last_version == version => *last_item = item,
+        _ => history.push((version, item)),
+    }
+}
+
+// Drops the entries no version from `retain_from` on can see: all but the latest one
+// written at or before it.
+fn prune_history<T>(history: &mut Vec<(u64, T)>, retain_from: u64) {
+    let visible = history.partition_point(|(v, _)| *v <= retain_from);
+    if visible > 1 {
+        history.drain(..visible - 1);
+    }
+}
+
+// Latest entry written at or before `version`.
+fn lookup<T>(history: &[(u64, T)], version: u64) -> Option<&T> {
+    let index = history.partition_point(|(v, _)| *v <= version);
+    if index == 0 { None } else { Some(&history[index - 1].1) }
+}
+
+/// Key under which an account is stored in the tree.
+pub fn account_key(account_id: &str) -> Hash {
+    Sha256::digest(account_id.as_bytes()).into()
+}
+
+/// Key under which a registered asset is stored in the tree, apart from the accounts.
+pub fn asset_key(asset_id: &str) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(b"asset/");
+    hasher.update(asset_id.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Key under which a non-fungible item is stored in the tree.
+pub fn nft_key(item_id: &str) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(b"nft/");
+    hasher.update(item_id.as_bytes());
+    hasher.finalize().into()
+}
+
+pub fn hash_leaf(key: &Hash, value: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(key);
+    hasher.update(value);
+    hasher.finalize().into()
+}
+
+pub fn hash_node(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+// Bit `index` of the key, most significant bit first.
+fn bit(key: &Hash, index: usize) -> bool {
+    (key[index / 8] >> (7 - index % 8)) & 1 == 1
+}
+
+fn flip_bit(key: &Hash, index: usize) -> Hash {
+    let mut flipped = *key;
+    flipped[index / 8] ^= 1 << (7 - index % 8);
+    flipped
+}
+
+// Keeps the first `depth` bits of the key and clears the rest.
+fn prefix(key: &Hash, depth: usize) -> Hash {
+    let mut path = [0u8; 32];
+    for index in 0..depth {
+        if bit(key, index) {
+            path[index / 8] |= 1 << (7 - index % 8);
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::ledger::LedgerError;
+    use crate::storage::MemoryStore;
+    use crate::testing::{self, ALICE, BOB};
+    use crate::validate_transaction::ValidationError;
+
+    #[test]
+    fn root_depends_on_the_state_not_the_order_of_updates() {
+        let mut forward = SparseMerkleTree::new();
+        forward.update(&account_key("alice"), Some(b"100"));
+        forward.update(&account_key("bob"), Some(b"7"));
+        let mut backward = SparseMerkleTree::new();
+        backward.update(&account_key("bob"), Some(b"7"));
+        backward.update(&account_key("alice"), Some(b"100"));
+        assert_eq!(forward.root(), backward.root());
+
+        // Removing every leaf gives back the root of the empty tree.
+        let empty = SparseMerkleTree::new().root();
+        assert_ne!(forward.root(), empty);
+        forward.update(&account_key("alice"), None);
+        forward.update(&account_key("bob"), None);
+        assert_eq!(forward.root(), empty);
+    }
+
+    #[test]
+    fn blocks_commit_to_the_root_over_every_account() {
+        let mut ledger = testing::ledger();
+        let (alice, bob) = (testing::address(&ALICE), testing::address(&BOB));
+        let root = ledger.accounts.state_root();
+
+        // Over the 10,000-coin cap: refused before anything moves, so the root stays.
+        let cap = Amount::from_units(10_000 * Amount::ONE.units());
+        let over = testing::transfer(&ledger, &ALICE, &bob, cap.saturating_add(Amount::from_units(1)));
+        assert!(matches!(ledger.apply(over), Err(LedgerError::Validation(ValidationError::ExceedsMaxAmount))));
+        assert_eq!(ledger.accounts.state_root(), root);
+
+        assert!(ledger.apply(testing::transfer(&ledger, &ALICE, &bob, Amount::from_units(100))).is_ok());
+        assert_ne!(ledger.accounts.state_root(), root);
+        assert!(ledger.commit_block(&mut MemoryStore::new(), &alice).is_ok());
+        let block = ledger.consensus.blockchain.last().unwrap();
+        assert_eq!(block.state_root, hex::encode(ledger.accounts.state_root()));
+    }
+
+    #[test]
+    fn inclusion_proof_verifies_against_root() {
+        let mut tree = SparseMerkleTree::new();
+        tree.update(&account_key("alice"), Some(b"100"));
+        tree.update(&account_key("bob"), Some(b"7"));
+        tree.seal(0);
+
+        let root = tree.root_at(0).unwrap();
+        let proof = tree.prove(&account_key("alice"), 0).unwrap();
+        assert_eq!(proof.value, Some(b"100".to_vec()));
+        assert!(verify_proof(&root, &proof));
+    }
+
+    #[test]
+    fn non_inclusion_proof_verifies_against_root() {
+        let mut tree = SparseMerkleTree::new();
+        tree.update(&account_key("alice"), Some(b"100"));
+        tree.seal(0);
+
+        let root = tree.root_at(0).unwrap();
+        let proof = tree.prove(&account_key("carol"), 0).unwrap();
+        assert_eq!(proof.value, None);
+        assert!(verify_proof(&root, &proof));
+    }
+
+    #[test]
+    fn tampered_proof_is_rejected() {
+        let mut tree = SparseMerkleTree::new();
+        tree.update(&account_key("alice"), Some(b"100"));
+        tree.update(&account_key("bob"), Some(b"7"));
+        tree.seal(0);
+        let root = tree.root_at(0).unwrap();
+        let proof = tree.prove(&account_key("alice"), 0).unwrap();
+
+        let mut wrong_value = proof.clone();
+        wrong_value.value = Some(b"1000".to_vec());
+        assert!(!verify_proof(&root, &wrong_value));
+
+        let mut wrong_key = proof.clone();
+        wrong_key.key = account_key("bob");
+        assert!(!verify_proof(&root, &wrong_key));
+
+        let mut short = proof.clone();
+        short.siblings.pop();
+        assert!(!verify_proof(&root, &short));
+
+        assert!(!verify_proof(&[0u8; 32], &proof));
+    }
+
+    #[test]
+    fn proofs_follow_the_requested_version() {
+        let mut tree = SparseMerkleTree::new();
+        tree.update(&account_key("alice"), Some(b"100"));
+        tree.seal(0);
+        tree.update(&account_key("alice"), Some(b"50"));
+        tree.seal(1);
+
+        let old_root = tree.root_at(0).unwrap();
+        let new_root = tree.root_at(1).unwrap();
+        assert_ne!(old_root, new_root);
+
+        let old_proof = tree.prove(&account_key("alice"), 0).unwrap();
+        assert_eq!(old_proof.value, Some(b"100".to_vec()));
+        assert!(verify_proof(&old_root, &old_proof));
+        assert!(!verify_proof(&new_root, &old_proof));
+
+        let new_proof = tree.prove(&account_key("alice"), 1).unwrap();
+        assert!(verify_proof(&new_root, &new_proof));
+    }
+
+    #[test]
+    fn pruned_versions_cannot_be_proven() {
+        let mut tree = SparseMerkleTree::new();
+        for version in 0..4 {
+            tree.update(&account_key("alice"), Some(&[version as u8]));
+            tree.seal(version);
+        }
+        let root = tree.root_at(3).unwrap();
+        tree.prune(2);
+
+        assert!(tree.prove(&account_key("alice"), 1).is_none());
+        assert_eq!(tree.root_at(3), Some(root));
+        let proof = tree.prove(&account_key("alice"), 3).unwrap();
+        assert!(verify_proof(&root, &proof));
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use crate::validate_transaction::ValidationError;
+
+// Upper bounds, in seconds, of the latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 12] = [0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Counter split by a label, such as the reason a transaction was rejected.
+#[derive(Debug, Default)]
+pub struct LabeledCounter(Mutex<BTreeMap<&'static str, u64>>);
+
+impl LabeledCounter {
+    pub fn inc(&self, label: &'static str) {
+        if let Ok(mut values) = self.0.lock() {
+            *values.entry(label).or_insert(0) += 1;
+        }
+    }
+
+    pub fn get(&self, label: &str) -> u64 {
+        self.0.lock().map(|values| values.get(label).copied().unwrap_or(0)).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    pub fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct HistogramState {
+    buckets: [u64; LATENCY_BUCKETS.len()], // Observations at or below each bound, not cumulative.
+    count: u64,
+    sum: f64,
+}
+
+#[derive(Debug)]
+pub struct Histogram(Mutex<HistogramState>);
+
+impl Histogram {
+    fn new() -> Self {
+        Histogram(Mutex::new(HistogramState { buckets: [0; LATENCY_BUCKETS.len()], count: 0, sum: 0.0 }))
+    }
+
+    pub fn observe(&self, seconds: f64) {
+        if let Ok(mut state) = self.0.lock() {
+            if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound) {
+                state.buckets[bucket] += 1;
+            }
+            state.count += 1;
+            state.sum += seconds;
+        }
+    }
+
+    // Observes the time until the returned timer is dropped.
+    pub fn start_timer(&self) -> HistogramTimer<'_> {
+        HistogramTimer { histogram: self, start: Instant::now() }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.lock().map(|state| state.count).unwrap_or(0)
+    }
+}
+
+pub struct HistogramTimer<'a> {
+    histogram: &'a Histogram,
+    start: Instant,
+}
+
+impl Drop for HistogramTimer<'_> {
+    fn drop(&mut self) {
+        self.histogram.observe(self.start.elapsed().as_secs_f64());
+    }
+}
+
+/// Process-wide ledger metrics, read by scraping `render` in the Prometheus text format.
+#[derive(Debug)]
+pub struct Metrics {
+    pub transactions_validated: Counter,
+    pub transactions_rejected: LabeledCounter, // By `ValidationError` variant, or "other".
+    pub block_production_seconds: Histogram,
+    pub signature_verification_seconds: Histogram,
+    pub mempool_size: Gauge,
+    pub total_supply: Gauge, // In base units.
+    pub total_burned: Gauge, // In base units.
+    pub base_fee: Gauge, // In base units.
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Metrics {
+            transactions_validated: Counter::default(),
+            transactions_rejected: LabeledCounter::default(),
+            block_production_seconds: Histogram::new(),
+            signature_verification_seconds: Histogram::new(),
+            mempool_size: Gauge::default(),
+            total_supply: Gauge::default(),
+            total_burned: Gauge::default(),
+            base_fee: Gauge::default(),
+        }
+    }
+
+    pub fn record_rejection(&self, error: Option<&ValidationError>) {
+        self.transactions_rejected.inc(error.map_or("other", rejection_reason));
+    }
+
+    /// Every metric in the Prometheus text exposition format (version 0.0.4).
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        write_metric(&mut out, "luks_transactions_validated_total", "counter", "Transactions accepted by the ledger.", self.transactions_validated.get());
+
+        let _ = writeln!(out, "# HELP luks_transactions_rejected_total Transactions rejected, by reason.");
+        let _ = writeln!(out, "# TYPE luks_transactions_rejected_total counter");
+        if let Ok(values) = self.transactions_rejected.0.lock() {
+            for (reason, value) in values.iter() {
+                let _ = writeln!(out, "luks_transactions_rejected_total{{reason=\"{}\"}} {}", reason, value);
+            }
+        }
+
+        write_histogram(&mut out, "luks_block_production_seconds", "Time taken to seal and commit a block.", &self.block_production_seconds);
+        write_histogram(&mut out, "luks_signature_verification_seconds", "Time taken to verify one signature.", &self.signature_verification_seconds);
+        write_metric(&mut out, "luks_mempool_size", "gauge", "Transactions applied but not yet in a block.", self.mempool_size.get());
+        write_metric(&mut out, "luks_total_supply_units", "gauge", "Luks coins in circulation, in base units.", self.total_supply.get());
+        write_metric(&mut out, "luks_total_burned_units", "gauge", "Luks coins burned as base fees, in base units.", self.total_burned.get());
+        write_metric(&mut out, "luks_base_fee_units", "gauge", "Base fee of the block being built, in base units.", self.base_fee.get());
+        out
+    }
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn write_histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    let Ok(state) = histogram.0.lock() else { return };
+    let mut cumulative = 0;
+    for (bound, count) in LATENCY_BUCKETS.iter().zip(state.buckets.iter()) {
+        cumulative += count;
+        let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+    }
+    let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, state.count);
+    let _ = writeln!(out, "{}_sum {}", name, state.sum);
+    let _ = writeln!(out, "{}_count {}", name, state.count);
+}
+
+// Label value of each rejection reason; stable, since dashboards depend on it.
+fn rejection_reason(error: &ValidationError) -> &'static str {
+    match error {
+        ValidationError::ExceedsMaxAmount => "ExceedsMaxAmount",
+        ValidationError::InsufficientSenderBalance => "InsufficientSenderBalance",
+        ValidationError::InvalidRecipient => "InvalidRecipient",
+        ValidationError::SignatureFailure => "SignatureFailure",
+        ValidationError::StaleNonce => "StaleNonce",
+        ValidationError::NonceGap => "NonceGap",
+        ValidationError::SenderAccountLocked => "SenderAccountLocked",
+        ValidationError::InvalidSignature => "InvalidSignature",
+        ValidationError::InsufficientSignatures => "InsufficientSignatures",
+        ValidationError::RevokedKey => "RevokedKey",
+        ValidationError::InvalidTransactionHash => "InvalidTransactionHash",
+        ValidationError::InvalidAddress => "InvalidAddress",
+    }
+}
+
+/// The metrics of this process.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::storage::MemoryStore;
+    use crate::testing::{self, ALICE, BOB};
+
+    #[test]
+    fn render_follows_the_prometheus_text_format() {
+        let metrics = Metrics::new();
+        metrics.transactions_validated.inc();
+        metrics.transactions_validated.inc();
+        metrics.record_rejection(Some(&ValidationError::StaleNonce));
+        metrics.record_rejection(None);
+        for seconds in [0.0002, 2.0, 10.0] {
+            metrics.block_production_seconds.observe(seconds);
+        }
+        metrics.mempool_size.set(3);
+
+        let text = metrics.render();
+        let lines: Vec<&str> = text.lines().collect();
+        for expected in [
+            "# TYPE luks_transactions_validated_total counter",
+            "luks_transactions_validated_total 2",
+            "luks_transactions_rejected_total{reason=\"StaleNonce\"} 1",
+            "luks_transactions_rejected_total{reason=\"other\"} 1",
+            "# TYPE luks_block_production_seconds histogram",
+            "luks_block_production_seconds_bucket{le=\"0.0001\"} 0",
+            "luks_block_production_seconds_bucket{le=\"0.0005\"} 1",
+            "luks_block_production_seconds_bucket{le=\"5\"} 2",
+            "luks_block_production_seconds_bucket{le=\"+Inf\"} 3",
+            "luks_block_production_seconds_count 3",
+            "luks_signature_verification_seconds_count 0",
+            "luks_mempool_size 3",
+        ] {
+            assert!(lines.contains(&expected), "missing {}", expected);
+        }
+    }
+
+    #[test]
+    fn the_ledger_counts_what_it_validates_rejects_and_seals() {
+        // The metrics are shared by every test in the process, so only check that they grew.
+        let metrics = metrics();
+        let validated = metrics.transactions_validated.get();
+        let over_cap = metrics.transactions_rejected.get("ExceedsMaxAmount");
+        let verifications = metrics.signature_verification_seconds.count();
+        let blocks = metrics.block_production_seconds.count();
+
+        let mut ledger = testing::ledger();
+        let bob = testing::address(&BOB);
+        assert!(ledger.apply(testing::transfer(&ledger, &ALICE, &bob, Amount::from_units(100))).is_ok());
+        let over = Amount::from_units(10_000 * Amount::ONE.units() + 1);
+        assert!(ledger.apply(testing::transfer(&ledger, &ALICE, &bob, over)).is_err());
+        assert!(ledger.commit_block(&mut MemoryStore::new(), &bob).is_ok());
+
+        assert!(metrics.transactions_validated.get() > validated);
+        assert!(metrics.transactions_rejected.get("ExceedsMaxAmount") > over_cap);
+        assert!(metrics.signature_verification_seconds.count() > verifications);
+        assert!(metrics.block_production_seconds.count() > blocks);
+        assert!(metrics.render().contains("# TYPE luks_total_supply_units gauge"));
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::accounts::{Accounts, AccountError, AccountSignature};
+use crate::amount::Amount;
+use crate::concensus::TransactionKind;
+use crate::storage::{StateStore, StorageError};
+use crate::validate_transaction::{ValidateTransaction, ValidationError};
+
+pub type ItemId = String;
+
+// Prefix of minted items in the store
+const NFT_KEY_PREFIX: &str = "nft/";
+
+pub enum NftError {
+    ItemAlreadyExists,
+    ItemNotFound,
+    InvalidMetadataHash,
+    NotOwner,
+    Validation(ValidationError),
+    Account(AccountError),
+}
+
+impl From<ValidationError> for NftError {
+    fn from(error: ValidationError) -> Self {
+        NftError::Validation(error)
+    }
+}
+
+impl From<AccountError> for NftError {
+    fn from(error: AccountError) -> Self {
+        NftError::Account(error)
+    }
+}
+
+// One change of ownership. `from` is None for the mint.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct OwnershipRecord {
+    pub from: Option<String>,
+    pub to: String,
+    pub nonce: u64, // Nonce of the account that signed the mint or transfer.
+    pub timestamp: u64,
+}
+
+/// A unique, non-fungible item.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct NftItem {
+    pub id: ItemId,
+    pub metadata_hash: String, // Hex SHA-256 of the off-chain metadata.
+    pub minter: String,
+    pub owner: String,
+    pub provenance: Vec<OwnershipRecord>, // Every owner since the mint, oldest first.
+}
+
+impl NftItem {
+    // Fixed-order encoding of the item, committed to the state tree. The provenance is
+    // not part of the commitment: it follows from the blocks.
+    pub fn canonical_encoding(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for field in [&self.id, &self.metadata_hash, &self.minter, &self.owner] {
+            data.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            data.extend_from_slice(field.as_bytes());
+        }
+        data
+    }
+}
+
+/// Registry of non-fungible items and their ownership history.
+#[derive(Debug, PartialEq)]
+pub struct NftRegistry {
+    items: HashMap<ItemId, NftItem>,
+}
+
+impl Default for NftRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NftRegistry {
+    pub fn new() -> Self {
+        NftRegistry {
+            items: HashMap::new(),
+        }
+    }
+
+    pub fn get_item(&self, item_id: &ItemId) -> Option<&NftItem> {
+        self.items.get(item_id)
+    }
+
+    pub fn owner_of(&self, item_id: &ItemId) -> Option<&String> {
+        self.items.get(item_id).map(|item| &item.owner)
+    }
+
+    pub fn items_owned_by(&self, owner: &String) -> Vec<&NftItem> {
+        self.items.values().filter(|item| &item.owner == owner).collect()
+    }
+
+    // Full ownership history of an item, starting with its mint.
+    pub fn ownership_history(&self, item_id: &ItemId) -> Result<&[OwnershipRecord], NftError> {
+        self.items.get(item_id).map(|item| item.provenance.as_slice()).ok_or(NftError::ItemNotFound)
+    }
+
+    // Block transaction kind of a mint; `metadata_hash` must be a hex SHA-256.
+    pub fn mint_kind(metadata_hash: &String) -> Result<TransactionKind, NftError> {
+        let bytes = hex::decode(metadata_hash).map_err(|_| NftError::InvalidMetadataHash)?;
+        let metadata_hash: [u8; 32] = bytes.try_into().map_err(|_| NftError::InvalidMetadataHash)?;
+        Ok(TransactionKind::NftMint { metadata_hash })
+    }
+
+    // Data the minter signs to mint an item: the signing payload of its block transaction.
+    pub fn mint_data(minter: &str, item_id: &ItemId, metadata_hash: &String, nonce: u64) -> Result<String, NftError> {
+        let kind = Self::mint_kind(metadata_hash)?;
+        Ok(ValidateTransaction::signing_data(&kind, minter, minter, item_id, Amount::ZERO, Amount::ZERO, nonce))
+    }
+
+    // Data the owner signs to transfer an item: the signing payload of its block transaction.
+    pub fn transfer_data(from: &str, to: &str, item_id: &ItemId, nonce: u64) -> String {
+        ValidateTransaction::signing_data(&TransactionKind::NftTransfer, from, to, item_id, Amount::ZERO, Amount::ZERO, nonce)
+    }
+
+    /// Mints a new item owned by the minter.
+    #[allow(clippy::too_many_arguments)]
+    pub fn mint(&mut self, accounts: &mut Accounts, validator: &ValidateTransaction, minter: &String, item_id: ItemId, metadata_hash: String, nonce: u64, signatures: &[AccountSignature], now: u64) -> Result<(), NftError> {
+        if self.items.contains_key(&item_id) {
+            return Err(NftError::ItemAlreadyExists);
+        }
+        // Lowercase only, so that the stored hash matches the signed bytes one to one.
+        if metadata_hash.chars().any(|c| c.is_ascii_uppercase()) {
+            return Err(NftError::InvalidMetadataHash);
+        }
+        let data = Self::mint_data(minter, &item_id, &metadata_hash, nonce)?;
+        validator.check_authorization(accounts, minter, minter, &data, nonce, signatures)?;
+
+        accounts.increment_nonce(minter)?;
+        let item = NftItem {
+            id: item_id.clone(),
+            metadata_hash,
+            minter: minter.clone(),
+            owner: minter.clone(),
+            provenance: vec![OwnershipRecord { from: None, to: minter.clone(), nonce, timestamp: now }],
+        };
+        accounts.commit_nft(&item);
+        self.items.insert(item_id, item);
+        Ok(())
+    }
+
+    /// Transfers an item, validated exactly like a coin transfer: lock state, recipient,
+    /// nonce, revoked keys and the sender's signature threshold.
+    #[allow(clippy::too_many_arguments)]
+    pub fn transfer(&mut self, accounts: &mut Accounts, validator: &ValidateTransaction, from: &String, to: &String, item_id: &ItemId, nonce: u64, signatures: &[AccountSignature], now: u64) -> Result<(), NftError> {
+        let item = self.items.get(item_id).ok_or(NftError::ItemNotFound)?;
+        if &item.owner != from {
+            return Err(NftError::NotOwner);
+        }
+        let data = Self::transfer_data(from, to, item_id, nonce);
+        validator.check_authorization(accounts, from, to, &data, nonce, signatures)?;
+
+        accounts.increment_nonce(from)?;
+        if let Some(item) = self.items.get_mut(item_id) {
+            item.owner = to.clone();
+            item.provenance.push(OwnershipRecord { from: Some(from.clone()), to: to.clone(), nonce, timestamp: now });
+            accounts.commit_nft(item);
+        }
+        Ok(())
+    }
+
+    // Writes every item to the store; items that no longer exist are deleted.
+    pub fn persist(&self, store: &mut dyn StateStore) -> Result<(), StorageError> {
+        for key in store.keys_with_prefix(NFT_KEY_PREFIX) {
+            if !self.items.contains_key(&key[NFT_KEY_PREFIX.len()..]) {
+                store.delete(&key);
+            }
+        }
+        for (id, item) in &self.items {
+            let value = serde_json::to_vec(item).map_err(|_| StorageError::Serialization)?;
+            store.put(&format!("{}{}", NFT_KEY_PREFIX, id), value);
+        }
+        Ok(())
+    }
+
+    // Rebuilds the registry from the last committed state in the store, committing each
+    // item back to the state tree of `accounts`.
+    pub fn restore(store: &dyn StateStore, accounts: &mut Accounts) -> Result<Self, StorageError> {
+        let mut registry = NftRegistry::new();
+        for key in store.keys_with_prefix(NFT_KEY_PREFIX) {
+            let value = store.get(&key).ok_or(StorageError::Corrupted)?;
+            let item: NftItem = serde_json::from_slice(&value).map_err(|_| StorageError::Serialization)?;
+            accounts.commit_nft(&item);
+            registry.items.insert(key[NFT_KEY_PREFIX.len()..].to_string(), item);
+        }
+        Ok(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::LedgerError;
+    use crate::storage::MemoryStore;
+    use crate::testing::{self, ALICE, BOB, CAROL};
+
+    const METADATA_HASH: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    #[test]
+    fn items_move_only_with_the_owners_signature_and_keep_their_provenance() {
+        let mut ledger = testing::ledger();
+        let (alice, bob, carol) = (testing::address(&ALICE), testing::address(&BOB), testing::address(&CAROL));
+        let item = "painting-1".to_string();
+        let metadata_hash = METADATA_HASH.to_string();
+
+        // The mint is signed by the minter over the item and its metadata.
+        let mint = NftRegistry::mint_data(&alice, &item, &metadata_hash, 0).ok().unwrap();
+        let by_bob = [testing::sign(&BOB, &mint)];
+        assert!(matches!(ledger.mint_nft(&alice, item.clone(), metadata_hash.clone(), 0, &by_bob), Err(LedgerError::Nft(NftError::Validation(ValidationError::InvalidSignature)))));
+        assert!(matches!(ledger.mint_nft(&alice, item.clone(), METADATA_HASH.to_uppercase(), 0, &[testing::sign(&ALICE, &mint)]), Err(LedgerError::Nft(NftError::InvalidMetadataHash))));
+        assert!(ledger.mint_nft(&alice, item.clone(), metadata_hash.clone(), 0, &[testing::sign(&ALICE, &mint)]).is_ok());
+        assert_eq!(ledger.nfts.owner_of(&item), Some(&alice));
+
+        // Only the owner moves it: bob cannot send alice's item, even to himself.
+        let stolen = NftRegistry::transfer_data(&bob, &bob, &item, 0);
+        assert!(matches!(ledger.transfer_nft(&bob, &bob, &item, 0, &[testing::sign(&BOB, &stolen)]), Err(LedgerError::Nft(NftError::NotOwner))));
+        let to_bob = NftRegistry::transfer_data(&alice, &bob, &item, 1);
+        assert!(matches!(ledger.transfer_nft(&alice, &bob, &item, 1, &[testing::sign(&BOB, &to_bob)]), Err(LedgerError::Nft(NftError::Validation(ValidationError::InvalidSignature)))));
+        assert!(ledger.transfer_nft(&alice, &bob, &item, 1, &[testing::sign(&ALICE, &to_bob)]).is_ok());
+        let to_carol = NftRegistry::transfer_data(&bob, &carol, &item, 0);
+        assert!(ledger.transfer_nft(&bob, &carol, &item, 0, &[testing::sign(&BOB, &to_carol)]).is_ok());
+        assert_eq!(ledger.nfts.owner_of(&item), Some(&carol));
+
+        let owners: Vec<(Option<String>, String)> = ledger.nfts.ownership_history(&item).ok().unwrap().iter().map(|record| (record.from.clone(), record.to.clone())).collect();
+        assert_eq!(owners, vec![(None, alice.clone()), (Some(alice.clone()), bob.clone()), (Some(bob), carol)]);
+
+        // Mint and transfers are sealed in the next block, in order.
+        assert!(ledger.commit_block(&mut MemoryStore::new(), &alice).is_ok());
+        let block = ledger.consensus.blockchain.last().unwrap();
+        let kinds: Vec<&TransactionKind> = block.transactions.iter().map(|tx| &tx.kind).collect();
+        let minted = NftRegistry::mint_kind(&metadata_hash).ok().unwrap();
+        assert_eq!(kinds, vec![&minted, &TransactionKind::NftTransfer, &TransactionKind::NftTransfer]);
+    }
+}
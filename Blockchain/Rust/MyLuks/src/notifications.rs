@@ -0,0 +1,872 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use crate::accounts::{AccountError, AccountSignature, Accounts};
+use crate::amount::Amount;
+use crate::assets::AssetId;
+use crate::encoding::{Action, AuthorizationPayload};
+use crate::events::{LedgerEvent, SubscriberId};
+use crate::ledger::Ledger;
+use crate::storage::{StateStore, StorageError};
+
+// Headers sent with every delivery
+pub const SIGNATURE_HEADER: &str = "X-Luks-Signature";
+pub const DELIVERY_HEADER: &str = "X-Luks-Delivery";
+
+// Seconds a delivered notification stays queryable with `delivery_status` by default.
+const DEFAULT_DELIVERED_RETENTION: u64 = 24 * 3_600;
+
+pub enum NotificationError {
+    AccountNotFound,
+    Unauthorized,
+    KeyRevoked,
+    InvalidEndpoint,
+    EndpointNotFound,
+    DeliveryNotFound,
+    Account(AccountError),
+}
+
+impl From<AccountError> for NotificationError {
+    fn from(error: AccountError) -> Self {
+        match error {
+            AccountError::AccountNotFound => NotificationError::AccountNotFound,
+            other => NotificationError::Account(other),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum NotificationKind {
+    IncomingTransfer,
+    OutgoingTransfer,
+    Security, // Lock, unlock, key rotation, role change or closure of the account.
+}
+
+pub type EndpointId = u64;
+pub type DeliveryId = u64;
+
+// Prefix of registered endpoints in the store, and key of the next endpoint id
+const ENDPOINT_KEY_PREFIX: &str = "webhook/";
+const NEXT_ENDPOINT_ID_KEY: &str = "webhook_next_id";
+
+/// HTTP callback registered by an account. Payloads are signed with `secret`.
+#[derive(PartialEq, Clone, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub id: EndpointId,
+    pub account: String,
+    pub url: String,
+    pub kinds: Vec<NotificationKind>,
+    secret: Vec<u8>,
+}
+
+// The secret signs every payload, so it never goes to the logs.
+impl fmt::Debug for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Endpoint")
+            .field("id", &self.id)
+            .field("account", &self.account)
+            .field("url", &self.url)
+            .field("kinds", &self.kinds)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+// Body of a delivery, serialized as JSON.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct NotificationPayload {
+    pub delivery_id: DeliveryId,
+    pub account: String,
+    pub kind: NotificationKind,
+    pub event: String, // Name of the ledger event, e.g. "TransferCompleted".
+    pub counterparty: Option<String>,
+    pub asset: Option<AssetId>,
+    pub amount: Option<Amount>,
+    pub transaction_hash: Option<String>,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct HttpRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TransportError {
+    ConnectionFailed,
+    Timeout,
+    UnsupportedUrl, // Not a plain `http://` URL.
+    InvalidResponse,
+}
+
+/// Sends a request and returns the HTTP status. `TcpHttpClient` is the real client;
+/// tests point it at a `LocalHttpStandIn`.
+pub trait HttpTransport {
+    fn post(&mut self, request: &HttpRequest) -> Result<u16, TransportError>;
+}
+
+/// HTTP/1.1 client over a plain TCP connection, one connection per request.
+///
+/// There is no TLS here, so `register_endpoint` refuses `https://` URLs: such endpoints
+/// must be registered as the `http://` URL of a TLS-terminating proxy.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TcpHttpClient {
+    timeout: Duration, // For connecting, writing and reading, each on its own.
+}
+
+impl TcpHttpClient {
+    pub fn new(timeout: Duration) -> Self {
+        TcpHttpClient { timeout }
+    }
+}
+
+impl Default for TcpHttpClient {
+    fn default() -> Self {
+        TcpHttpClient::new(Duration::from_secs(10))
+    }
+}
+
+impl HttpTransport for TcpHttpClient {
+    fn post(&mut self, request: &HttpRequest) -> Result<u16, TransportError> {
+        let (host, port, path) = parse_http_url(&request.url).ok_or(TransportError::UnsupportedUrl)?;
+        let address = (host.as_str(), port)
+            .to_socket_addrs()
+            .map_err(|_| TransportError::ConnectionFailed)?
+            .next()
+            .ok_or(TransportError::ConnectionFailed)?;
+        let mut stream = TcpStream::connect_timeout(&address, self.timeout).map_err(transport_error)?;
+        stream.set_read_timeout(Some(self.timeout)).map_err(transport_error)?;
+        stream.set_write_timeout(Some(self.timeout)).map_err(transport_error)?;
+
+        let authority = if host.contains(':') { format!("[{}]:{}", host, port) } else { format!("{}:{}", host, port) };
+        let mut head = format!("POST {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n", path, authority, request.body.len());
+        for (name, value) in &request.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(&request.body)).map_err(transport_error)?;
+
+        // Only the status line matters; the rest of the response is ignored.
+        let mut status_line = String::new();
+        BufReader::new(&stream).read_line(&mut status_line).map_err(transport_error)?;
+        if status_line.is_empty() {
+            return Err(TransportError::ConnectionFailed);
+        }
+        parse_status_line(&status_line).ok_or(TransportError::InvalidResponse)
+    }
+}
+
+// Host, port and path of a plain `http://` URL. Anything that could break the request
+// line or the headers is rejected.
+fn parse_http_url(url: &str) -> Option<(String, u16, String)> {
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return None;
+    }
+    let rest = url.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    if authority.contains('@') {
+        return None;
+    }
+    let (host, port) = match authority.strip_prefix('[') {
+        // IPv6 literal, e.g. [::1]:8080
+        Some(bracketed) => {
+            let (host, after) = bracketed.split_once(']')?;
+            match after.strip_prefix(':') {
+                Some(port) => (host, port.parse().ok()?),
+                None if after.is_empty() => (host, 80),
+                None => return None,
+            }
+        }
+        None => match authority.split_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (authority, 80),
+        },
+    };
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_string(), port, path.to_string()))
+}
+
+// Status code of a line such as `HTTP/1.1 200 OK`.
+fn parse_status_line(line: &str) -> Option<u16> {
+    let mut parts = line.split_whitespace();
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    parts.next()?.parse().ok().filter(|status| (100..600).contains(status))
+}
+
+fn transport_error(error: io::Error) -> TransportError {
+    match error.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => TransportError::Timeout,
+        _ => TransportError::ConnectionFailed,
+    }
+}
+
+/// Webhook receiver listening on a local port, for tests and local development. Answers
+/// with the queued responses, then with 200, and keeps every request it received.
+pub struct LocalHttpStandIn {
+    address: SocketAddr,
+    state: Arc<Mutex<StandInState>>,
+    shutdown: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct StandInState {
+    received: Vec<HttpRequest>,
+    responses: VecDeque<Result<u16, TransportError>>,
+}
+
+impl LocalHttpStandIn {
+    /// Listens on an ephemeral port of 127.0.0.1 and serves requests on a background
+    /// thread until dropped.
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(StandInState::default()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let (state, shutdown) = (state.clone(), shutdown.clone());
+            thread::spawn(move || serve(listener, address, state, shutdown))
+        };
+        Ok(LocalHttpStandIn { address, state, shutdown, worker: Some(worker) })
+    }
+
+    // URL to register as the endpoint.
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.address)
+    }
+
+    /// Queues the answer to a request: a status, `ConnectionFailed` to close the connection
+    /// without answering or `Timeout` to leave it open and unanswered.
+    pub fn respond_with(&self, response: Result<u16, TransportError>) {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).responses.push_back(response);
+    }
+
+    // Every request received so far, oldest first.
+    pub fn received(&self) -> Vec<HttpRequest> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).received.clone()
+    }
+}
+
+impl Drop for LocalHttpStandIn {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wakes the listener up so that it sees the flag.
+        let _ = TcpStream::connect(self.address);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn serve(listener: TcpListener, address: SocketAddr, state: Arc<Mutex<StandInState>>, shutdown: Arc<AtomicBool>) {
+    // Connections left unanswered so that the client times out; closed on shutdown.
+    let mut held = Vec::new();
+    for stream in listener.incoming() {
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+        let Ok(mut stream) = stream else { continue };
+        let Some(request) = read_request(&stream, address) else { continue };
+        let response = {
+            let mut state = state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            state.received.push(request);
+            state.responses.pop_front().unwrap_or(Ok(200))
+        };
+        match response {
+            Ok(status) => {
+                let _ = write!(stream, "HTTP/1.1 {} Stand-in\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+            }
+            Err(TransportError::Timeout) => held.push(stream),
+            Err(_) => {} // Closed without a response.
+        }
+    }
+}
+
+// Reads one request: request line, headers and a `Content-Length` body.
+fn read_request(stream: &TcpStream, address: SocketAddr) -> Option<HttpRequest> {
+    stream.set_read_timeout(Some(Duration::from_secs(5))).ok()?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let path = request_line.split_whitespace().nth(1)?.to_string();
+
+    let mut headers = Vec::new();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        let (name, value) = (name.trim().to_string(), value.trim().to_string());
+        if name.eq_ignore_ascii_case("Content-Length") {
+            content_length = value.parse().ok()?;
+        }
+        headers.push((name, value));
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).ok()?;
+    Some(HttpRequest { url: format!("http://{}{}", address, path), headers, body })
+}
+
+// Same transport shared by several notifiers.
+impl<T: HttpTransport> HttpTransport for Arc<Mutex<T>> {
+    fn post(&mut self, request: &HttpRequest) -> Result<u16, TransportError> {
+        match self.lock() {
+            Ok(mut transport) => transport.post(request),
+            Err(_) => Err(TransportError::ConnectionFailed),
+        }
+    }
+}
+
+/// Exponential backoff: the n-th retry waits `initial_backoff * multiplier^(n-1)` seconds,
+/// up to `max_backoff`. After `max_attempts` failed attempts the delivery is dead-lettered.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: u64,
+    pub multiplier: u64,
+    pub max_backoff: u64,
+}
+
+impl RetryPolicy {
+    pub fn backoff(&self, attempts: u32) -> u64 {
+        let factor = self.multiplier.saturating_pow(attempts.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { max_attempts: 6, initial_backoff: 5, multiplier: 3, max_backoff: 3_600 }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum DeliveryStatus {
+    Pending { next_attempt_at: u64 },
+    Delivered { at: u64 },
+    DeadLettered { last_error: String },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Delivery {
+    pub id: DeliveryId,
+    pub endpoint: EndpointId,
+    pub payload: NotificationPayload,
+    pub attempts: u32,
+    pub status: DeliveryStatus,
+}
+
+/// Delivers ledger events to the HTTP endpoints registered by the accounts involved.
+///
+/// Events are queued as they are published and sent by `process_due`, so a slow or
+/// unreachable endpoint never holds up the ledger. Failed deliveries are retried with
+/// backoff and end up in the dead-letter queue, from where they can be replayed.
+pub struct Notifier {
+    endpoints: HashMap<EndpointId, Endpoint>,
+    deliveries: BTreeMap<DeliveryId, Delivery>,
+    dead_letters: Vec<DeliveryId>,
+    retry_policy: RetryPolicy,
+    transport: Box<dyn HttpTransport + Send>,
+    next_endpoint_id: EndpointId,
+    next_delivery_id: DeliveryId,
+    delivered_retention: u64, // Seconds a delivered notification stays queryable.
+}
+
+impl Notifier {
+    pub fn new(transport: Box<dyn HttpTransport + Send>, retry_policy: RetryPolicy) -> Self {
+        Notifier {
+            endpoints: HashMap::new(),
+            deliveries: BTreeMap::new(),
+            dead_letters: Vec::new(),
+            retry_policy,
+            transport,
+            next_endpoint_id: 0,
+            next_delivery_id: 0,
+            delivered_retention: DEFAULT_DELIVERED_RETENTION,
+        }
+    }
+
+    pub fn set_delivered_retention(&mut self, seconds: u64) {
+        self.delivered_retention = seconds;
+    }
+
+    // Writes the registered endpoints to the store; removed endpoints are deleted.
+    // Deliveries are not persisted: whatever was queued before a restart is lost.
+    pub fn persist(&self, store: &mut dyn StateStore) -> Result<(), StorageError> {
+        for key in store.keys_with_prefix(ENDPOINT_KEY_PREFIX) {
+            let id = key[ENDPOINT_KEY_PREFIX.len()..].parse::<EndpointId>().map_err(|_| StorageError::Corrupted)?;
+            if !self.endpoints.contains_key(&id) {
+                store.delete(&key);
+            }
+        }
+        for (id, endpoint) in &self.endpoints {
+            let value = serde_json::to_vec(endpoint).map_err(|_| StorageError::Serialization)?;
+            store.put(&format!("{}{}", ENDPOINT_KEY_PREFIX, id), value);
+        }
+        let next = serde_json::to_vec(&self.next_endpoint_id).map_err(|_| StorageError::Serialization)?;
+        store.put(NEXT_ENDPOINT_ID_KEY, next);
+        Ok(())
+    }
+
+    /// Rebuilds a notifier with the endpoints committed to the store. Attach it to the
+    /// recovered ledger with `attach` so that it keeps receiving events and being persisted.
+    pub fn restore(store: &dyn StateStore, transport: Box<dyn HttpTransport + Send>, retry_policy: RetryPolicy) -> Result<Self, StorageError> {
+        let mut notifier = Notifier::new(transport, retry_policy);
+        for key in store.keys_with_prefix(ENDPOINT_KEY_PREFIX) {
+            let value = store.get(&key).ok_or(StorageError::Corrupted)?;
+            let endpoint: Endpoint = serde_json::from_slice(&value).map_err(|_| StorageError::Serialization)?;
+            notifier.endpoints.insert(endpoint.id, endpoint);
+        }
+        if let Some(value) = store.get(NEXT_ENDPOINT_ID_KEY) {
+            notifier.next_endpoint_id = serde_json::from_slice(&value).map_err(|_| StorageError::Serialization)?;
+        }
+        Ok(notifier)
+    }
+
+    // Data the account signs to register an endpoint.
+    pub fn registration_data(account_id: &str, url: &str, kinds: &[NotificationKind], nonce: u64) -> String {
+        let kinds: Vec<String> = kinds.iter().map(|kind| format!("{:?}", kind)).collect();
+        AuthorizationPayload::new(Action::RegisterWebhook).string(account_id).string(url).strings(&kinds).u64(nonce).signing_data()
+    }
+
+    // Data the account signs to remove an endpoint.
+    pub fn removal_data(account_id: &str, endpoint: EndpointId, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::RemoveWebhook).string(account_id).u64(endpoint).u64(nonce).signing_data()
+    }
+
+    /// Registers an endpoint for the notifications of `account_id`. Must be signed by the account.
+    /// Only plain `http://` URLs are accepted, since deliveries are sent without TLS.
+    pub fn register_endpoint(
+        &mut self,
+        accounts: &mut Accounts,
+        account_id: &String,
+        url: String,
+        secret: Vec<u8>,
+        kinds: Vec<NotificationKind>,
+        signatures: &[AccountSignature],
+    ) -> Result<EndpointId, NotificationError> {
+        if parse_http_url(&url).is_none() || secret.is_empty() || kinds.is_empty() {
+            return Err(NotificationError::InvalidEndpoint);
+        }
+        let nonce = accounts.get_nonce(account_id)?;
+        let data = Self::registration_data(account_id, &url, &kinds, nonce);
+        Self::check_signatures(accounts, account_id, &data, signatures)?;
+        accounts.increment_nonce(account_id)?;
+
+        let id = self.next_endpoint_id;
+        self.next_endpoint_id += 1;
+        self.endpoints.insert(id, Endpoint { id, account: account_id.clone(), url, kinds, secret });
+        Ok(id)
+    }
+
+    /// Removes an endpoint; deliveries already queued for it are dead-lettered.
+    pub fn remove_endpoint(&mut self, accounts: &mut Accounts, account_id: &String, endpoint: EndpointId, signatures: &[AccountSignature]) -> Result<(), NotificationError> {
+        if self.endpoints.get(&endpoint).map(|e| &e.account) != Some(account_id) {
+            return Err(NotificationError::EndpointNotFound);
+        }
+        let nonce = accounts.get_nonce(account_id)?;
+        Self::check_signatures(accounts, account_id, &Self::removal_data(account_id, endpoint, nonce), signatures)?;
+        accounts.increment_nonce(account_id)?;
+
+        self.endpoints.remove(&endpoint);
+        let orphaned: Vec<DeliveryId> = self
+            .deliveries
+            .values()
+            .filter(|d| d.endpoint == endpoint && matches!(d.status, DeliveryStatus::Pending { .. }))
+            .map(|d| d.id)
+            .collect();
+        for id in orphaned {
+            self.dead_letter(id, "endpoint removed".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn endpoints_of(&self, account_id: &String) -> Vec<&Endpoint> {
+        self.endpoints.values().filter(|endpoint| &endpoint.account == account_id).collect()
+    }
+
+    /// Queues a delivery to every endpoint interested in `event`. Nothing is sent yet.
+    pub fn enqueue(&mut self, event: &LedgerEvent, now: u64) {
+        for (account, kind, notification) in Self::notifications_for(event) {
+            let targets: Vec<EndpointId> = self
+                .endpoints
+                .values()
+                .filter(|endpoint| endpoint.account == account && endpoint.kinds.contains(&kind))
+                .map(|endpoint| endpoint.id)
+                .collect();
+            for endpoint in targets {
+                let id = self.next_delivery_id;
+                self.next_delivery_id += 1;
+                let payload = NotificationPayload { delivery_id: id, account: account.clone(), kind, timestamp: now, ..notification.clone() };
+                self.deliveries.insert(id, Delivery { id, endpoint, payload, attempts: 0, status: DeliveryStatus::Pending { next_attempt_at: now } });
+            }
+        }
+    }
+
+    // Accounts to notify about an event, with the kind of notification each one gets.
+    fn notifications_for(event: &LedgerEvent) -> Vec<(String, NotificationKind, NotificationPayload)> {
+        let payload = |event: &str, counterparty: Option<&String>, asset: Option<&AssetId>, amount: Option<Amount>, transaction_hash: Option<&String>| NotificationPayload {
+            delivery_id: 0,
+            account: String::new(),
+            kind: NotificationKind::Security,
+            event: event.to_string(),
+            counterparty: counterparty.cloned(),
+            asset: asset.cloned(),
+            amount,
+            transaction_hash: transaction_hash.cloned(),
+            timestamp: 0,
+        };
+        match event {
+            LedgerEvent::TransferCompleted { from, to, asset, amount, transaction_hash, .. } => vec![
+                (from.clone(), NotificationKind::OutgoingTransfer, payload("TransferCompleted", Some(to), Some(asset), Some(*amount), transaction_hash.as_ref())),
+                (to.clone(), NotificationKind::IncomingTransfer, payload("TransferCompleted", Some(from), Some(asset), Some(*amount), transaction_hash.as_ref())),
+            ],
+            LedgerEvent::CoinsIssued { to, amount, .. } => {
+                vec![(to.clone(), NotificationKind::IncomingTransfer, payload("CoinsIssued", None, None, Some(*amount), None))]
+            }
+            LedgerEvent::AssetIssued { asset, to, amount } => {
+                vec![(to.clone(), NotificationKind::IncomingTransfer, payload("AssetIssued", None, Some(asset), Some(*amount), None))]
+            }
+            LedgerEvent::NftMinted { item, owner, transaction_hash } => {
+                vec![(owner.clone(), NotificationKind::IncomingTransfer, payload("NftMinted", None, Some(item), None, Some(transaction_hash)))]
+            }
+            LedgerEvent::NftTransferred { item, from, to, transaction_hash } => vec![
+                (from.clone(), NotificationKind::OutgoingTransfer, payload("NftTransferred", Some(to), Some(item), None, Some(transaction_hash))),
+                (to.clone(), NotificationKind::IncomingTransfer, payload("NftTransferred", Some(from), Some(item), None, Some(transaction_hash))),
+            ],
+            LedgerEvent::VestingGranted { grantor, beneficiary, amount, transaction_hash } => vec![
+                (grantor.clone(), NotificationKind::OutgoingTransfer, payload("VestingGranted", Some(beneficiary), None, Some(*amount), Some(transaction_hash))),
+                (beneficiary.clone(), NotificationKind::IncomingTransfer, payload("VestingGranted", Some(grantor), None, Some(*amount), Some(transaction_hash))),
+            ],
+            LedgerEvent::AccountLocked { account } => vec![(account.clone(), NotificationKind::Security, payload("AccountLocked", None, None, None, None))],
+            LedgerEvent::AccountUnlocked { account } => vec![(account.clone(), NotificationKind::Security, payload("AccountUnlocked", None, None, None, None))],
+            LedgerEvent::KeyRotated { account } => vec![(account.clone(), NotificationKind::Security, payload("KeyRotated", None, None, None, None))],
+            LedgerEvent::AccountClosed { account, swept_to } => {
+                vec![(account.clone(), NotificationKind::Security, payload("AccountClosed", swept_to.as_ref(), None, None, None))]
+            }
+            LedgerEvent::RoleGranted { account, .. } => vec![(account.clone(), NotificationKind::Security, payload("RoleGranted", None, None, None, None))],
+            LedgerEvent::RoleRevoked { account, .. } => vec![(account.clone(), NotificationKind::Security, payload("RoleRevoked", None, None, None, None))],
+            LedgerEvent::OwnershipTransferProposed { owner, new_owner, .. } => vec![
+                (owner.clone(), NotificationKind::Security, payload("OwnershipTransferProposed", Some(new_owner), None, None, None)),
+                (new_owner.clone(), NotificationKind::Security, payload("OwnershipTransferProposed", Some(owner), None, None, None)),
+            ],
+            LedgerEvent::OwnershipTransferAccepted { previous_owner, new_owner } => vec![
+                (previous_owner.clone(), NotificationKind::Security, payload("OwnershipTransferAccepted", Some(new_owner), None, None, None)),
+                (new_owner.clone(), NotificationKind::Security, payload("OwnershipTransferAccepted", Some(previous_owner), None, None, None)),
+            ],
+            LedgerEvent::OwnershipTransferCancelled { owner, new_owner } => vec![
+                (owner.clone(), NotificationKind::Security, payload("OwnershipTransferCancelled", Some(new_owner), None, None, None)),
+                (new_owner.clone(), NotificationKind::Security, payload("OwnershipTransferCancelled", Some(owner), None, None, None)),
+            ],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Attempts every pending delivery that is due at `now`. Returns how many succeeded.
+    /// Deliveries that succeeded longer than the retention window ago are forgotten first.
+    pub fn process_due(&mut self, now: u64) -> usize {
+        self.prune_delivered(now);
+        let due: Vec<DeliveryId> = self
+            .deliveries
+            .values()
+            .filter(|d| matches!(d.status, DeliveryStatus::Pending { next_attempt_at } if next_attempt_at <= now))
+            .map(|d| d.id)
+            .collect();
+        let mut delivered = 0;
+        for id in due {
+            if self.attempt(id, now) {
+                delivered += 1;
+            }
+        }
+        delivered
+    }
+
+    fn attempt(&mut self, id: DeliveryId, now: u64) -> bool {
+        let Some(delivery) = self.deliveries.get(&id) else { return false };
+        let Some(endpoint) = self.endpoints.get(&delivery.endpoint) else {
+            self.dead_letter(id, "endpoint removed".to_string());
+            return false;
+        };
+        let body = serde_json::to_vec(&delivery.payload).unwrap_or_default();
+        let request = HttpRequest {
+            url: endpoint.url.clone(),
+            headers: vec![
+                ("Content-Type".to_string(), "application/json".to_string()),
+                (DELIVERY_HEADER.to_string(), id.to_string()),
+                (SIGNATURE_HEADER.to_string(), format!("sha256={}", hex::encode(hmac_sha256(&endpoint.secret, &body)))),
+            ],
+            body,
+        };
+
+        let error = match self.transport.post(&request) {
+            Ok(status) if (200..300).contains(&status) => None,
+            Ok(status) => Some(format!("HTTP {}", status)),
+            Err(e) => Some(format!("{:?}", e)),
+        };
+        let Some(delivery) = self.deliveries.get_mut(&id) else { return false };
+        delivery.attempts += 1;
+        match error {
+            None => {
+                delivery.status = DeliveryStatus::Delivered { at: now };
+                true
+            }
+            Some(error) if delivery.attempts >= self.retry_policy.max_attempts => {
+                self.dead_letter(id, error);
+                false
+            }
+            Some(_) => {
+                let next_attempt_at = now.saturating_add(self.retry_policy.backoff(delivery.attempts));
+                delivery.status = DeliveryStatus::Pending { next_attempt_at };
+                false
+            }
+        }
+    }
+
+    fn dead_letter(&mut self, id: DeliveryId, last_error: String) {
+        if let Some(delivery) = self.deliveries.get_mut(&id) {
+            delivery.status = DeliveryStatus::DeadLettered { last_error };
+            self.dead_letters.push(id);
+        }
+    }
+
+    /// Forgets the deliveries that succeeded at least `delivered_retention` seconds before
+    /// `now`; their status can no longer be queried. Returns how many were removed.
+    /// Pending and dead-lettered deliveries are kept.
+    pub fn prune_delivered(&mut self, now: u64) -> usize {
+        let retention = self.delivered_retention;
+        let before = self.deliveries.len();
+        self.deliveries.retain(|_, delivery| match delivery.status {
+            DeliveryStatus::Delivered { at } => now < at.saturating_add(retention),
+            _ => true,
+        });
+        before - self.deliveries.len()
+    }
+
+    pub fn delivery_status(&self, id: DeliveryId) -> Option<&DeliveryStatus> {
+        self.deliveries.get(&id).map(|delivery| &delivery.status)
+    }
+
+    pub fn deliveries_for(&self, endpoint: EndpointId) -> Vec<&Delivery> {
+        self.deliveries.values().filter(|delivery| delivery.endpoint == endpoint).collect()
+    }
+
+    // Oldest first.
+    pub fn dead_letters(&self) -> Vec<&Delivery> {
+        self.dead_letters.iter().filter_map(|id| self.deliveries.get(id)).collect()
+    }
+
+    /// Moves a dead-lettered delivery back to the queue, with a fresh set of attempts.
+    pub fn replay_dead_letter(&mut self, id: DeliveryId, now: u64) -> Result<(), NotificationError> {
+        let position = self.dead_letters.iter().position(|&dead| dead == id).ok_or(NotificationError::DeliveryNotFound)?;
+        let delivery = self.deliveries.get_mut(&id).ok_or(NotificationError::DeliveryNotFound)?;
+        if !self.endpoints.contains_key(&delivery.endpoint) {
+            return Err(NotificationError::EndpointNotFound);
+        }
+        delivery.attempts = 0;
+        delivery.status = DeliveryStatus::Pending { next_attempt_at: now };
+        self.dead_letters.remove(position);
+        Ok(())
+    }
+
+    // Checks that `signatures` satisfy the signing policy of `account_id`.
+    fn check_signatures(accounts: &Accounts, account_id: &String, data: &String, signatures: &[AccountSignature]) -> Result<(), NotificationError> {
+        if signatures.iter().any(|s| accounts.is_key_revoked(&s.public_key)) {
+            return Err(NotificationError::KeyRevoked);
+        }
+        let account = accounts.get_account_details(account_id)?;
+        if !account.signing_policy().is_satisfied(data, signatures) {
+            return Err(NotificationError::Unauthorized);
+        }
+        Ok(())
+    }
+}
+
+/// Subscribes `notifier` to the ledger's events. Deliveries are only queued here;
+/// call `Notifier::process_due` to send them.
+pub fn attach(ledger: &mut Ledger, notifier: Arc<Mutex<Notifier>>) -> SubscriberId {
+    ledger.set_notifier(notifier.clone());
+    ledger.subscribe(move |event| {
+        if let Ok(mut notifier) = notifier.lock() {
+            notifier.enqueue(event, current_timestamp());
+        }
+    })
+}
+
+/// HMAC-SHA256 (RFC 2104). Receivers recompute it over the raw body with their secret and
+/// compare it to the `X-Luks-Signature` header.
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner_pad: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    let outer_pad: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+
+    let mut inner = Sha256::new();
+    inner.update(&inner_pad);
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(&outer_pad);
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+fn current_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transfer::TransferType;
+    use crate::testing::{self, ALICE, BOB};
+
+    const SECRET: &[u8] = b"alice's webhook secret";
+    const NOW: u64 = 1_700_000_000;
+
+    // Notifier whose client gives up on an unanswered request after 200 ms.
+    fn notifier(retry_policy: RetryPolicy) -> Notifier {
+        Notifier::new(Box::new(TcpHttpClient::new(Duration::from_millis(200))), retry_policy)
+    }
+
+    fn register(notifier: &mut Notifier, accounts: &mut Accounts, url: &str) -> Result<EndpointId, NotificationError> {
+        let alice = testing::address(&ALICE);
+        let kinds = vec![NotificationKind::IncomingTransfer];
+        let nonce = accounts.get_nonce(&alice)?;
+        let signature = testing::sign(&ALICE, &Notifier::registration_data(&alice, url, &kinds, nonce));
+        notifier.register_endpoint(accounts, &alice, url.to_string(), SECRET.to_vec(), kinds, &[signature])
+    }
+
+    // Bob pays alice, which notifies alice's endpoint.
+    fn payment() -> LedgerEvent {
+        LedgerEvent::TransferCompleted {
+            from: testing::address(&BOB),
+            to: testing::address(&ALICE),
+            asset: crate::assets::native_asset_id(),
+            amount: Amount::from_units(500),
+            transfer_type: TransferType::PeerToPeer,
+            transaction_hash: Some("ab".repeat(32)),
+        }
+    }
+
+    fn header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+        request.headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(hex::encode(mac), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn deliveries_carry_the_hmac_of_their_body() {
+        let stand_in = LocalHttpStandIn::start().unwrap();
+        let mut accounts = testing::accounts();
+        let mut notifier = notifier(RetryPolicy::default());
+        let endpoint = register(&mut notifier, &mut accounts, &stand_in.url()).ok().unwrap();
+
+        notifier.enqueue(&payment(), NOW);
+        assert_eq!(notifier.process_due(NOW), 1);
+        let delivery = notifier.deliveries_for(endpoint)[0].clone();
+        assert_eq!(delivery.status, DeliveryStatus::Delivered { at: NOW });
+
+        let received = stand_in.received();
+        assert_eq!(received.len(), 1);
+        let expected = format!("sha256={}", hex::encode(hmac_sha256(SECRET, &received[0].body)));
+        assert_eq!(header(&received[0], SIGNATURE_HEADER), Some(expected.as_str()));
+        assert_eq!(header(&received[0], DELIVERY_HEADER), Some(delivery.id.to_string().as_str()));
+        let body: serde_json::Value = serde_json::from_slice(&received[0].body).unwrap();
+        assert_eq!(body["event"], "TransferCompleted");
+        assert_eq!(body["counterparty"], testing::address(&BOB).as_str());
+    }
+
+    #[test]
+    fn failed_deliveries_are_retried_with_backoff() {
+        let stand_in = LocalHttpStandIn::start().unwrap();
+        stand_in.respond_with(Ok(503));
+        stand_in.respond_with(Err(TransportError::Timeout));
+        let mut accounts = testing::accounts();
+        let mut notifier = notifier(RetryPolicy::default());
+        let endpoint = register(&mut notifier, &mut accounts, &stand_in.url()).ok().unwrap();
+        notifier.enqueue(&payment(), NOW);
+        let id = notifier.deliveries_for(endpoint)[0].id;
+
+        // 5 seconds after the first failure, then 15 after the second.
+        assert_eq!(notifier.process_due(NOW), 0);
+        assert_eq!(notifier.delivery_status(id), Some(&DeliveryStatus::Pending { next_attempt_at: NOW + 5 }));
+        assert_eq!(notifier.process_due(NOW + 4), 0);
+        assert_eq!(stand_in.received().len(), 1);
+        assert_eq!(notifier.process_due(NOW + 5), 0);
+        assert_eq!(notifier.delivery_status(id), Some(&DeliveryStatus::Pending { next_attempt_at: NOW + 20 }));
+        assert_eq!(notifier.process_due(NOW + 20), 1);
+        assert_eq!(notifier.delivery_status(id), Some(&DeliveryStatus::Delivered { at: NOW + 20 }));
+        assert_eq!(stand_in.received().len(), 3);
+    }
+
+    #[test]
+    fn dead_letters_can_be_replayed() {
+        let stand_in = LocalHttpStandIn::start().unwrap();
+        stand_in.respond_with(Ok(500));
+        stand_in.respond_with(Err(TransportError::ConnectionFailed));
+        let mut accounts = testing::accounts();
+        let policy = RetryPolicy { max_attempts: 2, ..RetryPolicy::default() };
+        let mut notifier = notifier(policy);
+        let endpoint = register(&mut notifier, &mut accounts, &stand_in.url()).ok().unwrap();
+        notifier.enqueue(&payment(), NOW);
+        let id = notifier.deliveries_for(endpoint)[0].id;
+
+        assert_eq!(notifier.process_due(NOW), 0);
+        assert_eq!(notifier.process_due(NOW + 5), 0);
+        assert_eq!(notifier.delivery_status(id), Some(&DeliveryStatus::DeadLettered { last_error: "ConnectionFailed".to_string() }));
+        assert_eq!(notifier.dead_letters().len(), 1);
+        // Nothing is attempted any more.
+        assert_eq!(notifier.process_due(NOW + 3_600), 0);
+        assert_eq!(stand_in.received().len(), 2);
+
+        assert!(notifier.replay_dead_letter(id, NOW + 3_600).is_ok());
+        assert!(notifier.dead_letters().is_empty());
+        assert!(matches!(notifier.replay_dead_letter(id, NOW + 3_600), Err(NotificationError::DeliveryNotFound)));
+        assert_eq!(notifier.process_due(NOW + 3_600), 1);
+        assert_eq!(notifier.delivery_status(id), Some(&DeliveryStatus::Delivered { at: NOW + 3_600 }));
+    }
+
+    #[test]
+    fn endpoints_must_be_plain_http_and_keep_their_secret() {
+        let mut accounts = testing::accounts();
+        let mut notifier = notifier(RetryPolicy::default());
+        assert!(matches!(register(&mut notifier, &mut accounts, "https://hooks.example.com/luks"), Err(NotificationError::InvalidEndpoint)));
+        assert!(matches!(register(&mut notifier, &mut accounts, "http://user@hooks.example.com/"), Err(NotificationError::InvalidEndpoint)));
+        assert_eq!(accounts.get_nonce(&testing::address(&ALICE)).ok(), Some(0));
+
+        let id = register(&mut notifier, &mut accounts, "http://127.0.0.1:8080/luks").ok().unwrap();
+        let endpoint = notifier.endpoints_of(&testing::address(&ALICE))[0];
+        assert_eq!(endpoint.id, id);
+        let debug = format!("{:?}", endpoint);
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains(&format!("{:?}", SECRET.to_vec())));
+    }
+}
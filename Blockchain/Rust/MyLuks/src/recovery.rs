@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::accounts::{Accounts, AccountError, AccountSignature, KeyRotationAuthority, MultiSigPolicy, PublicKey};
+use crate::address;
+use crate::encoding::{Action, AuthorizationPayload};
+use crate::storage::{StateStore, StorageError};
+
+// Prefixes of guardian configurations and recoveries in progress in the store
+const GUARDIANS_KEY_PREFIX: &str = "guardians/";
+const PENDING_RECOVERY_KEY_PREFIX: &str = "recovery/";
+
+/// Errors for guardian-based account recovery.
+pub enum RecoveryError {
+    AccountNotFound,
+    InvalidGuardianSet,
+    NotAGuardian,
+    Unauthorized,
+    KeyRevoked,
+    RecoveryInProgress,
+    NoRecoveryInProgress,
+    AlreadyApproved,
+    ThresholdNotMet,
+    DelayNotElapsed,
+    MultiSigAccount, // Recovery replaces a single key; multisig accounts rotate their key set instead.
+    Account(AccountError),
+}
+
+impl From<AccountError> for RecoveryError {
+    fn from(error: AccountError) -> Self {
+        match error {
+            AccountError::AccountNotFound => RecoveryError::AccountNotFound,
+            other => RecoveryError::Account(other),
+        }
+    }
+}
+
+// Guardians nominated by an account and how many of them must agree.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct GuardianConfig {
+    pub guardians: Vec<String>,
+    pub threshold: usize,
+    pub delay_secs: u64, // Time the owner has to cancel once a recovery starts.
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PendingRecovery {
+    pub new_key: PublicKey,
+    pub approvals: Vec<String>,
+    pub started_at: u64,
+}
+
+/// Social recovery: guardians jointly replace the key of an account whose owner lost it.
+/// Only single-key accounts can nominate guardians.
+///
+/// Starting a recovery freezes the account: it still receives, but cannot send. Once enough
+/// guardians approved and the delay has passed, the holder of the new key finalises the
+/// recovery; until then the owner may cancel with the current key.
+#[derive(Debug, PartialEq)]
+pub struct Recovery {
+    configs: HashMap<String, GuardianConfig>,
+    pending: HashMap<String, PendingRecovery>,
+}
+
+impl Default for Recovery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recovery {
+    pub fn new() -> Self {
+        Recovery {
+            configs: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn get_guardians(&self, account_id: &String) -> Option<&GuardianConfig> {
+        self.configs.get(account_id)
+    }
+
+    pub fn get_pending_recovery(&self, account_id: &String) -> Option<&PendingRecovery> {
+        self.pending.get(account_id)
+    }
+
+    // Data the owner signs to nominate guardians.
+    pub fn guardians_data(account_id: &str, config: &GuardianConfig, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::SetGuardians)
+            .string(account_id)
+            .strings(&config.guardians)
+            .u64(config.threshold as u64)
+            .u64(config.delay_secs)
+            .u64(nonce)
+            .signing_data()
+    }
+
+    // Data a guardian signs to start or approve a recovery.
+    pub fn approval_data(account_id: &str, new_key: &PublicKey, guardian_nonce: u64) -> String {
+        AuthorizationPayload::new(Action::ApproveRecovery).string(account_id).key(new_key).u64(guardian_nonce).signing_data()
+    }
+
+    // Data the owner signs to cancel a recovery.
+    pub fn cancel_data(account_id: &str, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::CancelRecovery).string(account_id).u64(nonce).signing_data()
+    }
+
+    // Data the new key signs to finalise a recovery, proving its holder controls it.
+    pub fn finalize_data(account_id: &str, new_key: &PublicKey, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::FinalizeRecovery).string(account_id).key(new_key).u64(nonce).signing_data()
+    }
+
+    // Drops the guardians and any pending recovery of a closed account, so that its
+    // guardians are free to close in turn.
+    pub(crate) fn forget(&mut self, account_id: &String) {
+        self.configs.remove(account_id);
+        self.pending.remove(account_id);
+    }
+
+    // Whether `account_id` is a guardian of some account.
+    pub fn is_guardian(&self, account_id: &String) -> bool {
+        self.configs.values().any(|config| config.guardians.contains(account_id))
+    }
+
+    /// Nominates the guardians of an account. Must be signed by the account itself.
+    pub fn set_guardians(&mut self, accounts: &mut Accounts, account_id: &String, config: GuardianConfig, signatures: &[AccountSignature]) -> Result<(), RecoveryError> {
+        if self.pending.contains_key(account_id) {
+            return Err(RecoveryError::RecoveryInProgress);
+        }
+        let mut distinct: Vec<&String> = Vec::new();
+        for guardian in &config.guardians {
+            if guardian == account_id || distinct.contains(&guardian) || address::validate_address(guardian).is_err() || accounts.get_account_details(guardian).is_err() {
+                return Err(RecoveryError::InvalidGuardianSet);
+            }
+            distinct.push(guardian);
+        }
+        if config.threshold == 0 || config.threshold > config.guardians.len() {
+            return Err(RecoveryError::InvalidGuardianSet);
+        }
+
+        let owner = accounts.get_account_details(account_id)?;
+        if !owner.signing_policy().is_single_key() {
+            return Err(RecoveryError::MultiSigAccount);
+        }
+        let data = Self::guardians_data(account_id, &config, owner.nonce);
+        Self::check_signatures(accounts, account_id, &data, signatures)?;
+
+        accounts.increment_nonce(account_id)?;
+        self.configs.insert(account_id.clone(), config);
+        Ok(())
+    }
+
+    /// Starts a recovery towards `new_key`, counting as the first guardian approval.
+    /// The account cannot send until the recovery is finalised or cancelled.
+    pub fn initiate_recovery(&mut self, accounts: &mut Accounts, account_id: &String, guardian_id: &String, new_key: PublicKey, signatures: &[AccountSignature], now: u64) -> Result<(), RecoveryError> {
+        if self.pending.contains_key(account_id) {
+            return Err(RecoveryError::RecoveryInProgress);
+        }
+        if accounts.is_key_revoked(&new_key) {
+            return Err(RecoveryError::KeyRevoked);
+        }
+        self.check_guardian_approval(accounts, account_id, guardian_id, &new_key, signatures)?;
+
+        accounts.increment_nonce(guardian_id)?;
+        accounts.set_recovering(account_id, true)?;
+        self.pending.insert(account_id.clone(), PendingRecovery {
+            new_key,
+            approvals: vec![guardian_id.clone()],
+            started_at: now,
+        });
+        Ok(())
+    }
+
+    /// Adds a guardian approval to the pending recovery.
+    pub fn approve_recovery(&mut self, accounts: &mut Accounts, account_id: &String, guardian_id: &String, signatures: &[AccountSignature]) -> Result<(), RecoveryError> {
+        let new_key = match self.pending.get(account_id) {
+            Some(pending) if pending.approvals.contains(guardian_id) => return Err(RecoveryError::AlreadyApproved),
+            Some(pending) => pending.new_key.clone(),
+            None => return Err(RecoveryError::NoRecoveryInProgress),
+        };
+        self.check_guardian_approval(accounts, account_id, guardian_id, &new_key, signatures)?;
+
+        accounts.increment_nonce(guardian_id)?;
+        if let Some(pending) = self.pending.get_mut(account_id) {
+            pending.approvals.push(guardian_id.clone());
+        }
+        Ok(())
+    }
+
+    /// Cancels the pending recovery. Must be signed by the account's current key(s).
+    pub fn cancel_recovery(&mut self, accounts: &mut Accounts, account_id: &String, signatures: &[AccountSignature]) -> Result<(), RecoveryError> {
+        if !self.pending.contains_key(account_id) {
+            return Err(RecoveryError::NoRecoveryInProgress);
+        }
+        let owner = accounts.get_account_details(account_id)?;
+        let data = Self::cancel_data(account_id, owner.nonce);
+        Self::check_signatures(accounts, account_id, &data, signatures)?;
+
+        accounts.increment_nonce(account_id)?;
+        self.pending.remove(account_id);
+        accounts.set_recovering(account_id, false)?;
+        Ok(())
+    }
+
+    /// Installs the new key once the threshold is met and the cancellation delay has passed.
+    /// Must be signed by the new key over `finalize_data`.
+    pub fn finalize_recovery(&mut self, accounts: &mut Accounts, account_id: &String, signature: &AccountSignature, now: u64, height: u64) -> Result<(), RecoveryError> {
+        let config = self.configs.get(account_id).ok_or(RecoveryError::InvalidGuardianSet)?;
+        let pending = self.pending.get(account_id).ok_or(RecoveryError::NoRecoveryInProgress)?;
+        if pending.approvals.len() < config.threshold {
+            return Err(RecoveryError::ThresholdNotMet);
+        }
+        if now < pending.started_at.saturating_add(config.delay_secs) {
+            return Err(RecoveryError::DelayNotElapsed);
+        }
+        if accounts.is_key_revoked(&pending.new_key) {
+            return Err(RecoveryError::KeyRevoked);
+        }
+        let account = accounts.get_account_details(account_id)?;
+        if !account.signing_policy().is_single_key() {
+            return Err(RecoveryError::MultiSigAccount);
+        }
+        let data = Self::finalize_data(account_id, &pending.new_key, account.nonce);
+        if !MultiSigPolicy::single(pending.new_key.clone()).is_satisfied(&data, std::slice::from_ref(signature)) {
+            return Err(RecoveryError::Unauthorized);
+        }
+
+        let pending = self.pending.remove(account_id).ok_or(RecoveryError::NoRecoveryInProgress)?;
+        accounts.replace_key(account_id, pending.new_key, KeyRotationAuthority::Guardians, now, height)?;
+        accounts.set_recovering(account_id, false)?;
+        Ok(())
+    }
+
+    fn check_guardian_approval(&self, accounts: &Accounts, account_id: &String, guardian_id: &String, new_key: &PublicKey, signatures: &[AccountSignature]) -> Result<(), RecoveryError> {
+        let config = self.configs.get(account_id).ok_or(RecoveryError::InvalidGuardianSet)?;
+        if !config.guardians.contains(guardian_id) {
+            return Err(RecoveryError::NotAGuardian);
+        }
+        let guardian = accounts.get_account_details(guardian_id)?;
+        let data = Self::approval_data(account_id, new_key, guardian.nonce);
+        Self::check_signatures(accounts, guardian_id, &data, signatures)
+    }
+
+    // Checks that `signatures` satisfy the signing policy of `signer_id`.
+    fn check_signatures(accounts: &Accounts, signer_id: &String, data: &String, signatures: &[AccountSignature]) -> Result<(), RecoveryError> {
+        if signatures.iter().any(|s| accounts.is_key_revoked(&s.public_key)) {
+            return Err(RecoveryError::KeyRevoked);
+        }
+        let signer = accounts.get_account_details(signer_id)?;
+        if !signer.signing_policy().is_satisfied(data, signatures) {
+            return Err(RecoveryError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    // Writes the guardian configurations and pending recoveries; removed entries are deleted.
+    pub fn persist(&self, store: &mut dyn StateStore) -> Result<(), StorageError> {
+        persist_map(store, GUARDIANS_KEY_PREFIX, &self.configs)?;
+        persist_map(store, PENDING_RECOVERY_KEY_PREFIX, &self.pending)
+    }
+
+    // Rebuilds the guardian configurations and pending recoveries from the store.
+    pub fn restore(store: &dyn StateStore) -> Result<Self, StorageError> {
+        Ok(Recovery {
+            configs: restore_map(store, GUARDIANS_KEY_PREFIX)?,
+            pending: restore_map(store, PENDING_RECOVERY_KEY_PREFIX)?,
+        })
+    }
+}
+
+fn persist_map<T: Serialize>(store: &mut dyn StateStore, prefix: &str, map: &HashMap<String, T>) -> Result<(), StorageError> {
+    for key in store.keys_with_prefix(prefix) {
+        if !map.contains_key(&key[prefix.len()..]) {
+            store.delete(&key);
+        }
+    }
+    for (account_id, entry) in map {
+        let value = serde_json::to_vec(entry).map_err(|_| StorageError::Serialization)?;
+        store.put(&format!("{}{}", prefix, account_id), value);
+    }
+    Ok(())
+}
+
+fn restore_map<T: for<'de> Deserialize<'de>>(store: &dyn StateStore, prefix: &str) -> Result<HashMap<String, T>, StorageError> {
+    let mut map = HashMap::new();
+    for key in store.keys_with_prefix(prefix) {
+        let value = store.get(&key).ok_or(StorageError::Corrupted)?;
+        let entry: T = serde_json::from_slice(&value).map_err(|_| StorageError::Serialization)?;
+        map.insert(key[prefix.len()..].to_string(), entry);
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::amount::Amount;
+    use crate::events::LedgerEvent;
+    use crate::history::{HistoryEntryKind, HistoryFilter, HistoryIndex};
+    use crate::ledger::{Ledger, LedgerError};
+    use crate::storage::MemoryStore;
+    use crate::testing::{self, ALICE, BOB, CAROL};
+    use crate::validate_transaction::ValidationError;
+
+    const NEW_KEY: [u8; 32] = [0x07; 32];
+
+    // Alice names bob and carol as guardians, both needed, with `delay_secs` to cancel.
+    fn ledger_with_guardians(delay_secs: u64) -> Ledger {
+        let mut ledger = testing::ledger();
+        let alice = testing::address(&ALICE);
+        let config = GuardianConfig { guardians: vec![testing::address(&BOB), testing::address(&CAROL)], threshold: 2, delay_secs };
+        let signature = testing::sign(&ALICE, &Recovery::guardians_data(&alice, &config, 0));
+        assert!(ledger.set_guardians(&alice, config, &[signature]).is_ok());
+        ledger
+    }
+
+    fn approval(ledger: &Ledger, guardian: &[u8; 32]) -> AccountSignature {
+        let nonce = ledger.accounts.get_nonce(&testing::address(guardian)).unwrap_or(0);
+        testing::sign(guardian, &Recovery::approval_data(&testing::address(&ALICE), &testing::public_key(&NEW_KEY), nonce))
+    }
+
+    fn start(ledger: &mut Ledger) -> Result<(), LedgerError> {
+        let signature = approval(ledger, &BOB);
+        ledger.initiate_recovery(&testing::address(&ALICE), &testing::address(&BOB), testing::public_key(&NEW_KEY), &[signature])
+    }
+
+    fn approve(ledger: &mut Ledger, guardian: &[u8; 32]) -> Result<(), LedgerError> {
+        let signature = approval(ledger, guardian);
+        ledger.approve_recovery(&testing::address(&ALICE), &testing::address(guardian), &[signature])
+    }
+
+    fn finalize(ledger: &mut Ledger, key: &[u8; 32]) -> Result<(), LedgerError> {
+        let alice = testing::address(&ALICE);
+        let nonce = ledger.accounts.get_nonce(&alice).unwrap_or(0);
+        let signature = testing::sign(key, &Recovery::finalize_data(&alice, &testing::public_key(&NEW_KEY), nonce));
+        ledger.finalize_recovery(&alice, &signature)
+    }
+
+    fn security_events(ledger: &mut Ledger) -> Arc<Mutex<Vec<LedgerEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        ledger.subscribe(move |event| {
+            if matches!(event, LedgerEvent::AccountLocked { .. } | LedgerEvent::AccountUnlocked { .. } | LedgerEvent::KeyRotated { .. }) {
+                sink.lock().unwrap().push(event.clone());
+            }
+        });
+        events
+    }
+
+    #[test]
+    fn recovery_needs_every_approval_and_the_new_key() {
+        let mut ledger = ledger_with_guardians(0);
+        let events = security_events(&mut ledger);
+        let (alice, bob) = (testing::address(&ALICE), testing::address(&BOB));
+        assert!(start(&mut ledger).is_ok());
+        assert!(matches!(start(&mut ledger), Err(LedgerError::Recovery(RecoveryError::RecoveryInProgress))));
+
+        // Frozen: alice cannot send, but still receives.
+        let outgoing = testing::transfer(&ledger, &ALICE, &bob, Amount::from_units(100));
+        assert!(matches!(ledger.apply(outgoing), Err(LedgerError::Validation(ValidationError::SenderAccountLocked))));
+        let incoming = testing::transfer(&ledger, &BOB, &alice, Amount::from_units(100));
+        assert!(ledger.apply(incoming).is_ok());
+
+        assert!(matches!(finalize(&mut ledger, &NEW_KEY), Err(LedgerError::Recovery(RecoveryError::ThresholdNotMet))));
+        assert!(matches!(approve(&mut ledger, &BOB), Err(LedgerError::Recovery(RecoveryError::AlreadyApproved))));
+        assert!(matches!(approve(&mut ledger, &ALICE), Err(LedgerError::Recovery(RecoveryError::NotAGuardian))));
+        assert!(approve(&mut ledger, &CAROL).is_ok());
+
+        // Only the holder of the new key can finish it.
+        assert!(matches!(finalize(&mut ledger, &ALICE), Err(LedgerError::Recovery(RecoveryError::Unauthorized))));
+        assert!(finalize(&mut ledger, &NEW_KEY).is_ok());
+        let details = ledger.accounts.get_account_details(&alice).ok().unwrap();
+        assert_eq!(details.public_key, testing::public_key(&NEW_KEY));
+        assert!(!details.is_frozen());
+        assert!(ledger.accounts.is_key_revoked(&testing::public_key(&ALICE)));
+        assert!(ledger.recovery().get_pending_recovery(&alice).is_none());
+        assert_eq!(*events.lock().unwrap(), vec![
+            LedgerEvent::AccountLocked { account: alice.clone() },
+            LedgerEvent::KeyRotated { account: alice.clone() },
+            LedgerEvent::AccountUnlocked { account: alice.clone() },
+        ]);
+
+        // Every step is in alice's history, which she now reads with the new key.
+        let filter = HistoryFilter {
+            account: Some(alice.clone()),
+            kinds: Some(vec![HistoryEntryKind::RecoveryStarted, HistoryEntryKind::RecoveryApproved, HistoryEntryKind::KeyRecovered]),
+            ..HistoryFilter::default()
+        };
+        let nonce = ledger.accounts.get_nonce(&alice).unwrap_or(0);
+        let signature = testing::sign(&NEW_KEY, &HistoryIndex::query_data(&alice, nonce));
+        let page = ledger.query_history(&alice, &filter, None, 10, &[signature]).ok().unwrap();
+        let kinds: Vec<HistoryEntryKind> = page.entries.iter().map(|entry| entry.kind).collect();
+        assert_eq!(kinds, vec![HistoryEntryKind::RecoveryStarted, HistoryEntryKind::RecoveryApproved, HistoryEntryKind::KeyRecovered]);
+    }
+
+    #[test]
+    fn the_owner_can_cancel_during_the_delay() {
+        let mut ledger = ledger_with_guardians(3600);
+        let events = security_events(&mut ledger);
+        let alice = testing::address(&ALICE);
+        assert!(start(&mut ledger).is_ok());
+        assert!(approve(&mut ledger, &CAROL).is_ok());
+        assert!(matches!(finalize(&mut ledger, &NEW_KEY), Err(LedgerError::Recovery(RecoveryError::DelayNotElapsed))));
+
+        // Guardians cannot cancel: only the current key can.
+        let nonce = ledger.accounts.get_nonce(&alice).unwrap_or(0);
+        let data = Recovery::cancel_data(&alice, nonce);
+        assert!(matches!(ledger.cancel_recovery(&alice, &[testing::sign(&BOB, &data)]), Err(LedgerError::Recovery(RecoveryError::Unauthorized))));
+        assert!(ledger.cancel_recovery(&alice, &[testing::sign(&ALICE, &data)]).is_ok());
+
+        let details = ledger.accounts.get_account_details(&alice).ok().unwrap();
+        assert_eq!(details.public_key, testing::public_key(&ALICE));
+        assert!(!details.is_frozen());
+        assert!(matches!(finalize(&mut ledger, &NEW_KEY), Err(LedgerError::Recovery(RecoveryError::NoRecoveryInProgress))));
+        assert_eq!(*events.lock().unwrap(), vec![
+            LedgerEvent::AccountLocked { account: alice.clone() },
+            LedgerEvent::AccountUnlocked { account: alice.clone() },
+        ]);
+    }
+
+    #[test]
+    fn a_pending_recovery_survives_a_restart() {
+        let mut ledger = ledger_with_guardians(3600);
+        let alice = testing::address(&ALICE);
+        assert!(start(&mut ledger).is_ok());
+
+        let mut store = MemoryStore::new();
+        assert!(ledger.commit_block(&mut store, &testing::address(&BOB)).is_ok());
+        let (restored, accounts) = match (Recovery::restore(&store), Accounts::restore(&store, 10)) {
+            (Ok(restored), Ok(accounts)) => (restored, accounts),
+            _ => panic!("restore"),
+        };
+        assert_eq!(&restored, ledger.recovery());
+        assert_eq!(restored.get_pending_recovery(&alice).map(|pending| pending.approvals.len()), Some(1));
+        assert!(accounts.get_account_details(&alice).ok().is_some_and(|details| details.is_frozen()));
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::{BTreeSet, HashMap};
+use serde::{Serialize, Deserialize};
+use crate::encoding::{Action, AuthorizationPayload};
+use crate::storage::{StateStore, StorageError};
+
+// Prefix of role grants in the store
+const ROLE_KEY_PREFIX: &str = "role/";
+
+pub enum RoleError {
+    Unauthorized,
+    RoleNotHeld,
+    AlreadyGranted,
+    LastOwner, // The last owner cannot be removed, or nobody could grant roles again.
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum Role {
+    Owner,
+    Issuer,
+    ComplianceOfficer,
+    Auditor,
+    Operator,
+}
+
+// Administrative actions, each allowed to one or more roles.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Permission {
+    ManageRoles,
+    TransferOwnership,
+    DeleteAccount,
+    IssueCoins,
+    LockAccount,
+    AdjustBalance,
+    ViewHistory,
+    ConfigureFees,
+    RegisterAsset,
+}
+
+impl Role {
+    // Byte identifying the role in signed payloads.
+    pub fn tag(self) -> u8 {
+        match self {
+            Role::Owner => 0x01,
+            Role::Issuer => 0x02,
+            Role::ComplianceOfficer => 0x03,
+            Role::Auditor => 0x04,
+            Role::Operator => 0x05,
+        }
+    }
+
+    pub fn permissions(&self) -> &'static [Permission] {
+        match self {
+            Role::Owner => &[Permission::ManageRoles, Permission::TransferOwnership, Permission::DeleteAccount],
+            Role::Issuer => &[Permission::IssueCoins],
+            Role::ComplianceOfficer => &[Permission::LockAccount, Permission::AdjustBalance, Permission::ViewHistory],
+            Role::Auditor => &[Permission::ViewHistory],
+            Role::Operator => &[Permission::ConfigureFees, Permission::RegisterAsset],
+        }
+    }
+}
+
+/// Who holds which administrative role. Every administrative operation checks a
+/// `Permission` here instead of comparing the caller to a fixed address. This only says
+/// what an account may do: the `Ledger` also makes the caller prove it is that account,
+/// with its signatures over the operation.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Roles {
+    grants: HashMap<String, BTreeSet<Role>>,
+}
+
+impl Roles {
+    // The first owner is set when the roles are created; the owner grants everything else.
+    pub fn new(owner: &str) -> Self {
+        let mut grants = HashMap::new();
+        grants.insert(owner.to_string(), BTreeSet::from([Role::Owner]));
+        Roles { grants }
+    }
+
+    pub fn has_role(&self, account: &String, role: Role) -> bool {
+        self.grants.get(account).is_some_and(|roles| roles.contains(&role))
+    }
+
+    pub fn roles_of(&self, account: &String) -> Vec<Role> {
+        self.grants.get(account).map(|roles| roles.iter().copied().collect()).unwrap_or_default()
+    }
+
+    pub fn members(&self, role: Role) -> Vec<&String> {
+        self.grants.iter().filter(|(_, roles)| roles.contains(&role)).map(|(account, _)| account).collect()
+    }
+
+    pub fn has_permission(&self, account: &String, permission: Permission) -> bool {
+        self.grants
+            .get(account)
+            .is_some_and(|roles| roles.iter().any(|role| role.permissions().contains(&permission)))
+    }
+
+    pub fn check_permission(&self, account: &String, permission: Permission) -> Result<(), RoleError> {
+        if !self.has_permission(account, permission) {
+            return Err(RoleError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    // Data the caller signs to grant `role` to `account`.
+    pub fn grant_data(caller: &str, account: &str, role: Role, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::GrantRole).string(caller).string(account).u64(role.tag() as u64).u64(nonce).signing_data()
+    }
+
+    // Data the caller signs to revoke `role` from `account`.
+    pub fn revoke_data(caller: &str, account: &str, role: Role, nonce: u64) -> String {
+        AuthorizationPayload::new(Action::RevokeRole).string(caller).string(account).u64(role.tag() as u64).u64(nonce).signing_data()
+    }
+
+    pub fn grant_role(&mut self, caller: &String, account: &str, role: Role) -> Result<(), RoleError> {
+        self.check_permission(caller, Permission::ManageRoles)?;
+        if !self.grants.entry(account.to_string()).or_default().insert(role) {
+            return Err(RoleError::AlreadyGranted);
+        }
+        Ok(())
+    }
+
+    pub fn revoke_role(&mut self, caller: &String, account: &String, role: Role) -> Result<(), RoleError> {
+        self.check_permission(caller, Permission::ManageRoles)?;
+        if !self.has_role(account, role) {
+            return Err(RoleError::RoleNotHeld);
+        }
+        if role == Role::Owner && self.members(Role::Owner).len() == 1 {
+            return Err(RoleError::LastOwner);
+        }
+        if let Some(roles) = self.grants.get_mut(account) {
+            roles.remove(&role);
+            if roles.is_empty() {
+                self.grants.remove(account);
+            }
+        }
+        Ok(())
+    }
+
+    // Writes every grant to the store; accounts left without roles are deleted.
+    pub fn persist(&self, store: &mut dyn StateStore) -> Result<(), StorageError> {
+        for key in store.keys_with_prefix(ROLE_KEY_PREFIX) {
+            if !self.grants.contains_key(&key[ROLE_KEY_PREFIX.len()..]) {
+                store.delete(&key);
+            }
+        }
+        for (account, roles) in &self.grants {
+            let value = serde_json::to_vec(roles).map_err(|_| StorageError::Serialization)?;
+            store.put(&format!("{}{}", ROLE_KEY_PREFIX, account), value);
+        }
+        Ok(())
+    }
+
+    // Restores the grants from the store, or None if none were ever committed.
+    pub fn restore(store: &dyn StateStore) -> Result<Option<Self>, StorageError> {
+        let mut grants = HashMap::new();
+        for key in store.keys_with_prefix(ROLE_KEY_PREFIX) {
+            let value = store.get(&key).ok_or(StorageError::Corrupted)?;
+            let roles: BTreeSet<Role> = serde_json::from_slice(&value).map_err(|_| StorageError::Serialization)?;
+            grants.insert(key[ROLE_KEY_PREFIX.len()..].to_string(), roles);
+        }
+        if grants.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Roles { grants }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::Accounts;
+    use crate::ledger::LedgerError;
+    use crate::testing::{self, ALICE, BOB, OWNER};
+    use crate::validate_transaction::ValidationError;
+
+    #[test]
+    fn granting_a_role_needs_the_callers_signature() {
+        let mut ledger = testing::ledger();
+        let (owner, alice) = (testing::address(&OWNER), testing::address(&ALICE));
+        let data = Roles::grant_data(&owner, &alice, Role::Auditor, 0);
+
+        // Naming the owner is not enough: the owner's keys must sign.
+        let forged = [testing::sign(&ALICE, &data)];
+        assert!(matches!(ledger.grant_role(&owner, &alice, Role::Auditor, 0, &forged), Err(LedgerError::Validation(ValidationError::InvalidSignature))));
+        assert!(matches!(ledger.grant_role(&owner, &alice, Role::Auditor, 0, &[]), Err(LedgerError::Validation(ValidationError::InvalidSignature))));
+        // Alice's own signature does not give her the owner's permissions.
+        let by_alice = [testing::sign(&ALICE, &Roles::grant_data(&alice, &alice, Role::Auditor, 0))];
+        assert!(matches!(ledger.grant_role(&alice, &alice, Role::Auditor, 0, &by_alice), Err(LedgerError::Role(RoleError::Unauthorized))));
+        assert!(!ledger.roles().has_role(&alice, Role::Auditor));
+
+        let signed = [testing::sign(&OWNER, &data)];
+        assert!(ledger.grant_role(&owner, &alice, Role::Auditor, 0, &signed).is_ok());
+        assert!(ledger.roles().has_role(&alice, Role::Auditor));
+        assert_eq!(ledger.accounts.get_nonce(&owner).ok(), Some(1));
+
+        // The signature named nonce 0 and cannot be replayed to undo a later revocation.
+        let revoke = [testing::sign(&OWNER, &Roles::revoke_data(&owner, &alice, Role::Auditor, 1))];
+        assert!(ledger.revoke_role(&owner, &alice, Role::Auditor, 1, &revoke).is_ok());
+        assert!(matches!(ledger.grant_role(&owner, &alice, Role::Auditor, 0, &signed), Err(LedgerError::Validation(ValidationError::StaleNonce))));
+        assert!(!ledger.roles().has_role(&alice, Role::Auditor));
+    }
+
+    #[test]
+    fn administrative_actions_check_the_role_of_the_signer() {
+        let mut ledger = testing::ledger();
+        let (owner, alice, bob) = (testing::address(&OWNER), testing::address(&ALICE), testing::address(&BOB));
+
+        // A correctly signed request from an account without the role is refused.
+        let data = Accounts::lock_data(&alice, &bob, true, 0);
+        assert!(matches!(ledger.lock_account(&alice, &bob, 0, &[testing::sign(&ALICE, &data)]), Err(LedgerError::Role(RoleError::Unauthorized))));
+        assert_eq!(ledger.accounts.get_nonce(&alice).ok(), Some(0));
+
+        testing::grant_role(&mut ledger, &alice, Role::ComplianceOfficer);
+        assert!(ledger.lock_account(&alice, &bob, 0, &[testing::sign(&ALICE, &data)]).is_ok());
+        assert!(ledger.accounts.is_account_locked(&bob).is_err());
+
+        // The only owner cannot remove itself.
+        let nonce = ledger.accounts.get_nonce(&owner).unwrap_or(0);
+        let revoke = [testing::sign(&OWNER, &Roles::revoke_data(&owner, &owner, Role::Owner, nonce))];
+        assert!(matches!(ledger.revoke_role(&owner, &owner, Role::Owner, nonce, &revoke), Err(LedgerError::Role(RoleError::LastOwner))));
+    }
+}
@@ -1,92 +1,121 @@
-use std::collections::{HashSet, HashMap};
+use std::collections::HashMap;
 use sha2::{Sha256, Digest};
-use std::time::Instant; // Para medir el rendimiento
+use crate::accounts::{AccountSignature, PublicKey};
+use crate::metrics::metrics;
+use crate::signature_scheme::{Secp256k1Ecdsa, SchemeId};
 
 /// Security module for handling transaction processing and cryptographic operations.
 pub enum SecurityError {
-    TransactionAlreadyProcessed,
+    StaleNonce,
+    NonceGap,
     InvalidSignature,
     KeyNotFound,
     FailedToSign,
     InvalidKey,
 }
 
+// How a secp256k1 signature is serialised before being hex-encoded.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SignatureEncoding {
+    Compact, // 64 bytes: r then s.
+    Der,
+}
+
 pub struct Security {
-    processed_transactions: HashSet<String>,
-    public_keys: HashMap<String, Vec<u8>>,
+    public_keys: HashMap<String, PublicKey>,
     private_keys: HashMap<String, Vec<u8>>,
 }
 
+impl Default for Security {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Security {
     pub fn new() -> Self {
         Security {
-            processed_transactions: HashSet::new(),
             public_keys: HashMap::new(),
             private_keys: HashMap::new(),
         }
     }
 
-    pub fn add_processed_transaction(&mut self, transaction_hash: String) -> Result<(), SecurityError> {
-        if !self.processed_transactions.insert(transaction_hash) {
-            return Err(SecurityError::TransactionAlreadyProcessed);
+    /// Checks a transaction nonce against the next nonce expected for the account.
+    /// Replayed transactions carry a nonce that was already used and are rejected as stale.
+    pub fn check_nonce(expected: u64, provided: u64) -> Result<(), SecurityError> {
+        if provided < expected {
+            return Err(SecurityError::StaleNonce);
+        }
+        if provided > expected {
+            return Err(SecurityError::NonceGap);
         }
         Ok(())
     }
 
-    pub fn has_transaction_been_processed(&self, transaction_hash: &String) -> bool {
-        self.processed_transactions.contains(transaction_hash)
+    /// Signs `data` with the account's private key, in the scheme of its public key.
+    /// secp256k1 signatures are low-S and hex-encoded in the compact form.
+    pub fn sign(&self, account: &String, data: &String) -> Result<String, SecurityError> {
+        self.sign_with_encoding(account, data, SignatureEncoding::Compact)
     }
 
-    pub fn sign(&self, account: &String, data: &String) -> Result<String, SecurityError> {
+    // `encoding` only matters for secp256k1; other schemes have a single encoding.
+    pub fn sign_with_encoding(&self, account: &String, data: &String, encoding: SignatureEncoding) -> Result<String, SecurityError> {
+        let public_key = self.public_keys.get(account).ok_or(SecurityError::KeyNotFound)?;
         let private_key = self.private_keys.get(account).ok_or(SecurityError::KeyNotFound)?;
-        let hashed_data = self.hash(data);
-        // En un escenario real, usaríamos el `private_key` para firmar los datos, pero para esta simulación, simplemente devolvemos el hash.
-        Ok(hashed_data)
+        let signature = match (public_key.scheme, encoding) {
+            (SchemeId::Secp256k1, SignatureEncoding::Der) => Secp256k1Ecdsa.sign_der(private_key, data.as_bytes()),
+            (scheme, _) => scheme.scheme().sign(private_key, data.as_bytes()),
+        };
+        signature.map(hex::encode).ok_or(SecurityError::FailedToSign)
     }
 
     pub fn validate_signature(&self, account: &String, data: &String, signature: &String) -> Result<bool, SecurityError> {
         let public_key = self.public_keys.get(account).ok_or(SecurityError::KeyNotFound)?;
-        let hashed_data = self.hash(data);
-        // En un escenario real, usaríamos el `public_key` para verificar la firma.
-        if &hashed_data == signature {
+        if Self::verify_key_signature(public_key, data, signature) {
             Ok(true)
         } else {
             Err(SecurityError::InvalidSignature)
         }
     }
 
-    fn validate_keys(public_key: &Vec<u8>, private_key: &Vec<u8>) -> Result<(), SecurityError> {
-        if public_key.is_empty() || private_key.is_empty() {
+    /// Verifies a hex-encoded signature made by `public_key` over `data`, with the scheme
+    /// the key is tagged with. This is the only signature check in the ledger: account key
+    /// sets, `ValidateTransaction` and `Consensus` all go through it.
+    pub fn verify_key_signature(public_key: &PublicKey, data: &String, signature: &String) -> bool {
+        let _timer = metrics().signature_verification_seconds.start_timer();
+        let Ok(signature) = hex::decode(signature) else { return false };
+        public_key.scheme.scheme().verify(&public_key.value, data.as_bytes(), &signature)
+    }
+
+    /// Verifies an account signature. Its scheme tag must match the key's.
+    pub fn verify_account_signature(signature: &AccountSignature, data: &String) -> bool {
+        signature.scheme == signature.public_key.scheme && Self::verify_key_signature(&signature.public_key, data, &signature.signature)
+    }
+
+    // The private key must sign what `public_key` verifies.
+    fn validate_keys(public_key: &PublicKey, private_key: &[u8]) -> Result<(), SecurityError> {
+        let scheme = public_key.scheme.scheme();
+        let probe = b"luks-key-check";
+        let signature = scheme.sign(private_key, probe).ok_or(SecurityError::InvalidKey)?;
+        if !scheme.verify(&public_key.value, probe, &signature) {
             return Err(SecurityError::InvalidKey);
         }
         Ok(())
     }
 
-    pub fn add_keys(&mut self, account: String, public_key: Vec<u8>, private_key: Vec<u8>) -> Result<(), SecurityError> {
+    pub fn add_keys(&mut self, account: String, public_key: PublicKey, private_key: Vec<u8>) -> Result<(), SecurityError> {
         Self::validate_keys(&public_key, &private_key)?;
         self.public_keys.insert(account.clone(), public_key);
         self.private_keys.insert(account, private_key);
         Ok(())
     }
 
-    fn hash(&self, data: &String) -> String {
+    pub fn hash(&self, data: &String) -> String {
         let mut hasher = Sha256::new();
         hasher.update(data);
         format!("{:x}", hasher.finalize())
     }
 
-    pub fn measure_performance() {
-        let start = Instant::now();
-        // Simulamos alguna operación costosa, como hash.
-        let mut hasher = Sha256::new();
-        for _ in 0..1000 {
-            hasher.update("performance_test");
-        }
-        let _ = hasher.finalize();
-        let duration = start.elapsed();
-        println!("Tiempo tomado para 1000 hashes: {:?}", duration);
-    }
-
     pub fn verify_hash(data: &str, expected_hash: &str) -> bool {
         let mut hasher = Sha256::new();
         hasher.update(data);
@@ -96,7 +125,8 @@ impl Security {
     /// Handles errors and prints custom messages based on error type.
     pub fn handle_errors(error: SecurityError) {
         match error {
-            SecurityError::TransactionAlreadyProcessed => println!("Error: La transacción ya ha sido procesada."),
+            SecurityError::StaleNonce => println!("Error: Nonce ya utilizado."),
+            SecurityError::NonceGap => println!("Error: Nonce fuera de secuencia."),
             SecurityError::InvalidSignature => println!("Error: Firma inválida."),
             SecurityError::KeyNotFound => println!("Error: Llave no encontrada."),
             SecurityError::FailedToSign => println!("Error: Fallo al firmar."),
@@ -104,12 +134,10 @@ impl Security {
         }
     }
 
-    /// Validates a transaction by checking its signature and if it's already processed.
-    pub fn validate_transaction(&self, account: &String, data: &String, signature: &String) -> Result<bool, SecurityError> {
-        // Check if the transaction has been processed
-        if self.has_transaction_been_processed(data) {
-            return Err(SecurityError::TransactionAlreadyProcessed);
-        }
+    /// Validates a transaction by checking its nonce and signature.
+    pub fn validate_transaction(&self, account: &String, data: &String, signature: &String, expected_nonce: u64, nonce: u64) -> Result<bool, SecurityError> {
+        // Reject replayed or out-of-order transactions
+        Self::check_nonce(expected_nonce, nonce)?;
 
         // Validate signature
         match self.validate_signature(account, data, signature) {
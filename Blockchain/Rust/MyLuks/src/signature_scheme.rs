@@ -0,0 +1,228 @@
+use std::sync::OnceLock;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use secp256k1::{ecdsa, All, Message, Secp256k1, SecretKey};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+
+/// Identifies the signature scheme of a key or signature.
+///
+/// Keys stored before schemes were tagged are secp256k1, hence the default.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum SchemeId {
+    #[default]
+    Secp256k1,
+    Ed25519,
+}
+
+impl SchemeId {
+    // Byte identifying the scheme in binary encodings.
+    pub fn tag(self) -> u8 {
+        match self {
+            SchemeId::Secp256k1 => 0x01,
+            SchemeId::Ed25519 => 0x02,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0x01 => Some(SchemeId::Secp256k1),
+            0x02 => Some(SchemeId::Ed25519),
+            _ => None,
+        }
+    }
+
+    // Prefix of the textual form of a key, e.g. `ed25519:<hex>`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SchemeId::Secp256k1 => "secp256k1",
+            SchemeId::Ed25519 => "ed25519",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "secp256k1" => Some(SchemeId::Secp256k1),
+            "ed25519" => Some(SchemeId::Ed25519),
+            _ => None,
+        }
+    }
+
+    /// The implementation that signs and verifies for this scheme.
+    pub fn scheme(self) -> &'static dyn SignatureScheme {
+        match self {
+            SchemeId::Secp256k1 => &Secp256k1Ecdsa,
+            SchemeId::Ed25519 => &Ed25519,
+        }
+    }
+}
+
+/// A signature algorithm. Keys and signatures are raw bytes in the scheme's own format;
+/// `message` is the data exactly as signed, any hashing is up to the scheme.
+pub trait SignatureScheme: Sync {
+    fn id(&self) -> SchemeId;
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> bool;
+    fn sign(&self, private_key: &[u8], message: &[u8]) -> Option<Vec<u8>>;
+    fn public_key(&self, private_key: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// ECDSA over secp256k1 of the SHA-256 of the message. Public keys are SEC1, compressed or
+/// not; signatures are compact (64 bytes) or strict DER and must be low-S, since each
+/// high-S signature has an equally valid low-S twin that would change a transaction's id.
+pub struct Secp256k1Ecdsa;
+
+impl Secp256k1Ecdsa {
+    fn digest(message: &[u8]) -> Message {
+        let digest: [u8; 32] = Sha256::digest(message).into();
+        Message::from_digest(digest)
+    }
+
+    // 64 bytes are the compact form; anything else must be DER.
+    fn parse_signature(signature: &[u8]) -> Option<ecdsa::Signature> {
+        if signature.len() == 64 {
+            ecdsa::Signature::from_compact(signature).ok()
+        } else {
+            ecdsa::Signature::from_der(signature).ok()
+        }
+    }
+
+    // Same as `sign`, in DER rather than compact form.
+    pub fn sign_der(&self, private_key: &[u8], message: &[u8]) -> Option<Vec<u8>> {
+        let secret_key = SecretKey::from_slice(private_key).ok()?;
+        Some(secp256k1_context().sign_ecdsa(&Self::digest(message), &secret_key).serialize_der().to_vec())
+    }
+}
+
+impl SignatureScheme for Secp256k1Ecdsa {
+    fn id(&self) -> SchemeId {
+        SchemeId::Secp256k1
+    }
+
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        let Ok(public_key) = secp256k1::PublicKey::from_slice(public_key) else { return false };
+        let Some(signature) = Self::parse_signature(signature) else { return false };
+        let mut normalized = signature;
+        normalized.normalize_s();
+        if normalized != signature {
+            return false;
+        }
+        secp256k1_context().verify_ecdsa(&Self::digest(message), &signature, &public_key).is_ok()
+    }
+
+    // libsecp256k1 always produces low-S signatures.
+    fn sign(&self, private_key: &[u8], message: &[u8]) -> Option<Vec<u8>> {
+        let secret_key = SecretKey::from_slice(private_key).ok()?;
+        Some(secp256k1_context().sign_ecdsa(&Self::digest(message), &secret_key).serialize_compact().to_vec())
+    }
+
+    // Compressed SEC1.
+    fn public_key(&self, private_key: &[u8]) -> Option<Vec<u8>> {
+        let secret_key = SecretKey::from_slice(private_key).ok()?;
+        Some(secp256k1::PublicKey::from_secret_key(secp256k1_context(), &secret_key).serialize().to_vec())
+    }
+}
+
+/// Ed25519 (RFC 8032) over the message itself. Keys are 32 bytes, signatures 64; the strict
+/// verification rejects non-canonical signatures and weak keys, so ids cannot be malleated.
+pub struct Ed25519;
+
+impl SignatureScheme for Ed25519 {
+    fn id(&self) -> SchemeId {
+        SchemeId::Ed25519
+    }
+
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        let Ok(public_key) = <[u8; 32]>::try_from(public_key) else { return false };
+        let Ok(verifying_key) = VerifyingKey::from_bytes(&public_key) else { return false };
+        let Ok(signature) = ed25519_dalek::Signature::from_slice(signature) else { return false };
+        verifying_key.verify_strict(message, &signature).is_ok()
+    }
+
+    fn sign(&self, private_key: &[u8], message: &[u8]) -> Option<Vec<u8>> {
+        let secret = <[u8; 32]>::try_from(private_key).ok()?;
+        Some(SigningKey::from_bytes(&secret).sign(message).to_bytes().to_vec())
+    }
+
+    fn public_key(&self, private_key: &[u8]) -> Option<Vec<u8>> {
+        let secret = <[u8; 32]>::try_from(private_key).ok()?;
+        Some(SigningKey::from_bytes(&secret).verifying_key().to_bytes().to_vec())
+    }
+}
+
+// Building a context is expensive, so one is shared by the whole process.
+fn secp256k1_context() -> &'static Secp256k1<All> {
+    static CONTEXT: OnceLock<Secp256k1<All>> = OnceLock::new();
+    CONTEXT.get_or_init(Secp256k1::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_KEY: [u8; 32] = [0x42; 32];
+
+    // Order of the secp256k1 group.
+    const CURVE_ORDER: [u8; 32] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+        0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+    ];
+
+    // Replaces S by n - S, the high-S twin of a low-S signature.
+    fn high_s(compact: &[u8]) -> Vec<u8> {
+        let mut out = compact.to_vec();
+        let mut borrow = 0i16;
+        for i in (0..32).rev() {
+            let diff = CURVE_ORDER[i] as i16 - compact[32 + i] as i16 - borrow;
+            borrow = if diff < 0 { 1 } else { 0 };
+            out[32 + i] = diff.rem_euclid(256) as u8;
+        }
+        out
+    }
+
+    #[test]
+    fn secp256k1_accepts_low_s_compact_and_der() {
+        let scheme = Secp256k1Ecdsa;
+        let public_key = scheme.public_key(&PRIVATE_KEY).unwrap();
+        let compact = scheme.sign(&PRIVATE_KEY, b"message").unwrap();
+        let der = scheme.sign_der(&PRIVATE_KEY, b"message").unwrap();
+        assert!(scheme.verify(&public_key, b"message", &compact));
+        assert!(scheme.verify(&public_key, b"message", &der));
+        assert!(!scheme.verify(&public_key, b"other message", &compact));
+    }
+
+    #[test]
+    fn secp256k1_rejects_high_s() {
+        let scheme = Secp256k1Ecdsa;
+        let public_key = scheme.public_key(&PRIVATE_KEY).unwrap();
+        let compact = scheme.sign(&PRIVATE_KEY, b"message").unwrap();
+        let twin = high_s(&compact);
+        assert_ne!(twin, compact);
+
+        // The twin is a valid ECDSA signature, only its S is high.
+        let mut parsed = ecdsa::Signature::from_compact(&twin).unwrap();
+        parsed.normalize_s();
+        assert_eq!(parsed.serialize_compact().to_vec(), compact);
+
+        assert!(!scheme.verify(&public_key, b"message", &twin));
+        let twin_der = ecdsa::Signature::from_compact(&twin).unwrap().serialize_der().to_vec();
+        assert!(!scheme.verify(&public_key, b"message", &twin_der));
+    }
+
+    #[test]
+    fn ed25519_round_trip() {
+        let scheme = Ed25519;
+        let public_key = scheme.public_key(&PRIVATE_KEY).unwrap();
+        let signature = scheme.sign(&PRIVATE_KEY, b"message").unwrap();
+        assert!(scheme.verify(&public_key, b"message", &signature));
+        assert!(!scheme.verify(&public_key, b"other message", &signature));
+    }
+
+    #[test]
+    fn scheme_tags_round_trip() {
+        for id in [SchemeId::Secp256k1, SchemeId::Ed25519] {
+            assert_eq!(SchemeId::from_tag(id.tag()), Some(id));
+            assert_eq!(SchemeId::from_name(id.name()), Some(id));
+            assert_eq!(id.scheme().id(), id);
+        }
+        assert_eq!(SchemeId::from_tag(0xff), None);
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use sha2::{Sha256, Digest};
+
+/// Errors raised while reading or writing persisted state.
+pub enum StorageError {
+    Io(io::Error),
+    Corrupted,
+    Serialization,
+}
+
+impl From<io::Error> for StorageError {
+    fn from(error: io::Error) -> Self {
+        StorageError::Io(error)
+    }
+}
+
+/// Key-value store the ledger modules persist through.
+///
+/// Writes are staged and only become visible (and durable) once `commit` is called
+/// with the height of the block they belong to.
+pub trait StateStore {
+    fn get(&self, key: &str) -> Option<Vec<u8>>;
+    fn put(&mut self, key: &str, value: Vec<u8>);
+    fn delete(&mut self, key: &str);
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String>;
+    fn commit(&mut self, height: u64) -> Result<(), StorageError>;
+    fn committed_height(&self) -> Option<u64>;
+    /// Drops the writes staged since the last commit, e.g. after `commit` failed.
+    fn discard(&mut self);
+}
+
+// Committed key/value state of a store.
+type State = BTreeMap<String, Vec<u8>>;
+
+enum StagedWrite {
+    Put(String, Vec<u8>),
+    Delete(String),
+}
+
+fn apply_writes(state: &mut BTreeMap<String, Vec<u8>>, writes: Vec<StagedWrite>) {
+    for write in writes {
+        match write {
+            StagedWrite::Put(key, value) => { state.insert(key, value); }
+            StagedWrite::Delete(key) => { state.remove(&key); }
+        }
+    }
+}
+
+fn prefixed_keys(state: &BTreeMap<String, Vec<u8>>, prefix: &str) -> Vec<String> {
+    state
+        .range(prefix.to_string()..)
+        .take_while(|(key, _)| key.starts_with(prefix))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+/// Volatile store, useful for tests and nodes that resync from peers.
+pub struct MemoryStore {
+    state: BTreeMap<String, Vec<u8>>,
+    staged: Vec<StagedWrite>,
+    height: Option<u64>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore {
+            state: BTreeMap::new(),
+            staged: Vec::new(),
+            height: None,
+        }
+    }
+}
+
+impl StateStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.state.get(key).cloned()
+    }
+
+    fn put(&mut self, key: &str, value: Vec<u8>) {
+        self.staged.push(StagedWrite::Put(key.to_string(), value));
+    }
+
+    fn delete(&mut self, key: &str) {
+        self.staged.push(StagedWrite::Delete(key.to_string()));
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        prefixed_keys(&self.state, prefix)
+    }
+
+    fn commit(&mut self, height: u64) -> Result<(), StorageError> {
+        let writes = std::mem::take(&mut self.staged);
+        apply_writes(&mut self.state, writes);
+        self.height = Some(height);
+        Ok(())
+    }
+
+    fn committed_height(&self) -> Option<u64> {
+        self.height
+    }
+
+    fn discard(&mut self) {
+        self.staged.clear();
+    }
+}
+
+// Tipos de registro del write-ahead log
+const RECORD_PUT: u8 = 1;
+const RECORD_DELETE: u8 = 2;
+const RECORD_COMMIT: u8 = 3;
+
+const WAL_FILE: &str = "state.wal";
+const SNAPSHOT_FILE: &str = "state.snapshot";
+const SNAPSHOT_TMP_FILE: &str = "state.snapshot.tmp";
+
+/// Durable store backed by an append-only write-ahead log plus periodic snapshots.
+///
+/// Every record is framed as `[len: u32][checksum: 4 bytes][payload]`. A batch of writes
+/// is only applied on recovery once its commit record is read back intact, so a process
+/// killed mid-write comes back at the last committed block.
+pub struct FileStore {
+    dir: PathBuf,
+    wal: File,
+    state: BTreeMap<String, Vec<u8>>,
+    staged: Vec<StagedWrite>,
+    height: Option<u64>,
+    wal_len: u64, // Bytes of the log up to its last commit record.
+    commits_since_compaction: u64,
+    compaction_interval: u64,
+}
+
+impl FileStore {
+    /// Opens (or creates) a store in `dir`, recovering the last committed state.
+    pub fn open(dir: &Path, compaction_interval: u64) -> Result<Self, StorageError> {
+        fs::create_dir_all(dir)?;
+
+        let (mut state, mut height) = match fs::read(dir.join(SNAPSHOT_FILE)) {
+            Ok(bytes) => Self::decode_snapshot(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (BTreeMap::new(), None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let wal_path = dir.join(WAL_FILE);
+        let mut wal_bytes = Vec::new();
+        if let Ok(mut file) = File::open(&wal_path) {
+            file.read_to_end(&mut wal_bytes)?;
+        }
+
+        // Replay committed batches; anything after the last intact commit is discarded.
+        let mut offset = 0;
+        let mut committed_offset = 0;
+        let mut batch = Vec::new();
+        let mut commits = 0;
+        while let Some((payload, next)) = read_record(&wal_bytes, offset) {
+            offset = next;
+            match payload.first() {
+                Some(&RECORD_PUT) => {
+                    let (key, rest) = read_bytes(&payload[1..]).ok_or(StorageError::Corrupted)?;
+                    let (value, _) = read_bytes(rest).ok_or(StorageError::Corrupted)?;
+                    let key = String::from_utf8(key.to_vec()).map_err(|_| StorageError::Corrupted)?;
+                    batch.push(StagedWrite::Put(key, value.to_vec()));
+                }
+                Some(&RECORD_DELETE) => {
+                    let (key, _) = read_bytes(&payload[1..]).ok_or(StorageError::Corrupted)?;
+                    let key = String::from_utf8(key.to_vec()).map_err(|_| StorageError::Corrupted)?;
+                    batch.push(StagedWrite::Delete(key));
+                }
+                Some(&RECORD_COMMIT) => {
+                    let block_height = read_u64(&payload[1..]).ok_or(StorageError::Corrupted)?;
+                    let writes = std::mem::take(&mut batch);
+                    // Batches already folded into the snapshot are skipped.
+                    if height.is_none_or(|h| block_height > h) {
+                        apply_writes(&mut state, writes);
+                        height = Some(block_height);
+                    }
+                    committed_offset = offset;
+                    commits += 1;
+                }
+                _ => break,
+            }
+        }
+
+        // Drop a torn tail so new records are not appended after garbage.
+        let wal = OpenOptions::new().create(true).append(true).open(&wal_path)?;
+        wal.set_len(committed_offset as u64)?;
+        wal.sync_all()?;
+
+        Ok(FileStore {
+            dir: dir.to_path_buf(),
+            wal,
+            state,
+            staged: Vec::new(),
+            height,
+            wal_len: committed_offset as u64,
+            commits_since_compaction: commits,
+            compaction_interval: compaction_interval.max(1),
+        })
+    }
+
+    /// Writes the full state to a new snapshot and truncates the log.
+    pub fn compact(&mut self) -> Result<(), StorageError> {
+        let mut payload = Vec::new();
+        payload.push(self.height.is_some() as u8);
+        payload.extend_from_slice(&self.height.unwrap_or(0).to_be_bytes());
+        payload.extend_from_slice(&(self.state.len() as u64).to_be_bytes());
+        for (key, value) in &self.state {
+            write_bytes(&mut payload, key.as_bytes());
+            write_bytes(&mut payload, value);
+        }
+
+        let tmp_path = self.dir.join(SNAPSHOT_TMP_FILE);
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&frame_record(&payload))?;
+        tmp.sync_all()?;
+        // The rename is atomic, so a crash leaves either the old or the new snapshot.
+        fs::rename(&tmp_path, self.dir.join(SNAPSHOT_FILE))?;
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+
+        self.wal.set_len(0)?;
+        self.wal.sync_all()?;
+        self.wal_len = 0;
+        self.commits_since_compaction = 0;
+        Ok(())
+    }
+
+    fn decode_snapshot(bytes: &[u8]) -> Result<(State, Option<u64>), StorageError> {
+        let (payload, _) = read_record(bytes, 0).ok_or(StorageError::Corrupted)?;
+        let has_height = *payload.first().ok_or(StorageError::Corrupted)? == 1;
+        let height = read_u64(&payload[1..]).ok_or(StorageError::Corrupted)?;
+        let count = read_u64(&payload[9..]).ok_or(StorageError::Corrupted)?;
+        let mut rest = &payload[17..];
+        let mut state = BTreeMap::new();
+        for _ in 0..count {
+            let (key, after_key) = read_bytes(rest).ok_or(StorageError::Corrupted)?;
+            let (value, after_value) = read_bytes(after_key).ok_or(StorageError::Corrupted)?;
+            let key = String::from_utf8(key.to_vec()).map_err(|_| StorageError::Corrupted)?;
+            state.insert(key, value.to_vec());
+            rest = after_value;
+        }
+        Ok((state, if has_height { Some(height) } else { None }))
+    }
+}
+
+impl StateStore for FileStore {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.state.get(key).cloned()
+    }
+
+    fn put(&mut self, key: &str, value: Vec<u8>) {
+        self.staged.push(StagedWrite::Put(key.to_string(), value));
+    }
+
+    fn delete(&mut self, key: &str) {
+        self.staged.push(StagedWrite::Delete(key.to_string()));
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        prefixed_keys(&self.state, prefix)
+    }
+
+    fn commit(&mut self, height: u64) -> Result<(), StorageError> {
+        let mut buffer = Vec::new();
+        for write in &self.staged {
+            let mut payload = Vec::new();
+            match write {
+                StagedWrite::Put(key, value) => {
+                    payload.push(RECORD_PUT);
+                    write_bytes(&mut payload, key.as_bytes());
+                    write_bytes(&mut payload, value);
+                }
+                StagedWrite::Delete(key) => {
+                    payload.push(RECORD_DELETE);
+                    write_bytes(&mut payload, key.as_bytes());
+                }
+            }
+            buffer.extend_from_slice(&frame_record(&payload));
+        }
+        let mut payload = vec![RECORD_COMMIT];
+        payload.extend_from_slice(&height.to_be_bytes());
+        buffer.extend_from_slice(&frame_record(&payload));
+
+        if let Err(e) = self.wal.write_all(&buffer).and_then(|_| self.wal.sync_data()) {
+            // Cut a partly written batch so the next one is not appended after it.
+            let _ = self.wal.set_len(self.wal_len);
+            return Err(e.into());
+        }
+        self.wal_len += buffer.len() as u64;
+
+        // Only touch the in-memory view once the batch is durable.
+        let writes = std::mem::take(&mut self.staged);
+        apply_writes(&mut self.state, writes);
+        self.height = Some(height);
+
+        // The batch is already durable; a failed compaction is retried on the next commit.
+        self.commits_since_compaction += 1;
+        if self.commits_since_compaction >= self.compaction_interval {
+            let _ = self.compact();
+        }
+        Ok(())
+    }
+
+    fn committed_height(&self) -> Option<u64> {
+        self.height
+    }
+
+    fn discard(&mut self) {
+        self.staged.clear();
+    }
+}
+
+fn checksum(payload: &[u8]) -> [u8; 4] {
+    let digest = Sha256::digest(payload);
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+fn frame_record(payload: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(payload.len() + 8);
+    record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    record.extend_from_slice(&checksum(payload));
+    record.extend_from_slice(payload);
+    record
+}
+
+// Returns the payload and the offset of the next record, or None for a torn or corrupt record.
+fn read_record(bytes: &[u8], offset: usize) -> Option<(&[u8], usize)> {
+    let header = bytes.get(offset..offset + 8)?;
+    let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let payload = bytes.get(offset + 8..offset + 8 + len)?;
+    if checksum(payload) != header[4..8] {
+        return None;
+    }
+    Some((payload, offset + 8 + len))
+}
+
+fn write_bytes(buffer: &mut Vec<u8>, data: &[u8]) {
+    buffer.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(data);
+}
+
+fn read_bytes(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = u32::from_be_bytes(bytes.get(0..4)?.try_into().ok()?) as usize;
+    let data = bytes.get(4..4 + len)?;
+    Some((data, &bytes[4 + len..]))
+}
+
+fn read_u64(bytes: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(0..8)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("myluks-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn open(dir: &Path, compaction_interval: u64) -> FileStore {
+        match FileStore::open(dir, compaction_interval) {
+            Ok(store) => store,
+            Err(_) => panic!("store should open"),
+        }
+    }
+
+    #[test]
+    fn replay_drops_a_truncated_batch() {
+        let dir = temp_dir("wal-truncated");
+        {
+            let mut store = open(&dir, 100);
+            store.put("a", b"1".to_vec());
+            assert!(store.commit(1).is_ok());
+            store.put("a", b"2".to_vec());
+            store.put("b", b"3".to_vec());
+            assert!(store.commit(2).is_ok());
+        }
+
+        // Cortar el ultimo registro a medias, como un proceso muerto durante la escritura.
+        let wal_path = dir.join(WAL_FILE);
+        let len = fs::metadata(&wal_path).unwrap().len();
+        OpenOptions::new().write(true).open(&wal_path).unwrap().set_len(len - 3).unwrap();
+
+        let mut store = open(&dir, 100);
+        assert_eq!(store.committed_height(), Some(1));
+        assert_eq!(store.get("a"), Some(b"1".to_vec()));
+        assert_eq!(store.get("b"), None);
+
+        // The torn tail is gone, so a new batch replays cleanly after it.
+        store.put("c", b"4".to_vec());
+        assert!(store.commit(2).is_ok());
+        drop(store);
+        let store = open(&dir, 100);
+        assert_eq!(store.committed_height(), Some(2));
+        assert_eq!(store.get("c"), Some(b"4".to_vec()));
+        assert_eq!(store.get("a"), Some(b"1".to_vec()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn replay_drops_a_batch_without_commit_record() {
+        let dir = temp_dir("wal-uncommitted");
+        {
+            let mut store = open(&dir, 100);
+            store.put("a", b"1".to_vec());
+            assert!(store.commit(1).is_ok());
+        }
+
+        let mut payload = vec![RECORD_PUT];
+        write_bytes(&mut payload, b"a");
+        write_bytes(&mut payload, b"2");
+        let mut wal = OpenOptions::new().append(true).open(dir.join(WAL_FILE)).unwrap();
+        wal.write_all(&frame_record(&payload)).unwrap();
+        drop(wal);
+
+        let store = open(&dir, 100);
+        assert_eq!(store.committed_height(), Some(1));
+        assert_eq!(store.get("a"), Some(b"1".to_vec()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn replay_after_compaction_keeps_snapshot_state() {
+        let dir = temp_dir("wal-compacted");
+        {
+            let mut store = open(&dir, 2);
+            store.put("a", b"1".to_vec());
+            assert!(store.commit(1).is_ok());
+            store.put("b", b"2".to_vec());
+            assert!(store.commit(2).is_ok()); // compacta
+            store.delete("a");
+            assert!(store.commit(3).is_ok());
+        }
+
+        let store = open(&dir, 2);
+        assert_eq!(store.committed_height(), Some(3));
+        assert_eq!(store.get("a"), None);
+        assert_eq!(store.get("b"), Some(b"2".to_vec()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,114 @@
+// Fixtures shared by the unit tests: fixed secp256k1 keys, the accounts they control, and
+// a ledger whose treasury and central bank are the same 2-of-2 key set.
+use crate::accounts::{Accounts, AccountSignature, MultiSigPolicy, PublicKey};
+use crate::address::LuksAddress;
+use crate::amount::Amount;
+use crate::assets::NATIVE_ASSET;
+use crate::coin_issue::CoinIssue;
+use crate::concensus::{Consensus, TransactionKind};
+use crate::ledger::{Ledger, LedgerTransaction, LedgerTransactionKind};
+use crate::roles::{Role, Roles};
+use crate::security::Security;
+use crate::signature_scheme::SchemeId;
+use crate::validate_transaction::ValidateTransaction;
+
+pub const OWNER: [u8; 32] = [0x06; 32];
+pub const ALICE: [u8; 32] = [0x01; 32];
+pub const BOB: [u8; 32] = [0x02; 32];
+pub const CAROL: [u8; 32] = [0x05; 32];
+pub const TREASURY_KEYS: [[u8; 32]; 2] = [[0x03; 32], [0x04; 32]];
+
+// Luks held by every fixture account but the treasury.
+pub const INITIAL_BALANCE: Amount = Amount::from_units(1_000_000);
+pub const INITIAL_SUPPLY: Amount = Amount::from_units(1_000_000_000);
+
+pub fn public_key(private_key: &[u8; 32]) -> PublicKey {
+    let value = SchemeId::Secp256k1.scheme().public_key(private_key).expect("valid private key");
+    PublicKey { value, scheme: SchemeId::Secp256k1 }
+}
+
+pub fn address(private_key: &[u8; 32]) -> String {
+    LuksAddress::from_public_key(&public_key(private_key)).to_string()
+}
+
+pub fn sign(private_key: &[u8; 32], data: &str) -> AccountSignature {
+    let signature = SchemeId::Secp256k1.scheme().sign(private_key, data.as_bytes()).expect("valid private key");
+    AccountSignature { public_key: public_key(private_key), signature: hex::encode(signature), scheme: SchemeId::Secp256k1 }
+}
+
+pub fn treasury_policy() -> MultiSigPolicy {
+    match MultiSigPolicy::new(TREASURY_KEYS.iter().map(public_key).collect(), 2) {
+        Ok(policy) => policy,
+        Err(_) => panic!("valid policy"),
+    }
+}
+
+pub fn treasury() -> String {
+    LuksAddress::from_multisig(&treasury_policy()).to_string()
+}
+
+// Owner, alice, bob and carol with `INITIAL_BALANCE` each, plus an empty treasury.
+pub fn accounts() -> Accounts {
+    let mut accounts = Accounts::new(10);
+    for key in [OWNER, ALICE, BOB, CAROL] {
+        assert!(accounts.create_account(address(&key), INITIAL_BALANCE, public_key(&key)).is_ok());
+    }
+    assert!(accounts.create_multisig_account(treasury(), Amount::ZERO, treasury_policy()).is_ok());
+    accounts
+}
+
+// Ledger over `accounts()`, owned by `OWNER`; the treasury is also the central bank.
+pub fn ledger() -> Ledger {
+    ledger_with(accounts())
+}
+
+pub fn ledger_with(accounts: Accounts) -> Ledger {
+    let coin_issue = match CoinIssue::new(INITIAL_SUPPLY, Amount::ONE, 0, Amount::MAX, treasury(), treasury_policy()) {
+        Ok(coin_issue) => coin_issue,
+        Err(_) => panic!("valid central bank"),
+    };
+    let validator = ValidateTransaction::new(Security::new());
+    let roles = Roles::new(&address(&OWNER));
+    match Ledger::new(accounts, validator, coin_issue, Consensus::new("00".repeat(32)), treasury(), roles) {
+        Ok(ledger) => ledger,
+        Err(_) => panic!("valid ledger"),
+    }
+}
+
+// Luks transfer of `amount` with a tip of 10 units, signed by `from` at its next nonce.
+pub fn transfer(ledger: &Ledger, from: &[u8; 32], to: &str, amount: Amount) -> LedgerTransaction {
+    let from_address = address(from);
+    let asset = NATIVE_ASSET.to_string();
+    let tip = Amount::from_units(10);
+    let nonce = ledger.accounts.get_nonce(&from_address).unwrap_or(0);
+    let data = ValidateTransaction::signing_data(&TransactionKind::Transfer, &from_address, to, &asset, amount, tip, nonce);
+    LedgerTransaction {
+        kind: LedgerTransactionKind::Transfer,
+        from: from_address,
+        to: to.to_string(),
+        asset,
+        amount,
+        tip,
+        nonce,
+        signatures: vec![sign(from, &data)],
+    }
+}
+
+// The owner grants `role` to `account`, signed at the owner's next nonce.
+pub fn grant_role(ledger: &mut Ledger, account: &str, role: Role) {
+    let owner = address(&OWNER);
+    let nonce = ledger.accounts.get_nonce(&owner).unwrap_or(0);
+    let data = Roles::grant_data(&owner, account, role, nonce);
+    assert!(ledger.grant_role(&owner, &account.to_string(), role, nonce, &[sign(&OWNER, &data)]).is_ok());
+}
+
+// The owner, made compliance officer if it was not, locks `account`.
+pub fn lock_account(ledger: &mut Ledger, account: &str) {
+    let owner = address(&OWNER);
+    if !ledger.roles().has_role(&owner, Role::ComplianceOfficer) {
+        grant_role(ledger, &owner, Role::ComplianceOfficer);
+    }
+    let nonce = ledger.accounts.get_nonce(&owner).unwrap_or(0);
+    let data = Accounts::lock_data(&owner, account, true, nonce);
+    assert!(ledger.lock_account(&owner, &account.to_string(), nonce, &[sign(&OWNER, &data)]).is_ok());
+}
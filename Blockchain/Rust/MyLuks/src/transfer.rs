@@ -1,121 +1,165 @@
 // Enhanced Error Handling for transfers
 pub enum TransferError {
     AccountNotFound,
+    AccountLocked, // Sender or recipient is locked
     InsufficientBalance,
     InvalidAmount, // New Error for invalid transfer amounts
+    AmountOverflow, // Amount plus fee does not fit in an `Amount`
     TransactionNotFound, // New Error for transaction reversion
-    UnauthorizedRevert // New Error for unauthorized transaction reversion
+    UnauthorizedRevert, // New Error for unauthorized transaction reversion
+    Rejected(LedgerError), // Any other reason the ledger refused the transfer
 }
 
-use crate::accounts::{Accounts, AccountError, TransactionHistory};
-use std::time::{SystemTime, UNIX_EPOCH};
+impl From<LedgerError> for TransferError {
+    fn from(error: LedgerError) -> Self {
+        match error {
+            LedgerError::Account(AccountError::AccountNotFound)
+            | LedgerError::Validation(ValidationError::InvalidRecipient) => TransferError::AccountNotFound,
+            LedgerError::Account(AccountError::AccountLocked)
+            | LedgerError::Validation(ValidationError::SenderAccountLocked) => TransferError::AccountLocked,
+            LedgerError::Account(AccountError::InsufficientBalance)
+            | LedgerError::Validation(ValidationError::InsufficientSenderBalance) => TransferError::InsufficientBalance,
+            LedgerError::Account(AccountError::BalanceOverflow) | LedgerError::Amount(_) => TransferError::AmountOverflow,
+            LedgerError::InvalidAmount => TransferError::InvalidAmount,
+            other => TransferError::Rejected(other),
+        }
+    }
+}
+
+use crate::accounts::{AccountError, AccountSignature};
+use crate::amount::Amount;
+use crate::fees::FeeQuote;
+use crate::ledger::{Ledger, LedgerError, LedgerTransaction, LedgerTransactionKind, Receipt};
 use crate::validate_transaction::ValidationError;
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub enum TransferType {
     PeerToPeer,
     ToContract,
     FromContract,
 }
 
-#[derive(Debug, PartialEq)]
+/// Transfers made through this handle, so that they can be reverted. Balances only ever
+/// move through `Ledger::apply`: its journal, fees, events and history apply here too.
+#[derive(Debug, PartialEq, Default)]
 pub struct Transfer {
-    pub transactions: Vec<TransactionHistory>, // New field to store transaction history
+    pub transactions: Vec<Receipt>, // New field to store transaction history
 }
 
 impl Transfer {
-    // Constantes y estructuras
-    const TRANSFER_FEE_PERCENTAGE: f32 = 0.05;
-
-    // Función para calcular la tarifa de transferencia
-    fn calculate_transfer_fee(&self, amount: u64) -> u64 {
-        (Self::TRANSFER_FEE_PERCENTAGE * amount as f32) as u64
+    // Tarifa, total a debitar e importe recibido, antes de mover nada
+    pub fn quote_transfer(&self, ledger: &Ledger, amount: Amount, tip: Amount) -> Result<FeeQuote, TransferError> {
+        ledger.quote_fee(amount, tip).map_err(|_| TransferError::AmountOverflow)
     }
 
-    fn validate_transfer_amount(&self, amount: u64) -> Result<(), TransferError> {
-        if amount <= 0 {
-            return Err(TransferError::InvalidAmount);
-        }
-        Ok(())
+    /// Applies a signed transfer. On error nothing was moved.
+    pub fn initiate_transfer(&mut self, ledger: &mut Ledger, tx: LedgerTransaction) -> Result<Receipt, TransferError> {
+        let receipt = ledger.apply(tx)?;
+        self.transactions.push(receipt.clone());
+        Ok(receipt)
     }
 
-    fn apply_transfer_fee(&self, amount: u64) -> u64 {
-        amount - self.calculate_transfer_fee(amount)
-    }
+    /// Sends the amount of transfer `transaction_id` back to its sender. The refund is a
+    /// new transfer from the recipient, so it must carry the recipient's signatures over
+    /// it (with `tip` and the recipient's next `nonce`); the recipient also pays its fee.
+    pub fn revert_transaction(&mut self, ledger: &mut Ledger, transaction_id: usize, tip: Amount, nonce: u64, signatures: Vec<AccountSignature>) -> Result<Receipt, TransferError> {
+        let original = self.transactions.get(transaction_id).ok_or(TransferError::TransactionNotFound)?;
+        let refund = LedgerTransaction {
+            kind: LedgerTransactionKind::Transfer,
+            from: original.to.clone(),
+            to: original.from.clone(),
+            asset: original.asset.clone(),
+            amount: original.amount,
+            tip,
+            nonce,
+            signatures,
+        };
+        let receipt = ledger.apply(refund).map_err(|e| match e {
+            LedgerError::Validation(ValidationError::InvalidSignature | ValidationError::InsufficientSignatures | ValidationError::RevokedKey) => TransferError::UnauthorizedRevert,
+            other => other.into(),
+        })?;
 
-    fn decrement_sender_balance(accounts: &mut Accounts, from: &String, amount: u64) -> Result<(), TransferError> {
-        accounts.adjust_balance(&from, -(amount as i64)).map_err(|e| match e {
-            AccountError::InsufficientBalance => TransferError::InsufficientBalance,
-            _ => panic!("Unexpected error"),
-        })
-    }
-    
-    fn increment_recipient_balance(accounts: &mut Accounts, to: &String, amount: u64) -> Result<(), TransferError> {
-        accounts.adjust_balance(&to, amount as i64).map_err(|_| TransferError::AccountNotFound)
+        // Remove the transaction from the history
+        self.transactions.remove(transaction_id);
+        Ok(receipt)
     }
+}
 
-    fn notify_user(user: &String, message: &str) {
-        println!("Notification to {}: {}", user, message);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::concensus::TransactionKind;
+    use crate::testing::{self, ALICE, BOB};
+    use crate::validate_transaction::ValidateTransaction;
+
+    #[test]
+    fn transfer_charges_the_ledger_fees() {
+        let mut ledger = testing::ledger();
+        let mut transfer = Transfer::default();
+        let (alice, bob) = (testing::address(&ALICE), testing::address(&BOB));
+        let amount = Amount::from_units(10_000);
+        let quote = transfer.quote_transfer(&ledger, amount, Amount::from_units(10)).ok().unwrap();
+        let supply = ledger.coin_issue.total_supply();
+
+        let tx = testing::transfer(&ledger, &ALICE, &bob, amount);
+        let receipt = transfer.initiate_transfer(&mut ledger, tx).ok().unwrap();
+        assert_eq!(receipt.fee, quote.fee);
+        assert_eq!(ledger.accounts.get_balance(&alice).ok(), testing::INITIAL_BALANCE.checked_sub(quote.total).ok());
+        assert_eq!(ledger.accounts.get_balance(&bob).ok(), testing::INITIAL_BALANCE.checked_add(amount).ok());
+        // The fee goes to the treasury and the base fee leaves the supply.
+        assert_eq!(ledger.accounts.get_balance(&testing::treasury()).ok(), Some(quote.fee));
+        assert_eq!(ledger.coin_issue.total_supply().checked_add(quote.base_fee).ok(), Some(supply));
+        assert_eq!(transfer.transactions, vec![receipt]);
     }
 
-    pub fn initiate_transfer(&mut self, accounts: &mut Accounts, from: &String, to: &String, amount: u64, transfer_type: TransferType) -> Result<(), TransferError> {
-        self.validate_transfer_amount(amount)?;
-
-        let fee = self.calculate_transfer_fee(amount);
-        let final_amount = self.apply_transfer_fee(amount);
-
-        // Check if both sender and receiver exist before making any changes
-        if !accounts.account_exists(from) || !accounts.account_exists(to) {
-            return Err(TransferError::AccountNotFound);
-        }
-
-        self.decrement_sender_balance(accounts, from, amount + fee)?; // Include fee in sender's decrement
-        self.increment_recipient_balance(accounts, to, final_amount)?;
-
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-        let transaction = TransactionHistory {
-            from: from.clone(),
-            to: to.clone(),
-            amount: final_amount,
-            timestamp,
-        };
-
-        accounts.add_transaction_history(from, transaction.clone()).unwrap();
-        accounts.add_transaction_history(to, transaction.clone()).unwrap();
-
-        self.transactions.push(transaction);
-        log_transfer(from, to, final_amount, &transfer_type);
-
-        self.notify_user(from, &format!("You transferred {} coins to {}. Fee: {} coins.", final_amount, to, fee));
-        self.notify_user(to, &format!("You received {} coins from {}.", final_amount, from));
-
-        Ok(())
+    #[test]
+    fn transfer_to_a_locked_account_moves_nothing() {
+        let mut ledger = testing::ledger();
+        let mut transfer = Transfer::default();
+        let (alice, bob) = (testing::address(&ALICE), testing::address(&BOB));
+        testing::lock_account(&mut ledger, &bob);
+        let root = ledger.accounts.state_root();
+
+        let tx = testing::transfer(&ledger, &ALICE, &bob, Amount::from_units(10_000));
+        assert!(matches!(transfer.initiate_transfer(&mut ledger, tx), Err(TransferError::AccountLocked)));
+        assert_eq!(ledger.accounts.get_balance(&alice).ok(), Some(testing::INITIAL_BALANCE));
+        assert_eq!(ledger.accounts.state_root(), root);
+        assert!(transfer.transactions.is_empty());
     }
 
-    pub fn revert_transaction(&mut self, accounts: &mut Accounts, transaction_id: usize, caller: &String) -> Result<(), TransferError> {
-        let transaction = self.transactions.get(transaction_id).ok_or(TransferError::TransactionNotFound)?;
-
-        // Check if the transaction was from the caller
-        if &transaction.from != caller {
-            return Err(TransferError::UnauthorizedRevert);
-        }
-
-        // Check if both sender and receiver exist before making any changes
-        if !accounts.account_exists(&transaction.from) || !accounts.account_exists(&transaction.to) {
-            return Err(TransferError::AccountNotFound);
-        }
-
-        // Revert the transaction by adjusting the balances
-        self.decrement_sender_balance(accounts, &transaction.to, transaction.amount)?;  // Note the reversed roles
-        self.increment_recipient_balance(accounts, &transaction.from, transaction.amount)?;
-
-        // Remove the transaction from the history
-        self.transactions.remove(transaction_id);
-
-        Ok(())
+    #[test]
+    fn unsigned_transfer_is_rejected() {
+        let mut ledger = testing::ledger();
+        let mut transfer = Transfer::default();
+        let mut tx = testing::transfer(&ledger, &ALICE, &testing::address(&BOB), Amount::from_units(10_000));
+        tx.signatures.clear();
+        assert!(matches!(
+            transfer.initiate_transfer(&mut ledger, tx),
+            Err(TransferError::Rejected(LedgerError::Validation(ValidationError::InvalidSignature)))
+        ));
     }
 
-    fn log_transfer(from: &String, to: &String, amount: u64, transfer_type: &TransferType) {
-        println!("Transfer of {} to {} of {} coins completed. Transfer Type: {:?}", from, to, amount, transfer_type);
+    #[test]
+    fn only_the_recipient_can_revert() {
+        let mut ledger = testing::ledger();
+        let mut transfer = Transfer::default();
+        let (alice, bob) = (testing::address(&ALICE), testing::address(&BOB));
+        let amount = Amount::from_units(10_000);
+        let tx = testing::transfer(&ledger, &ALICE, &bob, amount);
+        assert!(transfer.initiate_transfer(&mut ledger, tx).is_ok());
+
+        // The refund moves bob's funds, so alice cannot sign it.
+        let asset = crate::assets::native_asset_id();
+        let data = ValidateTransaction::signing_data(&TransactionKind::Transfer, &bob, &alice, &asset, amount, Amount::ZERO, 0);
+        let by_sender = vec![testing::sign(&ALICE, &data)];
+        assert!(matches!(transfer.revert_transaction(&mut ledger, 0, Amount::ZERO, 0, by_sender), Err(TransferError::UnauthorizedRevert)));
+
+        let alice_before = ledger.accounts.get_balance(&alice).ok().unwrap();
+        let by_recipient = vec![testing::sign(&BOB, &data)];
+        assert!(transfer.revert_transaction(&mut ledger, 0, Amount::ZERO, 0, by_recipient).is_ok());
+        assert_eq!(ledger.accounts.get_balance(&alice).ok(), alice_before.checked_add(amount).ok());
+        assert!(transfer.transactions.is_empty());
+        assert!(matches!(transfer.revert_transaction(&mut ledger, 0, Amount::ZERO, 1, Vec::new()), Err(TransferError::TransactionNotFound)));
     }
 }
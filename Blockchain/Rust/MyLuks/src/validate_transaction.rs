@@ -1,5 +1,10 @@
-use crate::luks_main_contract::accounts::{Accounts, AccountError};
+use crate::accounts::{Accounts, AccountSignature};
 use crate::security::{Security, SecurityError};  // Import modified to integrate the Security module
+use crate::address;
+use crate::amount::Amount;
+use crate::assets::AssetId;
+use crate::concensus::TransactionKind;
+use crate::encoding;
 
 /// Enum for handling validation errors in transactions.
 pub enum ValidationError {
@@ -7,10 +12,14 @@ pub enum ValidationError {
     InsufficientSenderBalance,
     InvalidRecipient,
     SignatureFailure,
-    DoubleSpend,
+    StaleNonce,  // Nonce already used: the transaction is a replay
+    NonceGap,  // Nonce skips ahead of the account's next nonce
     SenderAccountLocked,
     InvalidSignature,  // New error type for invalid signatures
+    InsufficientSignatures,  // Fewer valid signatures than the account's threshold
+    RevokedKey,  // Signature made with a key retired by a rotation
     InvalidTransactionHash,  // New error type for invalid transaction hashes
+    InvalidAddress,  // Malformed or mistyped address (bad prefix, characters or checksum)
 }
 
 /// Main structure for handling transaction validation.
@@ -24,63 +33,193 @@ impl ValidateTransaction {
         ValidateTransaction { security_module }
     }
 
-    /// Generate a unique transaction hash (New Function)
-    pub fn generate_transaction_hash(&self, from: &String, to: &String, amount: u64) -> String {
-        let data = format!("{}-{}-{}", from, to, amount);
-        self.security_module.hash(&data)
+    /// Read access to the underlying security module.
+    pub fn security(&self) -> &Security {
+        &self.security_module
+    }
+
+    /// Data the sender signs. Including the nonce makes every payment unique, even
+    /// repeated payments of the same amount to the same recipient; including the tip
+    /// stops anyone but the sender from raising it, and the kind stops an issuance
+    /// approval from passing as a transfer. Hex of the canonical signing payload.
+    pub fn signing_data(kind: &TransactionKind, from: &str, to: &str, asset: &AssetId, amount: Amount, tip: Amount, nonce: u64) -> String {
+        hex::encode(encoding::encode_signing_payload(kind, from, to, asset, amount, tip, nonce))
+    }
+
+    /// Generate a unique transaction hash: the transaction id, which leaves the signatures out.
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate_transaction_hash(&self, kind: &TransactionKind, from: &str, to: &str, asset: &AssetId, amount: Amount, tip: Amount, nonce: u64) -> String {
+        encoding::signing_payload_id(kind, from, to, asset, amount, tip, nonce)
     }
 
     /// Main function to validate a transaction based on various conditions.
+    #[allow(clippy::too_many_arguments)]
     pub fn validate_transaction(
-        &mut self,
+        &self,
         accounts: &Accounts,
         from: &String,
         to: &String,
-        amount: u64,
-        signature: &String,
+        asset: &AssetId,
+        amount: Amount,
+        tip: Amount,
+        nonce: u64,
+        signatures: &[AccountSignature],
     ) -> Result<bool, ValidationError> {
-        const MAX_TRANSACTION_AMOUNT: u64 = 10_000;
+        self.check_transaction(accounts, from, to, asset, amount, tip, nonce, signatures)?;
+        Ok(true)
+    }
+
+    /// Runs every validation check and returns the transaction hash. Nothing is recorded:
+    /// the caller consumes the nonce once the transaction is applied.
+    #[allow(clippy::too_many_arguments)]
+    pub fn check_transaction(
+        &self,
+        accounts: &Accounts,
+        from: &String,
+        to: &String,
+        asset: &AssetId,
+        amount: Amount,
+        tip: Amount,
+        nonce: u64,
+        signatures: &[AccountSignature],
+    ) -> Result<String, ValidationError> {
+        const MAX_TRANSACTION_AMOUNT: Amount = Amount::from_units(10_000 * Amount::ONE.units()); // 10,000 coins
 
         // Check if the amount exceeds the maximum allowed transaction amount.
         if amount > MAX_TRANSACTION_AMOUNT {
             return Err(ValidationError::ExceedsMaxAmount);
         }
 
+        let signed_data = Self::signing_data(&TransactionKind::Transfer, from, to, asset, amount, tip, nonce);
+        self.check_authorization(accounts, from, to, &signed_data, nonce, signatures)?;
+
+        // Check if the sender has enough unlocked balance to proceed with the transaction.
+        if accounts.get_spendable_asset_balance(from, asset).unwrap_or(Amount::ZERO) < amount {
+            return Err(ValidationError::InsufficientSenderBalance);
+        }
+
+        Ok(self.generate_transaction_hash(&TransactionKind::Transfer, from, to, asset, amount, tip, nonce))
+    }
+
+    /// Checks that `from` may send to `to`: both accounts exist, the sender is not locked,
+    /// the nonce is the sender's next one and `signatures` over `signed_data` satisfy the
+    /// sender's key set. Shared by every kind of transfer, fungible or not.
+    pub fn check_authorization(
+        &self,
+        accounts: &Accounts,
+        from: &String,
+        to: &String,
+        signed_data: &String,
+        nonce: u64,
+        signatures: &[AccountSignature],
+    ) -> Result<(), ValidationError> {
+        // Reject malformed addresses before looking anything up.
+        if address::validate_address(from).is_err() || address::validate_address(to).is_err() {
+            return Err(ValidationError::InvalidAddress);
+        }
+
         // Check if the sender's account is locked.
         let sender_details = accounts.get_account_details(from).map_err(|_| ValidationError::InvalidRecipient)?;
-        if sender_details.locked {
+        if sender_details.is_frozen() {
             return Err(ValidationError::SenderAccountLocked);
         }
 
-        // Check if the sender has enough balance to proceed with the transaction.
-        if accounts.get_balance(from).unwrap_or(0) < amount {
-            return Err(ValidationError::InsufficientSenderBalance);
-        }
-
         // Check if the recipient exists.
         if accounts.get_balance(to).is_err() {
             return Err(ValidationError::InvalidRecipient);
         }
 
-        // Validate the signature of the transaction using the Security module. (Modified)
-        match self.security_module.validate_signature(from, &amount.to_string(), signature) {
-            Ok(true) => {},
-            Ok(false) | Err(_) => return Err(ValidationError::InvalidSignature),
+        // Check for replays and out-of-order transactions.
+        match Security::check_nonce(sender_details.nonce, nonce) {
+            Ok(()) => {},
+            Err(SecurityError::StaleNonce) => return Err(ValidationError::StaleNonce),
+            Err(_) => return Err(ValidationError::NonceGap),
         }
 
-        // Check for double spending.
-        let transaction_hash = self.generate_transaction_hash(from, to, amount);  // Modified to use new function
-        if self.security_module.has_transaction_been_processed(&transaction_hash) {
-            return Err(ValidationError::DoubleSpend);
+        // Signatures from retired keys are never accepted.
+        if signatures.iter().any(|s| accounts.is_key_revoked(&s.public_key)) {
+            return Err(ValidationError::RevokedKey);
         }
 
-        // Validate transaction hash (New Check)
-        if self.generate_transaction_hash(from, to, amount) != transaction_hash {
-            return Err(ValidationError::InvalidTransactionHash);
+        // Validate the signatures against the sender's key set.
+        let policy = sender_details.signing_policy();
+        let valid_signatures = policy.count_valid_signatures(signed_data, signatures);
+        if valid_signatures == 0 {
+            return Err(ValidationError::InvalidSignature);
+        }
+        if valid_signatures < policy.threshold {
+            return Err(ValidationError::InsufficientSignatures);
         }
 
-        // Mark the transaction as processed.
-        self.security_module.add_processed_transaction(transaction_hash).unwrap();
-        Ok(true)
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::PublicKey;
+    use crate::address::LuksAddress;
+    use crate::signature_scheme::SchemeId;
+
+    struct Fixture {
+        accounts: Accounts,
+        validator: ValidateTransaction,
+        alice: String,
+        bob: String,
+    }
+
+    fn account(accounts: &mut Accounts, security: &mut Security, private_key: [u8; 32]) -> String {
+        let value = SchemeId::Secp256k1.scheme().public_key(&private_key).unwrap();
+        let public_key = PublicKey { value, scheme: SchemeId::Secp256k1 };
+        let account_id = LuksAddress::from_public_key(&public_key).to_string();
+        assert!(accounts.create_account(account_id.clone(), Amount::from_units(1_000), public_key.clone()).is_ok());
+        assert!(security.add_keys(account_id.clone(), public_key, private_key.to_vec()).is_ok());
+        account_id
+    }
+
+    fn fixture() -> Fixture {
+        let mut accounts = Accounts::new(10);
+        let mut security = Security::new();
+        let alice = account(&mut accounts, &mut security, [0x01; 32]);
+        let bob = account(&mut accounts, &mut security, [0x02; 32]);
+        Fixture { accounts, validator: ValidateTransaction::new(security), alice, bob }
+    }
+
+    // Checks a transfer of 10 units from alice to bob, signed by alice, at `nonce`.
+    fn check(fixture: &Fixture, nonce: u64) -> Result<String, ValidationError> {
+        let asset = crate::assets::native_asset_id();
+        let amount = Amount::from_units(10);
+        let data = ValidateTransaction::signing_data(&TransactionKind::Transfer, &fixture.alice, &fixture.bob, &asset, amount, Amount::ZERO, nonce);
+        let security = fixture.validator.security();
+        let signature = AccountSignature {
+            public_key: fixture.accounts.get_account_details(&fixture.alice).ok().unwrap().public_key.clone(),
+            signature: security.sign(&fixture.alice, &data).ok().unwrap(),
+            scheme: SchemeId::Secp256k1,
+        };
+        fixture.validator.check_transaction(&fixture.accounts, &fixture.alice, &fixture.bob, &asset, amount, Amount::ZERO, nonce, &[signature])
+    }
+
+    #[test]
+    fn next_nonce_is_accepted() {
+        let fixture = fixture();
+        assert!(check(&fixture, 0).is_ok());
+    }
+
+    #[test]
+    fn nonce_ahead_of_the_account_is_a_gap() {
+        let fixture = fixture();
+        assert!(matches!(check(&fixture, 1), Err(ValidationError::NonceGap)));
+        assert!(matches!(check(&fixture, 5), Err(ValidationError::NonceGap)));
+    }
+
+    #[test]
+    fn used_nonce_is_stale() {
+        let mut fixture = fixture();
+        assert!(check(&fixture, 0).is_ok());
+        assert!(fixture.accounts.increment_nonce(&fixture.alice).is_ok());
+
+        assert!(matches!(check(&fixture, 0), Err(ValidationError::StaleNonce)));
+        assert!(check(&fixture, 1).is_ok());
     }
 }
@@ -0,0 +1,152 @@
+use serde::{Serialize, Deserialize};
+use crate::amount::Amount;
+use crate::assets::NATIVE_ASSET;
+use crate::concensus::TransactionKind;
+use crate::validate_transaction::ValidateTransaction;
+
+pub enum VestingError {
+    InvalidSchedule,
+}
+
+/// A locked tranche of an account balance released over time.
+///
+/// Nothing is spendable before `start + cliff`; after that the tranche vests linearly
+/// until it is fully released at `start + duration`. Times are Unix seconds.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct VestingSchedule {
+    pub total: Amount,
+    pub start: u64,
+    pub cliff: u64,
+    pub duration: u64,
+}
+
+impl VestingSchedule {
+    pub fn new(total: Amount, start: u64, cliff: u64, duration: u64) -> Result<Self, VestingError> {
+        if total.is_zero() || cliff > duration {
+            return Err(VestingError::InvalidSchedule);
+        }
+        Ok(VestingSchedule { total, start, cliff, duration })
+    }
+
+    // Amount released at time `now`.
+    pub fn vested_amount(&self, now: u64) -> Amount {
+        if now < self.start.saturating_add(self.cliff) {
+            return Amount::ZERO;
+        }
+        let elapsed = now - self.start;
+        if elapsed >= self.duration {
+            return self.total;
+        }
+        // elapsed < duration, so the result is below `total` and cannot overflow.
+        self.total.checked_mul_ratio(elapsed, self.duration).unwrap_or(self.total)
+    }
+
+    // Amount still locked at time `now`.
+    pub fn locked_amount(&self, now: u64) -> Amount {
+        self.total.saturating_sub(self.vested_amount(now))
+    }
+
+    pub fn is_fully_vested(&self, now: u64) -> bool {
+        self.vested_amount(now) == self.total
+    }
+
+    // Block transaction kind of a grant of this schedule.
+    pub fn grant_kind(&self) -> TransactionKind {
+        TransactionKind::VestingGrant { start: self.start, cliff: self.cliff, duration: self.duration }
+    }
+
+    // Data the grantor signs to grant this schedule: the signing payload of its block transaction.
+    pub fn grant_data(&self, grantor: &str, beneficiary: &str, nonce: u64) -> String {
+        ValidateTransaction::signing_data(&self.grant_kind(), grantor, beneficiary, &NATIVE_ASSET.to_string(), self.total, Amount::ZERO, nonce)
+    }
+}
+
+// Total locked across all schedules at time `now`.
+pub fn locked_total(schedules: &[VestingSchedule], now: u64) -> Amount {
+    schedules.iter().fold(Amount::ZERO, |total, schedule| total.saturating_add(schedule.locked_amount(now)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use crate::accounts::AccountError;
+    use crate::concensus::TransactionKind;
+    use crate::ledger::{Ledger, LedgerError};
+    use crate::storage::MemoryStore;
+    use crate::testing::{self, ALICE, BOB, OWNER};
+    use crate::validate_transaction::ValidationError;
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    // The owner grants `schedule` to alice, signed by `key` at the owner's next nonce.
+    fn grant(ledger: &mut Ledger, key: &[u8; 32], schedule: VestingSchedule) -> Result<(), LedgerError> {
+        let (owner, alice) = (testing::address(&OWNER), testing::address(&ALICE));
+        let nonce = ledger.accounts.get_nonce(&owner).unwrap_or(0);
+        let signature = testing::sign(key, &schedule.grant_data(&owner, &alice, nonce));
+        ledger.grant_vesting(&owner, &alice, schedule, nonce, &[signature])
+    }
+
+    #[test]
+    fn nothing_vests_before_the_cliff_then_release_is_linear() {
+        let schedule = VestingSchedule::new(Amount::from_units(1_000), 100, 50, 200).ok().unwrap();
+        assert_eq!(schedule.vested_amount(0), Amount::ZERO);
+        assert_eq!(schedule.vested_amount(149), Amount::ZERO);
+        // At the cliff the time already elapsed vests at once.
+        assert_eq!(schedule.vested_amount(150), Amount::from_units(250));
+        assert_eq!(schedule.vested_amount(200), Amount::from_units(500));
+        assert_eq!(schedule.locked_amount(200), Amount::from_units(500));
+        assert!(!schedule.is_fully_vested(299));
+        assert!(schedule.is_fully_vested(300));
+        assert_eq!(schedule.locked_amount(1_000), Amount::ZERO);
+
+        assert!(VestingSchedule::new(Amount::from_units(1_000), 100, 201, 200).is_err());
+        assert!(VestingSchedule::new(Amount::ZERO, 100, 0, 200).is_err());
+    }
+
+    #[test]
+    fn only_the_vested_part_of_a_grant_can_be_spent() {
+        let mut ledger = testing::ledger();
+        let (owner, alice, bob) = (testing::address(&OWNER), testing::address(&ALICE), testing::address(&BOB));
+        // A quarter of the 400,000 units has vested; the rest vests over more than a day.
+        let schedule = VestingSchedule::new(Amount::from_units(400_000), now() - 25_000, 0, 100_000).ok().unwrap();
+        assert!(grant(&mut ledger, &OWNER, schedule.clone()).is_ok());
+        assert_eq!(ledger.accounts.get_balance(&owner).ok(), testing::INITIAL_BALANCE.checked_sub(schedule.total).ok());
+        assert_eq!(ledger.accounts.get_balance(&alice).ok(), testing::INITIAL_BALANCE.checked_add(schedule.total).ok());
+        let spendable = ledger.accounts.get_spendable_balance(&alice).ok().unwrap();
+        assert!(spendable >= Amount::from_units(1_100_000) && spendable < Amount::from_units(1_110_000));
+
+        let too_much = testing::transfer(&ledger, &ALICE, &bob, Amount::from_units(1_200_000));
+        assert!(ledger.apply(too_much).is_err());
+        assert_eq!(ledger.accounts.get_balance(&bob).ok(), Some(testing::INITIAL_BALANCE));
+        let vested = testing::transfer(&ledger, &ALICE, &bob, Amount::from_units(1_000_000));
+        assert!(ledger.apply(vested).is_ok());
+
+        // The grant goes into the next block, ahead of the transfer.
+        let mut store = MemoryStore::new();
+        assert!(ledger.commit_block(&mut store, &bob).is_ok());
+        let block = ledger.consensus.blockchain.last().unwrap();
+        assert_eq!(block.transactions[0].kind, schedule.grant_kind());
+        assert_eq!((&block.transactions[0].from, &block.transactions[0].to, block.transactions[0].amount), (&owner, &alice, schedule.total));
+        assert_eq!(block.transactions[1].kind, TransactionKind::Transfer);
+    }
+
+    #[test]
+    fn a_grant_needs_the_grantors_signature_and_an_open_beneficiary() {
+        let mut ledger = testing::ledger();
+        let (owner, alice) = (testing::address(&OWNER), testing::address(&ALICE));
+        let schedule = VestingSchedule::new(Amount::from_units(1_000), now(), 100, 1_000).ok().unwrap();
+
+        // Alice cannot lock up the owner's funds for herself.
+        assert!(matches!(grant(&mut ledger, &ALICE, schedule.clone()), Err(LedgerError::Validation(ValidationError::InvalidSignature))));
+        assert_eq!(ledger.accounts.get_balance(&owner).ok(), Some(testing::INITIAL_BALANCE));
+
+        testing::lock_account(&mut ledger, &alice);
+        let root = ledger.accounts.state_root();
+        assert!(matches!(grant(&mut ledger, &OWNER, schedule), Err(LedgerError::Account(AccountError::AccountLocked))));
+        assert_eq!(ledger.accounts.state_root(), root);
+        assert!(ledger.accounts.get_account_details(&alice).ok().is_some_and(|account| account.vesting.is_empty()));
+    }
+}